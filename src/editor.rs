@@ -2,7 +2,7 @@ mod command;
 mod commandbar;
 mod documentstatus;
 mod line;
-mod terminal;
+pub mod terminal;
 mod view;
 mod statusbar;
 mod messagebar;
@@ -10,11 +10,29 @@ mod uicomponent;
 mod size;
 mod position;
 mod annotatedstring;
+mod arguments;
+mod screen;
+mod paths;
+mod theme;
+mod colordepth;
+mod config;
+mod filetype;
+mod keymap;
+mod vim;
+mod excommand;
+mod picker;
+mod filefinder;
+mod dirbrowser;
+mod grep;
+mod macros;
+mod prompt;
 
-use crossterm::event::{ read, poll, Event, KeyEvent, KeyEventKind };
+use crossterm::event::{ Event, KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind };
 use std::{
-    env, 
-    io::Error,
+    fs::{create_dir_all, remove_dir, remove_file, rename, File},
+    io::{stdin, stdout, Error, ErrorKind},
+    mem::take,
+    path::{Path, PathBuf},
     panic::{set_hook, take_hook}
 };
 
@@ -22,40 +40,101 @@ use commandbar::CommandBar;
 use documentstatus::DocumentStatus;
 use line::Line;
 use messagebar::MessageBar;
-use position::{Col, Row, Position};
-use size::Size;
-use terminal::{Terminal};
+pub use position::{Col, Row, Position};
+pub use size::Size;
+use terminal::{Backend, CrosstermBackend, Terminal};
 use view::{View};
 use statusbar::{StatusBar};
 use uicomponent::UIComponent;
-use annotatedstring::AnnotatedString;
-use std::time::{Duration};
+pub use arguments::Arguments;
+pub use screen::{Screen, Style};
+use theme::{Theme, ThemeElement, Themes};
+pub use colordepth::ColorDepth;
+pub use config::Config;
+use config::Charset;
+use keymap::{format_sequence, KeyChord, Keymap, Lookup};
+use vim::{Action, Mode, Motion, Operator, Register, Vim};
+use excommand::ExCommand;
+use picker::Picker;
+use filefinder::FilePreview;
+use dirbrowser::DirBrowser;
+use grep::GrepResults;
+use macros::Macros;
+use prompt::{PendingAction, PromptType};
+use std::time::{Duration, Instant};
 
 use self::{
     command::{
-        Command::{self, Edit, Move, System},
-        Edit::InsertNewLine,
-        Move::{Up, Down, Left, Right},
-        System::{Quit, Resize, Save, Dismiss, Search},
+        Caret::{Above, AtAllMatches, AtNextMatch, Below},
+        Command::{self, Block, Caret, Edit, History, Lines, Macro, Move, Prompt, System},
+        Edit::{Delete, DeleteBackward, Insert, InsertNewLine},
+        Move::{Down, Left, Right, Up},
+        System::{
+            Quit, Resize, Save, Dismiss, Search, CycleTheme, ReloadConfig, CommandLine, CommandPalette,
+            OpenFile, Browse, Grep,
+        },
     },
 };
 
 const NAME: &str = env!("CARGO_PKG_NAME");
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
-const QUIT_TIMES: u8 = 3;
+const FILE_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+/// Clicks closer together than this on the same cell select a word, or a
+/// line for three of them.
+const MULTI_CLICK_INTERVAL: Duration = Duration::from_millis(500);
+const WHEEL_SCROLL_LINES: isize = 3;
+/// How often results are collected while a project search runs.
+const GREP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Macros can play macros up to this deep, so that one playing itself stops.
+const MAX_MACRO_DEPTH: usize = 20;
+const UNSAVED_CHANGES: &str = "No write since last change (add ! to override)";
 
-#[derive(Eq, PartialEq, Default)]
-enum PromptType {
-    Search,
-    Save,
-    #[default]
-    None,
+/// A read-only list shown in place of the text.
+enum Listing {
+    Directory(DirBrowser),
+    Grep(GrepResults),
 }
 
-impl PromptType {
-    fn is_none(&self) -> bool {
-        *self == Self::None
+impl Listing {
+    fn caret_position(&self) -> Position {
+        match self {
+            Self::Directory(browser) => browser.caret_position(),
+            Self::Grep(results) => results.caret_position(),
+        }
+    }
+    fn get_status(&self) -> DocumentStatus {
+        match self {
+            Self::Directory(browser) => browser.get_status(),
+            Self::Grep(results) => results.get_status(),
+        }
+    }
+}
+
+impl UIComponent for Listing {
+    fn set_needs_redraw(&mut self, value: bool) {
+        match self {
+            Self::Directory(browser) => browser.set_needs_redraw(value),
+            Self::Grep(results) => results.set_needs_redraw(value),
+        }
+    }
+    fn needs_redraw(&self) -> bool {
+        match self {
+            Self::Directory(browser) => browser.needs_redraw(),
+            Self::Grep(results) => results.needs_redraw(),
+        }
+    }
+    fn set_size(&mut self, size: Size) {
+        match self {
+            Self::Directory(browser) => browser.set_size(size),
+            Self::Grep(results) => results.set_size(size),
+        }
+    }
+    fn draw(&mut self, screen: &mut Screen, theme: &Theme, origin_row: usize) -> Result<(), Error> {
+        match self {
+            Self::Directory(browser) => browser.draw(screen, theme, origin_row),
+            Self::Grep(results) => results.draw(screen, theme, origin_row),
+        }
     }
 }
 
@@ -70,62 +149,182 @@ pub struct Editor {
     terminal_size: Size,
     title: String,
     quit_times: u8,
+    arguments: Arguments,
+    caret_position: Option<Position>,
+    screen: Screen,
+    presented_screen: Option<Screen>,
+    next_file_check: Option<Instant>,
+    terminal: Terminal,
+    themes: Themes,
+    config: Config,
+    next_autosave: Option<Instant>,
+    pending_keys: Vec<KeyChord>,
+    vim: Option<Vim>,
+    /// The candidates of the last Tab completion, and the one shown.
+    completion: Option<(Vec<String>, usize)>,
+    palette: Picker<Command>,
+    file_finder: Picker<String>,
+    file_preview: FilePreview,
+    pending: Option<PendingAction>,
+    /// Shown in place of the text while browsing a directory or grep results.
+    listing: Option<Listing>,
+    /// When and where the left button was last pressed, and how many clicks
+    /// in a row that made.
+    last_click: Option<(Instant, Position, u8)>,
+    macros: Macros,
+    /// How many macros are being played inside each other.
+    macro_depth: usize,
+    /// Where the config file, themes and macros live.
+    config_dir: Option<PathBuf>,
+    /// The block copied or cut last.
+    block_register: Register,
+    /// Whether moving the caret extends the block selection instead of
+    /// dropping it, as after `toggle_block_selection`.
+    extending_block: bool,
+    /// Where the file finder and grep look for files, if not the current
+    /// directory.
+    root_dir: Option<PathBuf>,
 }
 
 impl Editor {
     // region:: struct lifecycle
-    pub fn new() -> Result<Self, Error> {
+    /// Sets the editor up on the terminal with `config`, as loaded from
+    /// `config_dir`. A config that failed to load is reported, and the
+    /// defaults are used instead.
+    pub fn new(config: Result<Config, String>, config_dir: Option<PathBuf>) -> Result<Self, Error> {
         let current_hook = take_hook();
         set_hook(Box::new(move | panic_info | {
-            let _ = Terminal::terminate();
+            let _ = CrosstermBackend::default().terminate();
             current_hook(panic_info);
         }));
-        Terminal::initialize()?;
+        let (config, config_error) = match config {
+            Ok(config) => (config, None),
+            Err(err) => (Config::default(), Some(err)),
+        };
+        let mut editor = Self::with_backend(CrosstermBackend::default(), Arguments::parse(), config, config_dir)?;
+        if let Some(err) = config_error {
+            editor.update_message(&format!("ERR: {err}"));
+        }
+        Ok(editor)
+    }
+    /// Sets the editor up on `backend`. Themes, macros and reloaded config
+    /// come from `config_dir`, if any.
+    pub fn with_backend(
+        backend: impl Backend + 'static,
+        arguments: Arguments,
+        config: Config,
+        config_dir: Option<PathBuf>,
+    ) -> Result<Self, Error> {
         let mut editor = Self::default();
-        let size = Terminal::size().unwrap_or_default();
+        editor.arguments = arguments;
+        editor.config_dir = config_dir;
+        // The pipe has to be drained before the terminal takes over the keyboard.
+        let stdin_result = if editor.arguments.read_stdin {
+            editor.view.load_from_reader(&mut stdin().lock())
+        } else {
+            Ok(())
+        };
+        editor.terminal = Terminal::new(backend);
+        editor.terminal.initialize()?;
+        let size = editor.terminal.size().unwrap_or_default();
         editor.handle_resize_command(size);
-        editor.update_message("HELP: Ctrl - F = find | Ctrl-S = save | Ctrl-q = quit");
 
-        let args: Vec<String> = env::args().collect();
-        if let Some(file_name) = args.get(1) {
+        let (themes, theme_errors) = Themes::load(editor.config_dir.as_deref());
+        editor.themes = themes;
+        editor.themes.set_color_depth(editor.terminal.color_depth());
+        let config_result = editor.apply_config(config);
+        let macros_result = Macros::load(editor.config_dir.as_deref()).map(|macros| editor.macros = macros);
+        editor.update_message(&format!(
+            "HELP: {} = commands | {} = find | {} = save | {} = quit",
+            editor.key_hint(System(CommandPalette)),
+            editor.key_hint(System(Search)),
+            editor.key_hint(System(Save)),
+            editor.key_hint(System(Quit)),
+        ));
+        if let Some(err) = theme_errors.first() {
+            editor.update_message(&format!("ERR: Could not load theme: {err}"));
+        }
+        if let Err(err) = config_result {
+            editor.update_message(&format!("ERR: {err}"));
+        }
+        if let Err(err) = macros_result {
+            editor.update_message(&format!("ERR: Could not load macros: {err}"));
+        }
+
+        if stdin_result.is_err() {
+            editor.update_message("ERR: Could not read from stdin");
+        } else if let Some(file_name) = editor.arguments.file_name.clone() {
             debug_assert!(!file_name.is_empty());
-            if editor.view.load(file_name).is_err() {
-                editor.update_message("ERR: Could not open file: {file_name}");
+            match editor.open_file(&file_name) {
+                Ok(()) if editor.view.charset() == Charset::Latin1 => {
+                    let message = editor.opened_message(&file_name);
+                    editor.update_message(&message);
+                }
+                Ok(()) => {}
+                Err(err) => editor.update_message(&format!("ERR: {err}")),
             }
         }
 
-        editor.refresh_status();
+        editor.schedule_file_check();
         Ok(editor)
     }
+    /// Makes the file finder and grep search `dir` instead of the current
+    /// directory.
+    pub fn set_root_dir(&mut self, dir: PathBuf) {
+        self.root_dir = Some(dir);
+    }
     // endregion
 
     // region: Event Loop
     pub fn run(&mut self) {
         loop {
+            self.refresh_status();
             self.refresh_screen();
             if self.should_quit {
                 break;
             }
 
-            let has_event = poll(Duration::from_secs(0)).unwrap();
-
-            if has_event {
-                match read() {
-                    Ok(event) => self.evaluate_event(event),
-                    Err(err) => {
-                        #[cfg(debug_assertions)]
-                        {
-                            panic!("Could not read event: {err:?}");
-                        }
-                        #[cfg(not(debug_assertions))]
-                        {
-                            let _ = err;
-                        }
-                    }
+            match self.wait_for_event() {
+                Ok(true) => self.process_pending_events(),
+                Ok(false) => {},
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+                Err(err) => Self::handle_read_error(&err),
+            }
+
+            self.process_timers();
+        }
+    }
+    /// Blocks until an event arrives or the earliest pending timer is due.
+    fn wait_for_event(&mut self) -> Result<bool, Error> {
+        let timeout = self.time_until_next_timer();
+        self.terminal.poll_event(timeout)
+    }
+    /// Drains every queued event so that a burst of input (a paste, key
+    /// repeat) results in a single redraw.
+    fn process_pending_events(&mut self) {
+        loop {
+            match self.terminal.read_event() {
+                Ok(event) => self.evaluate_event(&event),
+                Err(err) => {
+                    Self::handle_read_error(&err);
+                    return;
                 }
             }
-            
-            self.refresh_status();
+            if self.should_quit
+                || !matches!(self.terminal.poll_event(Some(Duration::ZERO)), Ok(true))
+            {
+                return;
+            }
+        }
+    }
+    fn handle_read_error(err: &Error) {
+        #[cfg(debug_assertions)]
+        {
+            panic!("Could not read event: {err:?}");
+        }
+        #[cfg(not(debug_assertions))]
+        {
+            let _ = err;
         }
     }
     fn refresh_screen(&mut self) {
@@ -134,61 +333,487 @@ impl Editor {
         }
 
         let bottom_row_bar = self.terminal_size.height.saturating_sub(1);
-        let _ = Terminal::hide_caret();
+        let new_caret_pos = if self.in_prompt() {
+            Position {
+                row: bottom_row_bar,
+                col: self.command_bar.caret_position_col(),
+            }
+        } else if let Some(listing) = &self.listing {
+            listing.caret_position()
+        } else {
+            self.view.caret_position()
+        };
 
+        if !self.needs_redraw() && self.caret_position == Some(new_caret_pos) {
+            return;
+        }
+        if self.listing.is_none() {
+            self.view.update_bracket_pair();
+        }
+
+        let theme = self.themes.current();
         if self.in_prompt() {
-            self.command_bar.render(bottom_row_bar);
+            self.command_bar.render(&mut self.screen, theme, bottom_row_bar);
         } else {
-            self.message_bar.render(bottom_row_bar);
+            self.message_bar.render(&mut self.screen, theme, bottom_row_bar);
         }
         
         if self.terminal_size.height > 1 {
             self.status_bar
-                .render(self.terminal_size.height.saturating_sub(2));
+                .render(&mut self.screen, theme, self.terminal_size.height.saturating_sub(2));
         }
 
+        if self.prompt_type.has_popup() && (self.popup_needs_redraw() || self.text_needs_redraw()) {
+            // Popups cover the text, which has to show again wherever they
+            // shrank.
+            self.view.set_needs_redraw(true);
+            if let Some(listing) = &mut self.listing {
+                listing.set_needs_redraw(true);
+            }
+            self.palette.set_needs_redraw(true);
+            self.file_finder.set_needs_redraw(true);
+            self.file_preview.set_needs_redraw(true);
+        }
         if self.terminal_size.height > 2 {
-            self.view.render(0);
+            if let Some(listing) = &mut self.listing {
+                listing.render(&mut self.screen, theme, 0);
+            } else {
+                self.view.render(&mut self.screen, theme, 0);
+            }
         }
-
-        let new_caret_pos = if self.in_prompt() {
-            Position {
-                row: bottom_row_bar,
-                col: self.command_bar.caret_position_col(),
+        let status_row = self.terminal_size.height.saturating_sub(2);
+        match self.prompt_type {
+            PromptType::Palette => {
+                self.palette.render_above(&mut self.screen, theme, status_row);
             }
-        } else {
-            self.view.caret_position()
-        };
+            PromptType::OpenFile => {
+                let list_row = self.file_finder.render_above(&mut self.screen, theme, status_row);
+                self.file_preview.set_size(Size {
+                    height: list_row,
+                    width: self.terminal_size.width,
+                });
+                self.file_preview.render(&mut self.screen, theme, 0);
+            }
+            PromptType::Search
+            | PromptType::Save
+            | PromptType::Command
+            | PromptType::Confirm
+            | PromptType::NewEntry
+            | PromptType::Rename
+            | PromptType::Grep
+            | PromptType::RecordMacro
+            | PromptType::PlayMacro
+            | PromptType::ApplyMacro
+            | PromptType::None => {}
+        }
+
         debug_assert!(new_caret_pos.col <= self.terminal_size.width);
         debug_assert!(new_caret_pos.row <= self.terminal_size.height);
-        
-        let _ = Terminal::move_caret_to(new_caret_pos);
-        let _ = Terminal::show_caret();
-        let _ = Terminal::execute();
 
+        let _ = self.terminal.hide_caret();
+        let _ = self.terminal.present(self.presented_screen.as_ref(), &self.screen);
+        let _ = self.terminal.move_caret_to(new_caret_pos);
+        // The wheel can scroll the caret out of view.
+        if self.in_prompt() || self.listing.is_some() || self.view.is_caret_visible() {
+            let _ = self.terminal.show_caret();
+        }
+        let _ = self.terminal.execute();
+        self.caret_position = Some(new_caret_pos);
+        self.presented_screen.get_or_insert_with(Screen::default).clone_from(&self.screen);
+    }
+    fn needs_redraw(&self) -> bool {
+        let bottom_bar_needs_redraw = if self.in_prompt() {
+            self.command_bar.needs_redraw()
+        } else {
+            self.message_bar.needs_redraw()
+        };
+
+        bottom_bar_needs_redraw
+            || self.status_bar.needs_redraw()
+            || self.text_needs_redraw()
+            || self.popup_needs_redraw()
+    }
+    /// Whether the text area, showing either the text or a listing, needs a
+    /// redraw.
+    fn text_needs_redraw(&self) -> bool {
+        match &self.listing {
+            Some(listing) => listing.needs_redraw(),
+            None => self.view.needs_redraw(),
+        }
+    }
+    fn popup_needs_redraw(&self) -> bool {
+        match self.prompt_type {
+            PromptType::Palette => self.palette.needs_redraw(),
+            PromptType::OpenFile => self.file_finder.needs_redraw() || self.file_preview.needs_redraw(),
+            PromptType::Search
+            | PromptType::Save
+            | PromptType::Command
+            | PromptType::Confirm
+            | PromptType::NewEntry
+            | PromptType::Rename
+            | PromptType::Grep
+            | PromptType::RecordMacro
+            | PromptType::PlayMacro
+            | PromptType::ApplyMacro
+            | PromptType::None => false,
+        }
     }
     pub fn refresh_status(&mut self) {
-        let status = self.view.get_status();
+        let status = if let Some(listing) = &self.listing {
+            listing.get_status()
+        } else {
+            let mut status = self.view.get_status();
+            status.mode = self.vim.as_ref().map(|vim| vim.mode().name());
+            status
+        };
         let title = format!("{} - {NAME}", status.file_name);
         self.status_bar.update_status(status);
 
-        if title != self.title && matches!(Terminal::set_title(&title), Ok(())) {
+        if title != self.title && matches!(self.terminal.set_title(&title), Ok(())) {
             self.title = title;
         }
     }
-    pub fn evaluate_event(&mut self, event: Event) {
-        let should_process = match &event {
-            Event::Key(KeyEvent { kind, .. }) => kind == &KeyEventKind::Press,
-            Event::Resize(_, _) => true,
-            _ => false,
+    /// Handles `event` as one undo step. Typed characters join the step of
+    /// those typed before them.
+    pub fn evaluate_event(&mut self, event: &Event) {
+        self.view.begin_undo_step(self.types_text(event));
+        match event {
+            Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
+                self.evaluate_key(KeyChord::from(*key_event));
+            }
+            Event::Mouse(mouse_event) => self.evaluate_mouse_event(*mouse_event),
+            Event::Resize(width_u16, height_u16) => self.process_command(System(Resize(Size {
+                height: usize::from(*height_u16),
+                width: usize::from(*width_u16),
+            }))),
+            _ => {}
+        }
+        self.view.end_undo_step();
+    }
+    /// Whether `event` types a character into the text.
+    fn types_text(&self, event: &Event) -> bool {
+        let Event::Key(key_event) = event else {
+            return false;
         };
+        KeyChord::from(*key_event).as_char().is_some()
+            && self.pending_keys.is_empty()
+            && !self.in_prompt()
+            && self.listing.is_none()
+            && self.vim.as_ref().is_none_or(|vim| vim.mode() == Mode::Insert)
+    }
+    // end region
 
-        if should_process {
-            if let Ok(command) = Command::try_from(event) {
+    // region key bindings
+    fn keymap(&self) -> &Keymap {
+        let keymaps = &self.config.keymaps;
+        match self.prompt_type {
+            PromptType::Search => &keymaps.search,
+            PromptType::Save
+            | PromptType::NewEntry
+            | PromptType::Rename
+            | PromptType::Grep
+            | PromptType::RecordMacro
+            | PromptType::PlayMacro
+            | PromptType::ApplyMacro => &keymaps.save,
+            PromptType::Command => &keymaps.command,
+            PromptType::Palette | PromptType::OpenFile => &keymaps.picker,
+            PromptType::Confirm => &keymaps.confirm,
+            PromptType::None => &keymaps.normal,
+        }
+    }
+    /// Collects chords until they form a bound sequence. An unbound single
+    /// chord that types a character inserts it.
+    fn evaluate_key(&mut self, chord: KeyChord) {
+        if self.pending_keys.is_empty() && self.takes_vim_keys() {
+            if chord.is_ctrl('v') {
+                self.macros.record(Block(command::Block::ToggleSelection));
+                self.process_command(Block(command::Block::ToggleSelection));
+                return;
+            }
+            if let Some(character) = chord.as_char() {
+                self.macros.record(Edit(Insert(character)));
+                self.process_vim_char(character);
+                return;
+            }
+        }
+        self.pending_keys.push(chord);
+        match self.keymap().lookup(&self.pending_keys) {
+            Lookup::Command(command) => {
+                if take(&mut self.pending_keys).len() > 1 {
+                    self.update_message("");
+                }
+                self.macros.record(command);
                 self.process_command(command);
             }
+            Lookup::Prefix => {
+                let keys = format_sequence(&self.pending_keys);
+                self.update_message(&format!("{keys}-"));
+            }
+            Lookup::Unbound => {
+                let keys = take(&mut self.pending_keys);
+                if let [chord] = keys.as_slice() {
+                    if let Some(character) = chord.as_char() {
+                        self.macros.record(Edit(Insert(character)));
+                        self.process_command(Edit(Insert(character)));
+                    }
+                } else {
+                    self.update_message(&format!("{} is not bound", format_sequence(&keys)));
+                }
+            }
         }
     }
+    /// Outside of insert mode and prompts, typed characters are Vim keys.
+    fn takes_vim_keys(&self) -> bool {
+        !self.in_prompt()
+            && self.listing.is_none()
+            && self.vim.as_ref().is_some_and(|vim| vim.mode() != Mode::Insert)
+    }
+    fn process_vim_char(&mut self, character: char) {
+        if let Some(vim) = &mut self.vim
+            && let Some((action, count)) = vim.handle_char(character)
+        {
+            self.process_vim_action(action, count);
+        }
+    }
+    /// The keys bound to `command` in the normal keymap, for help texts.
+    fn key_hint(&self, command: Command) -> String {
+        self.config
+            .keymaps
+            .normal
+            .keys_for(command)
+            .or_else(|| command.name().map(String::from))
+            .unwrap_or_default()
+    }
+    // end region
+
+    // region mouse
+    fn evaluate_mouse_event(&mut self, event: MouseEvent) {
+        if self.in_prompt() {
+            return;
+        }
+        let position = Position {
+            row: usize::from(event.row),
+            col: usize::from(event.column),
+        };
+        if self.listing.is_some() {
+            // Listings only follow the wheel, by moving their selection.
+            let command = match event.kind {
+                MouseEventKind::ScrollUp => Move(Up),
+                MouseEventKind::ScrollDown => Move(Down),
+                _ => return,
+            };
+            for _ in 0..WHEEL_SCROLL_LINES {
+                self.process_command(command);
+            }
+            return;
+        }
+        match event.kind {
+            MouseEventKind::Down(MouseButton::Left) if event.modifiers.contains(KeyModifiers::ALT) => {
+                self.add_caret_at(position);
+            }
+            MouseEventKind::Down(MouseButton::Left) => self.click(position),
+            MouseEventKind::Drag(MouseButton::Left) => {
+                self.drag_to(position, event.modifiers.contains(KeyModifiers::ALT));
+            }
+            MouseEventKind::ScrollUp => self.view.scroll_by(-WHEEL_SCROLL_LINES),
+            MouseEventKind::ScrollDown => self.view.scroll_by(WHEEL_SCROLL_LINES),
+            _ => {}
+        }
+    }
+    /// Places the caret; a double click selects a word, a triple click the
+    /// line.
+    fn click(&mut self, position: Position) {
+        if position.row >= self.terminal_size.height.saturating_sub(2) {
+            self.last_click = None;
+            return;
+        }
+        let now = Instant::now();
+        let count = match self.last_click {
+            Some((time, last_position, count))
+                if last_position == position && now.duration_since(time) <= MULTI_CLICK_INTERVAL =>
+            {
+                if count >= 3 { 1 } else { count.saturating_add(1) }
+            }
+            _ => 1,
+        };
+        self.last_click = Some((now, position, count));
+
+        self.view.clear_carets();
+        self.clear_selection();
+        if count > 1 {
+            self.begin_selection(false);
+        }
+        self.view.move_caret_to_position(position);
+        match count {
+            2 => self.view.select_word(),
+            3 => self.view.select_line(),
+            _ => {}
+        }
+        self.after_mouse_move();
+    }
+    /// Alt+click adds a caret, and makes it the primary one.
+    fn add_caret_at(&mut self, position: Position) {
+        if position.row >= self.terminal_size.height.saturating_sub(2) {
+            self.last_click = None;
+            return;
+        }
+        // Dragging from here selects a block.
+        self.last_click = Some((Instant::now(), position, 1));
+        self.clear_selection();
+        self.view.add_caret_at_position(position);
+        self.after_mouse_move();
+    }
+    /// Selects from where the button was pressed to `position`, or the
+    /// block between them while Alt is held.
+    fn drag_to(&mut self, position: Position, block: bool) {
+        if self.view.selection_range().is_none() {
+            let Some((_, pressed_at, _)) = self.last_click else {
+                return;
+            };
+            self.begin_selection(block);
+            self.view.move_caret_to_position(pressed_at);
+            if block {
+                // The caret Alt added on the press is not wanted after all.
+                self.view.clear_carets();
+                self.view.start_block_selection();
+            } else {
+                self.view.start_selection();
+            }
+        }
+        self.view.move_caret_to_position(position);
+        self.after_mouse_move();
+    }
+    /// Drops the selection, and leaves visual mode in Vim mode.
+    fn clear_selection(&mut self) {
+        self.extending_block = false;
+        self.view.clear_selection();
+        if let Some(vim) = &mut self.vim
+            && vim.mode().is_visual()
+        {
+            vim.set_mode(Mode::Normal);
+        }
+    }
+    /// Selecting with the mouse enters visual mode, or visual block mode,
+    /// in Vim mode.
+    fn begin_selection(&mut self, block: bool) {
+        self.extending_block = false;
+        if self.vim.as_ref().is_some_and(|vim| vim.mode() == Mode::Insert) {
+            self.leave_vim_mode();
+        }
+        if let Some(vim) = &mut self.vim {
+            vim.set_mode(if block { Mode::VisualBlock } else { Mode::Visual });
+        }
+    }
+    /// Outside of Vim mode, moving the caret drops the selection, unless it
+    /// extends a block, and typing replaces it. Returns whether that used up `command`.
+    fn replace_selection(&mut self, command: Command) -> bool {
+        let Some(range) = self.view.selection_range() else {
+            return false;
+        };
+        match command {
+            Edit(Delete | DeleteBackward) => {
+                self.clear_selection();
+                self.view.delete_range(range);
+                true
+            }
+            Edit(Insert(character))
+                if matches!(range, view::TextRange::Chars { .. }) && self.view.closing_partner(character).is_some() =>
+            {
+                self.view.wrap_range(range, character);
+                true
+            }
+            Edit(_) => {
+                // Typing over a block types on each of its lines.
+                self.clear_selection();
+                self.view.change_range(range);
+                false
+            }
+            Move(_) if self.extending_block => false,
+            Move(_) => {
+                self.clear_selection();
+                false
+            }
+            System(Dismiss) => {
+                self.clear_selection();
+                true
+            }
+            // The other commands work on the selection, or leave it alone.
+            _ => false,
+        }
+    }
+    fn after_mouse_move(&mut self) {
+        self.reset_quit_times();
+        let Some(vim) = &mut self.vim else {
+            return;
+        };
+        if vim.mode() == Mode::Insert {
+            vim.restart_insert();
+        } else {
+            vim.reset_pending();
+            self.view.clamp_caret_to_text();
+        }
+    }
+    // end region
+
+    // region timers
+    fn time_until_next_timer(&self) -> Option<Duration> {
+        // The message bar is hidden while a prompt is open, so its expiry is
+        // only picked up once the prompt closes.
+        let message_deadline = if self.in_prompt() {
+            None
+        } else {
+            self.message_bar.next_deadline()
+        };
+
+        let grep_poll = match &self.listing {
+            Some(Listing::Grep(results)) if results.is_running() => Instant::now().checked_add(GREP_POLL_INTERVAL),
+            _ => None,
+        };
+
+        [message_deadline, self.next_file_check, self.next_autosave, grep_poll]
+            .into_iter()
+            .flatten()
+            .min()
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+    fn process_timers(&mut self) {
+        // Message expiry needs no handling here: the message bar flags itself
+        // for a redraw once its deadline has passed.
+        if let Some(Listing::Grep(results)) = &mut self.listing {
+            results.poll();
+        }
+        if self
+            .next_file_check
+            .is_some_and(|deadline| deadline <= Instant::now())
+        {
+            if self.view.check_changed_on_disk() {
+                self.update_message("WARNING: File has been changed on disk.");
+            }
+            self.schedule_file_check();
+        }
+        if self
+            .next_autosave
+            .is_some_and(|deadline| deadline <= Instant::now())
+        {
+            if self.view.is_file_loaded() && self.view.get_status().is_modified {
+                self.autosave();
+            }
+            self.schedule_autosave();
+        }
+    }
+    fn schedule_autosave(&mut self) {
+        self.next_autosave = self
+            .config
+            .autosave
+            .and_then(|interval| Instant::now().checked_add(interval));
+    }
+    fn schedule_file_check(&mut self) {
+        self.next_file_check = self
+            .view
+            .is_file_loaded()
+            .then(|| Instant::now().checked_add(FILE_CHECK_INTERVAL))
+            .flatten();
+    }
     // end region
     
     // region command handling
@@ -198,35 +823,262 @@ impl Editor {
             return;
         }
 
-        match self.prompt_type {
-            PromptType::Search => self.process_command_during_search(command),
-            PromptType::Save => self.process_command_during_save(command),
-            PromptType::None => self.process_command_no_prompt(command),
+        if self.in_prompt() {
+            self.process_command_during_prompt(command);
+        } else {
+            self.process_command_no_prompt(command);
         }
-        
     }
     fn process_command_no_prompt(&mut self, command: Command) {
+        let handled = match self.listing {
+            Some(Listing::Directory(_)) => self.process_command_in_browser(command),
+            Some(Listing::Grep(_)) => self.process_command_in_grep(command),
+            None => false,
+        };
+        if handled {
+            return;
+        }
+        if let Some(vim) = &mut self.vim {
+            match (vim.mode(), command) {
+                (Mode::Normal | Mode::Visual | Mode::VisualBlock, Edit(edit)) => {
+                    self.process_vim_edit_key(edit);
+                    return;
+                }
+                (Mode::Normal | Mode::Visual | Mode::VisualBlock, Move(move_command)) => {
+                    vim.reset_pending();
+                    self.view.handle_move_command(move_command);
+                    self.view.clamp_caret_to_text();
+                    return;
+                }
+                (_, System(Dismiss)) => {
+                    self.leave_vim_mode();
+                    return;
+                }
+                (_, Block(command::Block::ToggleSelection)) => {
+                    vim.reset_pending();
+                    self.process_vim_action(Action::ToggleVisualBlock, None);
+                    return;
+                }
+                (Mode::Insert, Edit(edit)) => vim.record_edit(edit),
+                (Mode::Insert, Move(_)) => vim.restart_insert(),
+                _ => {}
+            }
+        }
         if matches!(command, System(Quit)) {
             self.handle_quit_command();
             return;
         }
         self.reset_quit_times();
+        if self.vim.is_none() && self.replace_selection(command) {
+            return;
+        }
 
         match command {
-            System(Quit | Resize(_) | Dismiss) => {},
+            System(Quit | Resize(_)) | Prompt(_) => {},
+            System(Dismiss) => {
+                self.view.clear_carets();
+            }
             System(Search) => self.set_prompt(PromptType::Search),
+            System(CommandLine) => self.set_prompt(PromptType::Command),
+            System(CommandPalette) => self.set_prompt(PromptType::Palette),
+            System(OpenFile) => self.set_prompt(PromptType::OpenFile),
+            System(Browse) => self.browse_current_dir(),
+            System(Grep) => self.set_prompt(PromptType::Grep),
+            System(CycleTheme) => self.cycle_theme(),
+            System(ReloadConfig) => self.reload_config(),
             System(Save) => self.handle_save_command(),
             Edit(edit_command) => self.view.handle_edit_command(edit_command),
             Move(move_command) => self.view.handle_move_command(move_command),
+            Caret(caret_command) => self.add_carets(caret_command),
+            Macro(macro_command) => self.process_macro_command(macro_command),
+            Lines(lines_command) => {
+                if let Err(err) = self.view.handle_lines_command(lines_command) {
+                    self.update_message(&format!("ERR: {err}"));
+                }
+                // Their lines are gone, so the selection is too.
+                if matches!(lines_command, command::Lines::Join | command::Lines::Delete | command::Lines::Reflow) {
+                    self.clear_selection();
+                }
+            }
+            Block(block_command) => self.process_block_command(block_command),
+            History(history_command) => self.process_history_command(history_command),
+        }
+    }
+    // region block selection
+    fn process_block_command(&mut self, command: command::Block) {
+        match command {
+            command::Block::ToggleSelection => {
+                if self.extending_block {
+                    self.clear_selection();
+                    return;
+                }
+                self.begin_selection(true);
+                if self.view.selection_range().is_some() {
+                    self.view.set_block_selection(true);
+                } else {
+                    self.view.start_block_selection();
+                }
+                self.extending_block = true;
+            }
+            command::Block::Copy | command::Block::Cut => {
+                let Some(range @ view::TextRange::Block { .. }) = self.view.selection_range() else {
+                    self.update_message("ERR: No block selected");
+                    return;
+                };
+                self.block_register = self.view.text_in_range(range);
+                if command == command::Block::Cut {
+                    self.view.delete_range(range);
+                }
+                self.clear_selection();
+            }
+            command::Block::Paste => {
+                if self.block_register.text.is_empty() {
+                    self.update_message("ERR: Nothing to paste");
+                    return;
+                }
+                self.clear_selection();
+                self.view.put(&self.block_register, true, 1);
+            }
+        }
+    }
+    // end region
+    // region undo
+    fn process_history_command(&mut self, command: command::History) {
+        self.clear_selection();
+        let (done, nothing) = match command {
+            command::History::Undo => (self.view.undo(), "Nothing to undo"),
+            command::History::Redo => (self.view.redo(), "Nothing to redo"),
+        };
+        if !done {
+            self.update_message(nothing);
+        }
+        if self.vim.is_some() {
+            self.view.clamp_caret_to_text();
+        }
+    }
+    // end region
+    // region macros
+    fn process_macro_command(&mut self, command: command::Macro) {
+        match command {
+            command::Macro::Record => {
+                if self.macros.recording().is_some() {
+                    self.stop_recording(Macro(command::Macro::Record));
+                } else {
+                    self.set_prompt(PromptType::RecordMacro);
+                }
+            }
+            command::Macro::Play => self.set_prompt(PromptType::PlayMacro),
+            command::Macro::ApplyToSelection => {
+                if self.view.selected_lines().is_some() {
+                    self.set_prompt(PromptType::ApplyMacro);
+                } else {
+                    self.update_message("ERR: Nothing selected");
+                }
+            }
+            command::Macro::Save => match self.macros.save(self.config_dir.as_deref()) {
+                Ok(path) => self.update_message(&format!("Macros saved to {}", path.display())),
+                Err(err) => self.update_message(&format!("ERR: Could not save macros: {err}")),
+            },
+        }
+    }
+    fn start_recording(&mut self, register: char) {
+        self.macros.start_recording(register);
+        let stop_key = if let Some(vim) = &mut self.vim {
+            vim.set_recording(true);
+            String::from("q")
+        } else {
+            self.key_hint(Macro(command::Macro::Record))
+        };
+        self.update_message(&format!("Recording macro {register}, {stop_key} stops"));
+    }
+    /// Ends the recording, which `stop` did. It is not part of the macro.
+    fn stop_recording(&mut self, stop: Command) {
+        if let Some(vim) = &mut self.vim {
+            vim.set_recording(false);
+        }
+        if let Some((register, len)) = self.macros.stop_recording(stop) {
+            self.update_message(&format!("Recorded {len} commands into {register}"));
+        }
+    }
+    /// Plays the macro in `register`, or the one played last, `count` times.
+    /// Its commands run as if typed, except that they are not recorded.
+    #[allow(clippy::arithmetic_side_effects)]
+    fn play_macro(&mut self, register: Option<char>, count: usize) -> Result<(), String> {
+        let commands = self.macros.play(register)?;
+        if self.macro_depth >= MAX_MACRO_DEPTH {
+            return Err(String::from("Macros nested too deeply"));
         }
+        self.macro_depth += 1;
+        for _ in 0..count.max(1) {
+            for command in &commands {
+                if let Edit(Insert(character)) = *command
+                    && self.takes_vim_keys()
+                {
+                    self.process_vim_char(character);
+                } else {
+                    self.process_command(*command);
+                }
+            }
+            if self.should_quit {
+                break;
+            }
+        }
+        self.macro_depth -= 1;
+        Ok(())
     }
+    /// Plays the macro once on each selected line, starting at the start of
+    /// the line, as the lines are numbered before it runs.
+    fn apply_macro_to_selection(&mut self, register: Option<char>) -> Result<(), String> {
+        let lines = self
+            .view
+            .selected_lines()
+            .ok_or_else(|| String::from("Nothing selected"))?;
+        self.clear_selection();
+        for line_idx in lines {
+            if line_idx >= self.view.height() {
+                break;
+            }
+            self.view.goto_line(line_idx);
+            self.play_macro(register, 1)?;
+            if self.vim.as_ref().is_some_and(|vim| vim.mode() == Mode::Insert) {
+                self.leave_vim_mode();
+            }
+        }
+        Ok(())
+    }
+    // end region
+    // region carets
+    fn add_carets(&mut self, command: command::Caret) {
+        let added = match command {
+            Above => self.view.add_caret_vertically(false),
+            Below => self.view.add_caret_vertically(true),
+            AtNextMatch => self.view.add_caret_at_next_match(),
+            AtAllMatches => self.view.add_carets_at_all_matches(),
+        };
+        // Carets at matches take the place of the selection.
+        self.clear_selection();
+        if added {
+            self.update_message(&format!("{} carets", self.view.caret_count()));
+        } else {
+            self.update_message("No more carets to add");
+        }
+    }
+    // end region
     // region resize command handling
     fn handle_resize_command(&mut self, size: Size) {
         self.terminal_size = size;
-        self.view.resize(Size {
+        self.screen = Screen::new(size);
+        // Whatever is on the terminal now is unknown, so the next frame is
+        // painted in full.
+        self.presented_screen = None;
+        let text_size = Size {
             height: size.height.saturating_sub(2),
             width: size.width,
-        });
+        };
+        self.view.resize(text_size);
+        if let Some(listing) = &mut self.listing {
+            listing.resize(text_size);
+        }
         let bar_size = Size {
             height: 1,
             width: size.width,
@@ -234,17 +1086,26 @@ impl Editor {
         self.message_bar.resize(bar_size);
         self.status_bar.resize(bar_size);
         self.command_bar.resize(bar_size);
+        let popup_size = Size {
+            height: size.height.saturating_sub(2),
+            width: size.width,
+        };
+        self.palette.resize(popup_size);
+        self.file_finder.resize(popup_size);
+        self.file_preview.resize(popup_size);
     }
     // end region
     // region quit command handling
     #[allow(clippy::arithmetic_side_effects)]
     fn handle_quit_command(&mut self) {
-        if !self.view.get_status().is_modified || self.quit_times + 1 == QUIT_TIMES {
+        let quit_times = self.config.quit_times;
+        if !self.view.get_status().is_modified || self.quit_times + 1 >= quit_times {
             self.should_quit = true;
         } else if self.view.get_status().is_modified {
             self.update_message(&format!(
-                "WARNING: File has unsaved changes. Press Ctrl-Q {} more times to quit!",
-                QUIT_TIMES - self.quit_times - 1
+                "WARNING: File has unsaved changes. Press {} {} more times to quit!",
+                self.key_hint(System(Quit)),
+                quit_times - self.quit_times - 1
             ));
             self.quit_times += 1;
         }
@@ -256,7 +1117,7 @@ impl Editor {
         }
     }
     // end region
-    // region save command & prompt handling
+    // region save command handling
     fn handle_save_command(&mut self) {
         if self.view.is_file_loaded() {
             self.save(None);
@@ -264,22 +1125,9 @@ impl Editor {
             self.set_prompt(PromptType::Save);
         }
     }
-    fn process_command_during_save(&mut self, command: Command) {
-        match command {
-            System(Quit | Resize(_) | Search | Save ) | Move(_) => {},
-            System(Dismiss) => {
-                self.set_prompt(PromptType::None);
-                self.update_message("Save aborted.");
-            }
-            Edit(InsertNewLine) => {
-                let file_name = self.command_bar.value();
-                self.save(Some(&file_name));
-                self.set_prompt(PromptType::None);
-            }
-            Edit(edit_command) => self.command_bar.handle_edit_command(edit_command),
-        }
-    }
-    fn save(&mut self, file_name: Option<&str>) {
+    /// Writes the buffer, under a new name if given. Returns whether that
+    /// worked; either way the outcome is shown in the message bar.
+    fn save(&mut self, file_name: Option<&str>) -> bool {
         let result = if let Some(name) = file_name {
             self.view.save_as(name)
         } else {
@@ -287,64 +1135,666 @@ impl Editor {
         };
 
         if result.is_ok() {
+            self.schedule_file_check();
+            if file_name.is_some() {
+                // The new name may come with a different file type.
+                self.apply_buffer_settings();
+            }
+            self.message_bar.update_message("File saved successfully.");
+        } else {
+            self.message_bar.update_message("Error writing file!");
+        }
+        result.is_ok()
+    }
+    fn autosave(&mut self) {
+        if self.view.autosave().is_ok() {
+            self.schedule_file_check();
             self.message_bar.update_message("File saved successfully.");
         } else {
             self.message_bar.update_message("Error writing file!");
         }
     }
+    /// Loads the file into the view, or browses it if it is a directory.
+    fn open_file(&mut self, file_name: &str) -> Result<(), String> {
+        if Path::new(file_name).is_dir() {
+            return self.browse(Path::new(file_name));
+        }
+        self.view
+            .load(file_name)
+            .map_err(|_| format!("Could not open file: {file_name}"))?;
+        self.apply_buffer_settings();
+        if self.vim.is_some() {
+            self.view.clamp_caret_to_text();
+        }
+        self.schedule_file_check();
+        self.close_listing();
+        Ok(())
+    }
+    /// Reports the file as opened, pointing out when it wasn't UTF-8.
+    fn opened_message(&self, file_name: &str) -> String {
+        if self.view.charset() == Charset::Latin1 {
+            format!("Opened {file_name}, which is not UTF-8, as Latin-1")
+        } else {
+            format!("Opened {file_name}")
+        }
+    }
     // end region
-    // region search command & prompt handling
-    fn process_command_during_search(&mut self, command: Command) {
+    // region command line
+    fn run_ex_command(&mut self, input: &str) {
+        if let Err(err) = ExCommand::parse(input).and_then(|command| self.execute_ex_command(command)) {
+            self.update_message(&format!("ERR: {err}"));
+        }
+    }
+    fn execute_ex_command(&mut self, command: ExCommand) -> Result<(), String> {
+        let is_modified = self.view.get_status().is_modified;
         match command {
-            System(Dismiss) => {
-                self.set_prompt(PromptType::None);
-                self.view.dismiss_search();
-            },
-            Edit(InsertNewLine) => {
-                self.set_prompt(PromptType::None);
-                self.view.exit_search();
-            },
-            Edit(edit_command) => {
-                self.command_bar.handle_edit_command(edit_command);
-                let query = self.command_bar.value();
-                self.view.search(&query);
-            },
-            Move(Right | Down) => self.view.search_next(),
-            Move(Left | Up) => self.view.search_prev(),
-            System(Quit | Resize(_) | Search | Save) | Move(_) => {},
+            ExCommand::Write(file_name) => self.write_file(file_name.as_deref())?,
+            ExCommand::WriteQuit => {
+                self.write_file(None)?;
+                self.should_quit = true;
+            }
+            ExCommand::Quit { force } => {
+                if is_modified && !force {
+                    return Err(String::from(UNSAVED_CHANGES));
+                }
+                self.should_quit = true;
+            }
+            ExCommand::Edit { file_name, force } => {
+                if is_modified && !force {
+                    return Err(String::from(UNSAVED_CHANGES));
+                }
+                let file_name = file_name
+                    .or_else(|| self.view.file_name())
+                    .ok_or_else(|| String::from("No file name"))?;
+                self.open_file(&file_name)?;
+                self.update_message(&self.opened_message(&file_name));
+            }
+            ExCommand::Set(options) => {
+                for (name, value) in options {
+                    self.set_option(&name, value.as_deref())?;
+                }
+            }
+            ExCommand::Goto(line) => self.view.goto_line(line.saturating_sub(1)),
+            ExCommand::Substitute {
+                range,
+                regex,
+                replacement,
+                global,
+            } => {
+                let lines = range.resolve(self.view.current_line_idx(), self.view.height())?;
+                let replacements = self.view.substitute(lines, &regex, &replacement, global);
+                if replacements == 0 {
+                    return Err(format!("Pattern not found: {}", regex.as_str()));
+                }
+                self.update_message(&format!("{replacements} substitutions"));
+            }
+            ExCommand::Sort { range, options } => {
+                let height = self.view.height();
+                let lines = match range {
+                    Some(range) => range.resolve(self.view.current_line_idx(), height)?,
+                    None => 0..=height.saturating_sub(1),
+                };
+                self.view.sort_lines(&lines, options);
+            }
+        }
+        Ok(())
+    }
+    fn write_file(&mut self, file_name: Option<&str>) -> Result<(), String> {
+        if file_name.is_none() && !self.view.is_file_loaded() {
+            return Err(String::from("No file name"));
+        }
+        if self.save(file_name) {
+            Ok(())
+        } else {
+            Err(String::from("Could not write file"))
+        }
+    }
+    /// Sets an option for this session. Boolean options are set when given
+    /// without a value, and cleared when prefixed with `no`.
+    fn set_option(&mut self, name: &str, value: Option<&str>) -> Result<(), String> {
+        const BOOLEAN_OPTIONS: [&str; 6] = ["auto_pairs", "auto_wrap", "expand_tabs", "et", "mouse", "vim_mode"];
+        let (name, value) = match (value, name.strip_prefix("no")) {
+            (Some(value), _) => (name, value),
+            (None, Some(cleared)) if BOOLEAN_OPTIONS.contains(&cleared) => (cleared, "false"),
+            (None, _) if BOOLEAN_OPTIONS.contains(&name) => (name, "true"),
+            (None, _) => return Err(format!("{name} needs a value")),
+        };
+        let parse_bool = |value: &str| {
+            value
+                .parse::<bool>()
+                .map_err(|_| format!("{name} must be true or false, got '{value}'"))
+        };
 
+        let mut settings = self.view.settings();
+        match name {
+            "tab_width" | "ts" => {
+                let tab_width = value
+                    .parse()
+                    .map_err(|_| format!("{name} must be a number, got '{value}'"))?;
+                config::validate_tab_width(tab_width)?;
+                settings.tab_width = tab_width;
+                self.view.apply_settings(settings);
+            }
+            "expand_tabs" | "et" => {
+                settings.expand_tabs = parse_bool(value)?;
+                self.view.apply_settings(settings);
+            }
+            "auto_pairs" => {
+                settings.auto_pairs = parse_bool(value)?;
+                self.view.apply_settings(settings);
+            }
+            "text_width" | "tw" => {
+                let text_width = value
+                    .parse()
+                    .map_err(|_| format!("{name} must be a number, got '{value}'"))?;
+                config::validate_text_width(text_width)?;
+                settings.text_width = text_width;
+                self.view.apply_settings(settings);
+            }
+            "auto_wrap" => {
+                settings.auto_wrap = parse_bool(value)?;
+                self.view.apply_settings(settings);
+            }
+            "theme" => {
+                if !self.themes.select(value) {
+                    return Err(format!("Unknown theme '{value}'"));
+                }
+                self.redraw_all();
+            }
+            "vim_mode" => {
+                self.config.vim_mode = parse_bool(value)?;
+                self.apply_vim_mode();
+            }
+            "mouse" => {
+                self.config.mouse = parse_bool(value)?;
+                self.apply_mouse_capture();
+            }
+            _ => return Err(format!("Unknown option '{name}'")),
         }
+        Ok(())
     }
     // end region
-    // region message & command bar
-    fn update_message(&mut self, new_message: &str) {
-        self.message_bar.update_message(new_message);
+    // region vim mode
+    fn process_vim_action(&mut self, action: Action, count: Option<usize>) {
+        if action == Action::Repeat {
+            self.repeat_change(count);
+            return;
+        }
+        self.execute_vim_action(action, count);
+        if let Some(vim) = &mut self.vim {
+            vim.record(action, count);
+            if vim.mode() != Mode::Insert {
+                self.view.clamp_caret_to_text();
+            }
+        }
+    }
+    fn execute_vim_action(&mut self, action: Action, count: Option<usize>) {
+        let Some(vim) = &mut self.vim else {
+            return;
+        };
+        match action {
+            Action::Move(motion) => self.view.move_by(motion, count),
+            Action::Operate(operator, motion) => {
+                // `cw` on a word changes just the word, like `ce`.
+                let motion = if operator == Operator::Change
+                    && motion == Motion::WordStart
+                    && !self.view.is_blank_at_caret()
+                {
+                    Motion::WordEnd
+                } else {
+                    motion
+                };
+                let range = self.view.motion_range(motion, count);
+                self.operate(operator, range);
+            }
+            Action::OperateSelection(operator) => {
+                if let Some(range) = self.view.selection_range() {
+                    self.view.clear_selection();
+                    vim.set_mode(Mode::Normal);
+                    self.operate(operator, range);
+                }
+            }
+            Action::Insert(at) => {
+                self.view.prepare_insert(at);
+                vim.set_mode(Mode::Insert);
+            }
+            Action::Put { before } => self.view.put(&vim.register, before, count.unwrap_or(1)),
+            Action::ToggleVisual | Action::ToggleVisualBlock => {
+                let mode = if action == Action::ToggleVisual { Mode::Visual } else { Mode::VisualBlock };
+                if vim.mode() == mode {
+                    self.view.clear_selection();
+                    vim.set_mode(Mode::Normal);
+                } else if vim.mode().is_visual() {
+                    // Switches between the two, keeping the selection.
+                    self.view.set_block_selection(mode == Mode::VisualBlock);
+                    vim.set_mode(mode);
+                } else {
+                    if mode == Mode::VisualBlock {
+                        self.view.start_block_selection();
+                    } else {
+                        self.view.start_selection();
+                    }
+                    vim.set_mode(mode);
+                }
+            }
+            Action::Command(command) => self.process_command(command),
+            Action::RecordMacro(register) => self.start_recording(register),
+            Action::StopRecording => self.stop_recording(Edit(Insert('q'))),
+            Action::PlayMacro(register) => {
+                let result = if vim.mode().is_visual() {
+                    self.apply_macro_to_selection(register)
+                } else {
+                    self.play_macro(register, count.unwrap_or(1))
+                };
+                if let Err(err) = result {
+                    self.update_message(&format!("ERR: {err}"));
+                }
+            }
+            Action::Repeat => {}
+        }
+    }
+    fn operate(&mut self, operator: Operator, range: view::TextRange) {
+        let Some(vim) = &mut self.vim else {
+            return;
+        };
+        if operator == Operator::Format {
+            self.view.reflow(range.lines());
+            return;
+        }
+        vim.register = self.view.text_in_range(range);
+        match operator {
+            Operator::Format => {}
+            Operator::Delete => self.view.delete_range(range),
+            Operator::Change => {
+                self.view.change_range(range);
+                vim.set_mode(Mode::Insert);
+            }
+            Operator::Yank => self.view.move_to_range_start(range),
+        }
+    }
+    /// Replays the last change, with `count` in place of its own if given.
+    fn repeat_change(&mut self, count: Option<usize>) {
+        let Some(change) = self.vim.as_ref().and_then(Vim::last_change).cloned() else {
+            return;
+        };
+        let count = count.or(change.count);
+        self.process_vim_action(change.action, count);
+        if self.vim.as_ref().is_some_and(|vim| vim.mode() == Mode::Insert) {
+            for edit in change.inserted {
+                self.process_command_no_prompt(Edit(edit));
+            }
+            self.leave_vim_mode();
+        }
+    }
+    /// Keys such as Enter and Backspace still do something sensible outside
+    /// of insert mode.
+    fn process_vim_edit_key(&mut self, edit: command::Edit) {
+        let action = match edit {
+            InsertNewLine => Action::Move(Motion::Down),
+            DeleteBackward => Action::Move(Motion::Left),
+            Delete => Action::Operate(Operator::Delete, Motion::Right),
+            Insert(_) => return,
+        };
+        if let Some(vim) = &mut self.vim {
+            vim.reset_pending();
+        }
+        self.process_vim_action(action, None);
+    }
+    /// Esc: back to normal mode from insert or visual mode, or drop a half
+    /// typed command.
+    fn leave_vim_mode(&mut self) {
+        let Some(vim) = &mut self.vim else {
+            return;
+        };
+        match vim.mode() {
+            Mode::Insert => {
+                for edit in vim.finish_insert() {
+                    self.view.handle_edit_command(edit);
+                }
+                self.view.step_left_in_line();
+                self.view.clear_carets();
+            }
+            Mode::Visual | Mode::VisualBlock => {
+                self.view.clear_selection();
+                vim.set_mode(Mode::Normal);
+            }
+            Mode::Normal => {
+                vim.reset_pending();
+                self.view.clear_carets();
+            }
+        }
+        self.view.clamp_caret_to_text();
     }
     // end region
-    // region prompt handling
-    fn in_prompt(&self) -> bool {
-        !self.prompt_type.is_none()
-    }
-    fn set_prompt(&mut self, prompt_type: PromptType) {
-        match prompt_type {
-            PromptType::None => self.message_bar.set_needs_redraw(true),
-            PromptType::Save => self.command_bar.set_prompt("Save as: "),
-            PromptType::Search => {
-                self.view.enter_search();
-                self.command_bar.set_prompt("Search (Esc to cancel, Arrows to navigate): ");
-            },
+    // region opening files
+    /// Where a path the file finder or grep shows is, relative to the
+    /// current directory.
+    fn in_root_dir(&self, file_name: &str) -> PathBuf {
+        self.root_dir
+            .as_ref()
+            .map_or_else(|| PathBuf::from(file_name), |dir| dir.join(file_name))
+    }
+    /// Opens the file, and goes to a line if given, asking first if that
+    /// would lose unsaved changes.
+    fn request_open(&mut self, file_name: String, line_idx: Option<usize>) {
+        if self.view.get_status().is_modified && !Path::new(&file_name).is_dir() {
+            self.pending = Some(PendingAction::Open(file_name, line_idx));
+            self.set_prompt(PromptType::Confirm);
+        } else {
+            self.open_and_report(&file_name, line_idx);
+        }
+    }
+    fn open_and_report(&mut self, file_name: &str, line_idx: Option<usize>) {
+        match self.open_file(file_name) {
+            Ok(()) => {
+                if let Some(line_idx) = line_idx {
+                    self.view.goto_line(line_idx);
+                }
+                self.update_message(&self.opened_message(file_name));
+            }
+            Err(err) => self.update_message(&format!("ERR: {err}")),
         }
-        self.command_bar.clear_prompt();
-        self.prompt_type = prompt_type;
+    }
+    // end region
+    // region directory browser
+    fn browse(&mut self, dir: &Path) -> Result<(), String> {
+        let mut browser = DirBrowser::open(dir)?;
+        browser.resize(Size {
+            height: self.terminal_size.height.saturating_sub(2),
+            width: self.terminal_size.width,
+        });
+        self.listing = Some(Listing::Directory(browser));
+        Ok(())
+    }
+    /// Browses the directory of the current file, or the current directory.
+    fn browse_current_dir(&mut self) {
+        let dir = self
+            .view
+            .file_name()
+            .and_then(|file_name| Path::new(&file_name).parent().map(Path::to_path_buf))
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or_else(|| PathBuf::from("."));
+        if let Err(err) = self.browse(&dir) {
+            self.update_message(&format!("ERR: {err}"));
+        }
+    }
+    /// Goes back to the text from the directory browser or the grep results.
+    fn close_listing(&mut self) {
+        if self.listing.take().is_some() {
+            self.view.set_needs_redraw(true);
+        }
+    }
+    /// Handles the commands that mean something else in the browser. Returns
+    /// false for the ones that work as usual, such as quitting.
+    fn process_command_in_browser(&mut self, command: Command) -> bool {
+        let Some(Listing::Directory(browser)) = &mut self.listing else {
+            return false;
+        };
+        match command {
+            Move(Left) | Edit(DeleteBackward | Insert('-' | 'h')) => self.browse_parent(),
+            Move(Right) | Edit(InsertNewLine | Insert('l')) => self.open_selected_entry(),
+            Edit(Insert('j')) => browser.handle_move_command(Down),
+            Edit(Insert('k')) => browser.handle_move_command(Up),
+            Move(move_command) => browser.handle_move_command(move_command),
+            Edit(Insert('s')) => {
+                let sort_order = browser.cycle_sort_order();
+                self.update_message(&format!("Sorted by {}", sort_order.name()));
+            }
+            Edit(Insert('%' | 'c')) => self.set_prompt(PromptType::NewEntry),
+            Edit(Insert('r' | 'R')) => {
+                if let Some(name) = self.selected_entry_name() {
+                    self.set_prompt(PromptType::Rename);
+                    self.command_bar.set_value(&name);
+                }
+            }
+            Edit(Delete | Insert('d' | 'D')) => {
+                if let Some(name) = self.selected_entry_name() {
+                    let path = self.browser_path(&name);
+                    self.pending = Some(PendingAction::Delete(path));
+                    self.set_prompt(PromptType::Confirm);
+                }
+            }
+            System(Dismiss) => self.close_listing(),
+            System(Save | Search) => {}
+            System(_) | Prompt(_) => return false,
+            // The listing is read-only.
+            _ => {}
+        }
+        true
+    }
+    fn dir_browser(&self) -> Option<&DirBrowser> {
+        match &self.listing {
+            Some(Listing::Directory(browser)) => Some(browser),
+            Some(Listing::Grep(_)) | None => None,
+        }
+    }
+    /// The name of the selected entry, unless it is `..`.
+    fn selected_entry_name(&self) -> Option<String> {
+        self.dir_browser()
+            .and_then(DirBrowser::selected_entry)
+            .map(|entry| entry.name.clone())
+            .filter(|name| name != "..")
+    }
+    fn browser_path(&self, name: &str) -> PathBuf {
+        self.dir_browser()
+            .map_or_else(|| PathBuf::from(name), |browser| browser.dir().join(name))
+    }
+    fn browse_parent(&mut self) {
+        let Some(Listing::Directory(browser)) = &mut self.listing else {
+            return;
+        };
+        if let Some(parent) = browser.dir().parent().map(Path::to_path_buf)
+            && let Err(err) = browser.change_dir(&parent)
+        {
+            self.update_message(&format!("ERR: {err}"));
+        }
+    }
+    fn open_selected_entry(&mut self) {
+        let Some(Listing::Directory(browser)) = &mut self.listing else {
+            return;
+        };
+        let Some((path, is_dir)) = browser
+            .selected_entry()
+            .map(|entry| (entry.path.clone(), entry.is_dir))
+        else {
+            return;
+        };
+        if is_dir {
+            if let Err(err) = browser.change_dir(&path) {
+                self.update_message(&format!("ERR: {err}"));
+            }
+        } else {
+            self.request_open(DirBrowser::display_path(&path), None);
+        }
+    }
+    /// Creates an empty file, or a directory if `name` ends with a slash.
+    fn create_entry(&mut self, name: &str) -> Result<(), String> {
+        let trimmed = name.trim_end_matches('/');
+        if trimmed.is_empty() {
+            return Err(String::from("No name given"));
+        }
+        let path = self.browser_path(trimmed);
+        let result = if name.ends_with('/') {
+            create_dir_all(&path)
+        } else {
+            File::create_new(&path).map(|_| ())
+        };
+        result.map_err(|err| format!("Could not create {trimmed}: {err}"))?;
+        self.refresh_browser(trimmed)?;
+        self.update_message(&format!("Created {trimmed}"));
+        Ok(())
+    }
+    fn rename_selected_entry(&mut self, new_name: &str) -> Result<(), String> {
+        let Some(old_name) = self.selected_entry_name() else {
+            return Ok(());
+        };
+        if new_name.is_empty() || new_name == old_name {
+            return Ok(());
+        }
+        let new_path = self.browser_path(new_name);
+        if new_path.exists() {
+            return Err(format!("{new_name} already exists"));
+        }
+        rename(self.browser_path(&old_name), &new_path)
+            .map_err(|err| format!("Could not rename {old_name}: {err}"))?;
+        self.refresh_browser(new_name)?;
+        self.update_message(&format!("Renamed {old_name} to {new_name}"));
+        Ok(())
+    }
+    /// Deletes a file, or a directory if it is empty.
+    fn delete_entry(&mut self, path: &Path) {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let result = if path.is_dir() { remove_dir(path) } else { remove_file(path) };
+        let result = result
+            .map_err(|err| format!("Could not delete {name}: {err}"))
+            .and_then(|()| self.refresh_browser(""));
+        match result {
+            Ok(()) => self.update_message(&format!("Deleted {name}")),
+            Err(err) => self.update_message(&format!("ERR: {err}")),
+        }
+    }
+    /// Lists the directory again and selects `name`, if given.
+    fn refresh_browser(&mut self, name: &str) -> Result<(), String> {
+        let Some(Listing::Directory(browser)) = &mut self.listing else {
+            return Ok(());
+        };
+        browser.refresh()?;
+        if !name.is_empty() {
+            browser.select_name(name);
+        }
+        Ok(())
+    }
+    // end region
+    // region project grep
+    /// Whether grep is still looking for matches.
+    pub fn is_searching(&self) -> bool {
+        matches!(&self.listing, Some(Listing::Grep(results)) if results.is_running())
+    }
+    /// Searches the files below the root directory. The results show in
+    /// place of the text as they come in.
+    fn grep(&mut self, pattern: &str) {
+        match GrepResults::start(pattern, self.root_dir.clone().unwrap_or_else(|| PathBuf::from("."))) {
+            Ok(mut results) => {
+                results.resize(Size {
+                    height: self.terminal_size.height.saturating_sub(2),
+                    width: self.terminal_size.width,
+                });
+                self.listing = Some(Listing::Grep(results));
+            }
+            Err(err) => self.update_message(&format!("ERR: {err}")),
+        }
+    }
+    /// Handles the commands that mean something else in the results. Returns
+    /// false for the ones that work as usual, such as quitting.
+    fn process_command_in_grep(&mut self, command: Command) -> bool {
+        let Some(Listing::Grep(results)) = &mut self.listing else {
+            return false;
+        };
+        match command {
+            Move(Right) | Edit(InsertNewLine | Insert('l')) => self.open_selected_match(),
+            Edit(Insert('j')) => results.handle_move_command(Down),
+            Edit(Insert('k')) => results.handle_move_command(Up),
+            Move(move_command) => results.handle_move_command(move_command),
+            System(Dismiss) => self.close_listing(),
+            System(Save | Search) => {}
+            System(_) | Prompt(_) => return false,
+            // The results are read-only.
+            _ => {}
+        }
+        true
+    }
+    fn open_selected_match(&mut self) {
+        let Some(Listing::Grep(results)) = &self.listing else {
+            return;
+        };
+        if let Some((path, line_idx)) = results
+            .selected_match()
+            .map(|grep_match| (self.in_root_dir(&grep_match.path), grep_match.line_idx))
+        {
+            self.request_open(path.to_string_lossy().into_owned(), Some(line_idx));
+        }
+    }
+    // end region
+    // region config handling
+    fn reload_config(&mut self) {
+        match Config::load(self.config_dir.as_deref()).and_then(|config| self.apply_config(config)) {
+            Ok(()) => self.update_message("Configuration reloaded."),
+            Err(err) => self.update_message(&format!("ERR: {err}")),
+        }
+    }
+    /// Takes over the config. An unknown theme is reported, but everything
+    /// else in the config still applies.
+    fn apply_config(&mut self, config: Config) -> Result<(), String> {
+        self.message_bar.set_duration(config.message_duration);
+        let theme_result = match &config.theme {
+            Some(name) if !self.themes.select(name) => Err(format!("Unknown theme '{name}'")),
+            _ => Ok(()),
+        };
+        self.config = config;
+        self.apply_vim_mode();
+        self.apply_mouse_capture();
+        self.apply_buffer_settings();
+        self.schedule_autosave();
+        self.redraw_all();
+        theme_result
+    }
+    /// Switches Vim mode on or off to match the config.
+    fn apply_vim_mode(&mut self) {
+        if self.config.vim_mode == self.vim.is_some() {
+            return;
+        }
+        self.vim = self.config.vim_mode.then(Vim::default);
+        self.view.clear_selection();
+        if self.vim.is_some() {
+            self.view.clamp_caret_to_text();
+        }
+    }
+    fn apply_mouse_capture(&mut self) {
+        let _ = self.terminal.set_mouse_capture(self.config.mouse);
+    }
+    fn apply_buffer_settings(&mut self) {
+        let file_name = self.view.file_name();
+        let settings = self.config.buffer_settings(self.view.file_type(), file_name.as_deref().map(Path::new));
+        self.view.apply_settings(settings);
+    }
+    // end region
+    // region theme handling
+    fn cycle_theme(&mut self) {
+        self.themes.select_next();
+        let message = format!("Theme: {}", self.themes.current().name());
+        self.redraw_all();
+        self.update_message(&message);
+    }
+    fn redraw_all(&mut self) {
+        self.view.set_needs_redraw(true);
+        if let Some(listing) = &mut self.listing {
+            listing.set_needs_redraw(true);
+        }
+        self.status_bar.set_needs_redraw(true);
+        self.message_bar.set_needs_redraw(true);
+        self.command_bar.set_needs_redraw(true);
+    }
+    // end region
+    // region message & command bar
+    fn update_message(&mut self, new_message: &str) {
+        self.message_bar.update_message(new_message);
     }
     // end region
 }
     
 impl Drop for Editor {
     fn drop(&mut self) {
-        let _ = Terminal::terminate();
+        let _ = self.terminal.terminate();
         if self.should_quit {
-            let _ = Terminal::print("Goodbye.\r\n");
+            let _ = self.terminal.print("Goodbye.\r\n");
+            let _ = self.terminal.execute();
+            if self.arguments.write_stdout {
+                let _ = self.view.write_to(&mut stdout().lock());
+            }
         }
     }
 }
+
@@ -1,9 +1,13 @@
 
-#[derive(Default, Clone, Copy, Debug)]
+#[derive(Default, Clone, Copy, Eq, PartialEq, Debug)]
 pub enum AnnotationType {
     #[default]
     None,
     Highlight,
+    Selection,
+    Caret,
+    MatchingBracket,
+    TrailingWhitespace,
 }
 
 #[derive(Default, Clone)]
@@ -22,6 +26,7 @@ impl AnnotatedFragment {
 }
 
 #[derive(Default, Clone, Debug)]
+#[allow(clippy::struct_field_names)]
 pub struct Annotation {
     pub start_byte_idx: usize,
     pub end_byte_idx: usize,
@@ -45,13 +50,6 @@ pub struct AnnotatedString {
 }
 
 impl AnnotatedString {
-    pub fn new(string: &str) -> Self {
-        AnnotatedString {
-            string: String::from(string),
-            annotations: vec![],
-        }
-    }
-
     pub fn get_annotated_fragments(&self) -> Vec<AnnotatedFragment> {
         let mut annotated_fragments = vec![];
 
@@ -78,8 +76,21 @@ impl AnnotatedString {
 
         self.annotations.push(annotation);
     }
-    pub fn get_display_string(&self) -> &str {
-        &self.string
-    }
 
+    /// Appends `text`, extending the last annotation if it has the same type.
+    pub fn push(&mut self, text: &str, annotation_type: AnnotationType) {
+        if text.is_empty() {
+            return;
+        }
+        let start_byte_idx = self.string.len();
+        self.string.push_str(text);
+        let end_byte_idx = self.string.len().saturating_sub(1);
+
+        match self.annotations.last_mut() {
+            Some(last) if last.annotation_type == annotation_type => {
+                last.end_byte_idx = end_byte_idx;
+            }
+            _ => self.add_annotation(Annotation::new(start_byte_idx, end_byte_idx, annotation_type)),
+        }
+    }
 }
@@ -0,0 +1,36 @@
+use std::{
+    env,
+    io::{stdin, IsTerminal},
+};
+
+const STDIN_FILE_NAME: &str = "-";
+const STDOUT_FLAG: &str = "--stdout";
+
+#[derive(Default)]
+pub struct Arguments {
+    pub file_name: Option<String>,
+    pub read_stdin: bool,
+    pub write_stdout: bool,
+}
+
+impl Arguments {
+    pub fn parse() -> Self {
+        let mut arguments = Self::default();
+
+        for arg in env::args().skip(1) {
+            match arg.as_str() {
+                STDOUT_FLAG => arguments.write_stdout = true,
+                STDIN_FILE_NAME => arguments.read_stdin = true,
+                _ if arguments.file_name.is_none() => arguments.file_name = Some(arg),
+                _ => {},
+            }
+        }
+
+        // `cmd | hecto` without any file name reads the pipe as well.
+        if arguments.file_name.is_none() && !stdin().is_terminal() {
+            arguments.read_stdin = true;
+        }
+
+        arguments
+    }
+}
@@ -0,0 +1,195 @@
+use std::env;
+
+use crossterm::style::Color;
+use terminfo::{capability::MaxColors, Database};
+
+/// The 16 standard colors in palette order, with their usual xterm values.
+const ANSI_COLORS: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (205, 0, 0)),
+    (Color::DarkGreen, (0, 205, 0)),
+    (Color::DarkYellow, (205, 205, 0)),
+    (Color::DarkBlue, (0, 0, 238)),
+    (Color::DarkMagenta, (205, 0, 205)),
+    (Color::DarkCyan, (0, 205, 205)),
+    (Color::Grey, (229, 229, 229)),
+    (Color::DarkGrey, (127, 127, 127)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (92, 92, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+/// The channel values of the 6x6x6 color cube in the 256 color palette.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+const CUBE_START: u8 = 16;
+const GRAYSCALE_START: u8 = 232;
+
+#[derive(Default, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub enum ColorDepth {
+    None,
+    Ansi16,
+    Ansi256,
+    #[default]
+    TrueColor,
+}
+
+impl ColorDepth {
+    /// Works out what the terminal supports from `NO_COLOR`, `COLORTERM`,
+    /// `TERM` and finally the terminfo entry for `TERM`.
+    pub fn detect() -> Self {
+        if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+            return Self::None;
+        }
+        if env::var("COLORTERM").is_ok_and(|value| value == "truecolor" || value == "24bit") {
+            return Self::TrueColor;
+        }
+
+        let term = env::var("TERM").unwrap_or_default();
+        if term.is_empty() || term == "dumb" {
+            return Self::None;
+        }
+        if term.ends_with("-direct") {
+            return Self::TrueColor;
+        }
+        if term.contains("256color") {
+            return Self::Ansi256;
+        }
+        if term == "linux" {
+            return Self::Ansi16;
+        }
+
+        Database::from_env()
+            .ok()
+            .and_then(|database| database.get::<MaxColors>())
+            .map_or(Self::Ansi16, |MaxColors(colors)| Self::from_max_colors(colors))
+    }
+    /// The depth for the number of colors terminfo says the terminal has.
+    const fn from_max_colors(colors: i32) -> Self {
+        match colors {
+            ..=7 => Self::None,
+            8..=255 => Self::Ansi16,
+            256..=0x00FF_FFFF => Self::Ansi256,
+            _ => Self::TrueColor,
+        }
+    }
+    /// Maps a color onto the closest one this depth can show, or `None` if
+    /// colors should not be used at all.
+    pub fn adapt(self, color: Color) -> Option<Color> {
+        match self {
+            Self::None => None,
+            Self::TrueColor => Some(color),
+            Self::Ansi256 => Some(match color {
+                Color::Rgb { r, g, b } => Color::AnsiValue(rgb_to_ansi256(r, g, b)),
+                _ => color,
+            }),
+            Self::Ansi16 => Some(
+                to_rgb(color).map_or(color, |(r, g, b)| nearest_ansi16(r, g, b)),
+            ),
+        }
+    }
+}
+
+/// The RGB value of colors outside the 16 standard ones.
+fn to_rgb(color: Color) -> Option<(u8, u8, u8)> {
+    match color {
+        Color::Rgb { r, g, b } => Some((r, g, b)),
+        Color::AnsiValue(value) => Some(ansi256_to_rgb(value)),
+        _ => None,
+    }
+}
+
+#[allow(clippy::arithmetic_side_effects, clippy::integer_division)]
+fn ansi256_to_rgb(value: u8) -> (u8, u8, u8) {
+    if let Some((_, rgb)) = ANSI_COLORS.get(usize::from(value)) {
+        return *rgb;
+    }
+    if value >= GRAYSCALE_START {
+        let level = 8 + (value - GRAYSCALE_START) * 10;
+        return (level, level, level);
+    }
+    let idx = value - CUBE_START;
+    let level = |idx: u8| CUBE_LEVELS.get(usize::from(idx)).copied().unwrap_or_default();
+    (level(idx / 36), level(idx / 6 % 6), level(idx % 6))
+}
+
+#[allow(clippy::arithmetic_side_effects, clippy::integer_division)]
+fn rgb_to_ansi256(r: u8, g: u8, b: u8) -> u8 {
+    let cube_idx = |channel: u8| -> u8 {
+        match channel {
+            0..48 => 0,
+            48..115 => 1,
+            _ => (channel - 35) / 40,
+        }
+    };
+    let cube = CUBE_START + 36 * cube_idx(r) + 6 * cube_idx(g) + cube_idx(b);
+
+    let average = (u16::from(r) + u16::from(g) + u16::from(b)) / 3;
+    let gray = if average > 238 {
+        GRAYSCALE_START + 23
+    } else {
+        GRAYSCALE_START + u8::try_from(average.saturating_sub(3) / 10).unwrap_or_default()
+    };
+
+    if distance((r, g, b), ansi256_to_rgb(gray)) < distance((r, g, b), ansi256_to_rgb(cube)) {
+        gray
+    } else {
+        cube
+    }
+}
+
+fn nearest_ansi16(r: u8, g: u8, b: u8) -> Color {
+    ANSI_COLORS
+        .iter()
+        .min_by_key(|(_, rgb)| distance((r, g, b), *rgb))
+        .map_or(Color::White, |(color, _)| *color)
+}
+
+#[allow(clippy::arithmetic_side_effects)]
+fn distance(from: (u8, u8, u8), to: (u8, u8, u8)) -> u32 {
+    let channel = |a: u8, b: u8| u32::from(a.abs_diff(b)).pow(2);
+    channel(from.0, to.0) + channel(from.1, to.1) + channel(from.2, to.2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn max_colors_map_to_depths_at_the_thresholds() {
+        assert_eq!(ColorDepth::from_max_colors(-1), ColorDepth::None);
+        assert_eq!(ColorDepth::from_max_colors(7), ColorDepth::None);
+        assert_eq!(ColorDepth::from_max_colors(8), ColorDepth::Ansi16);
+        assert_eq!(ColorDepth::from_max_colors(255), ColorDepth::Ansi16);
+        assert_eq!(ColorDepth::from_max_colors(256), ColorDepth::Ansi256);
+        assert_eq!(ColorDepth::from_max_colors(0x00FF_FFFF), ColorDepth::Ansi256);
+        assert_eq!(ColorDepth::from_max_colors(0x0100_0000), ColorDepth::TrueColor);
+    }
+
+    #[test]
+    fn rgb_maps_to_the_cube_or_the_grayscale_ramp() {
+        assert_eq!(rgb_to_ansi256(0, 0, 0), CUBE_START);
+        assert_eq!(rgb_to_ansi256(255, 255, 255), 231);
+        assert_eq!(rgb_to_ansi256(255, 0, 0), 196);
+        assert_eq!(rgb_to_ansi256(95, 135, 175), 67);
+        assert_eq!(rgb_to_ansi256(128, 128, 128), 244);
+        assert_eq!(rgb_to_ansi256(8, 8, 8), GRAYSCALE_START);
+        for value in CUBE_START..=u8::MAX {
+            let (r, g, b) = ansi256_to_rgb(value);
+            assert_eq!(ansi256_to_rgb(rgb_to_ansi256(r, g, b)), (r, g, b), "{value}");
+        }
+    }
+
+    #[test]
+    fn nearest_ansi16_picks_the_closest_standard_color() {
+        assert_eq!(nearest_ansi16(250, 10, 10), Color::Red);
+        assert_eq!(nearest_ansi16(200, 0, 0), Color::DarkRed);
+        assert_eq!(nearest_ansi16(120, 120, 130), Color::DarkGrey);
+        assert_eq!(nearest_ansi16(1, 2, 3), Color::Black);
+        assert_eq!(ColorDepth::Ansi16.adapt(Color::Rgb { r: 0, g: 250, b: 250 }), Some(Color::Cyan));
+        assert_eq!(ColorDepth::Ansi16.adapt(Color::Blue), Some(Color::Blue));
+        assert_eq!(ColorDepth::None.adapt(Color::Blue), None);
+    }
+}
@@ -1,15 +1,6 @@
-use crossterm::event::{
-    Event,
-    KeyCode::{
-        self, Backspace, Char, Delete, Down, End, Enter, Home, Left, PageDown, PageUp, Right, Tab, Up
-    },
-    KeyEvent, KeyModifiers
-};
-use std::convert::TryFrom;
-
 use super::Size;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Eq, PartialEq)]
 pub enum Move {
     PageUp,
     PageDown,
@@ -19,36 +10,11 @@ pub enum Move {
     Left,
     Right,
     Down,
-}
-impl TryFrom<KeyEvent> for Move {
-    type Error = String;
-
-    fn try_from(event: KeyEvent) -> Result<Self, Self::Error> {
-        let KeyEvent {
-            code, modifiers, ..
-        } = event;
-
-        if modifiers == KeyModifiers::NONE {
-            match code {
-                Up => Ok(Self::Up),
-                Down => Ok(Self::Down),
-                Left => Ok(Self::Left),
-                Right => Ok(Self::Right),
-                PageDown => Ok(Self::PageDown),
-                PageUp => Ok(Self::PageUp),
-                Home => Ok(Self::StartOfLine),
-                End => Ok(Self::EndOfLine),
-                _ => Err(format!("Unsupported code: {code:?}")),
-            }
-        } else {
-            Err(format!(
-                "Unsupported key code {code:?} or modifier {modifiers:?}"
-            ))
-        }
-    }
+    /// To the other bracket of the pair at the caret.
+    MatchingBracket,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Eq, PartialEq)]
 pub enum Edit {
     Insert(char),
     InsertNewLine,
@@ -56,94 +22,234 @@ pub enum Edit {
     DeleteBackward,
 }
 
-impl TryFrom<KeyEvent> for Edit {
-    type Error = String;
-
-    fn try_from(event: KeyEvent) -> Result<Self, Self::Error> {
-        match (event.code, event.modifiers) {
-            (Char(character), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
-                Ok(Self::Insert(character))
-            },
-            (Tab, KeyModifiers::NONE) => Ok(Self::Insert('\t')),
-            (Enter, KeyModifiers::NONE) => Ok(Self::InsertNewLine),
-            (Backspace, KeyModifiers::NONE) => Ok(Self::DeleteBackward),
-            (Delete, KeyModifiers::NONE) => Ok(Self::Delete),
-            _ => Err(format!(
-                "Unsupported key code {:?} with modifiers {:?}",
-                event.code, event.modifiers
-            )),
-        }
-    }
-}
-
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Eq, PartialEq)]
 pub enum System {
     Save,
     Resize(Size),
     Quit,
     Dismiss,
     Search,
+    CycleTheme,
+    ReloadConfig,
+    CommandLine,
+    CommandPalette,
+    OpenFile,
+    Browse,
+    Grep,
 }
 
-impl TryFrom<KeyEvent> for System {
-    type Error = String;
-
-    fn try_from(event: KeyEvent) -> Result<Self, Self::Error> {
-        let KeyEvent {
-            code, modifiers, ..
-        } = event;
-
-        if modifiers == KeyModifiers::CONTROL {
-            match code {
-                Char('q') => Ok(Self::Quit),
-                Char('f') => Ok(Self::Search),
-                Char('s') => Ok(Self::Save),
-                _ => Err(format!("Unsupported Ctrl+{code:?} combination")),
-            }
-        } else if modifiers == KeyModifiers::NONE && matches!(code, KeyCode::Esc) {
-            Ok(Self::Dismiss)
-        } else {
-            Err(format!(
-                "Unsupported key code {code:?} or modifiers {modifiers:?}"
-            ))
-        }
-    }
+/// Where to add carets, so that edits apply in several places at once.
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum Caret {
+    Above,
+    Below,
+    /// At the next match of the selection, or of the word at the caret.
+    AtNextMatch,
+    AtAllMatches,
 }
 
-#[derive(Clone, Copy)]
-pub enum Command {
-    Move(Move),
-    Edit(Edit),
-    System(System),
+/// Recording and playing back keyboard macros.
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum Macro {
+    /// Starts recording, or stops if recording already.
+    Record,
+    Play,
+    /// Plays a macro once on every line of the selection.
+    ApplyToSelection,
+    /// Writes the macros to the config directory.
+    Save,
 }
 
-#[allow(clippy::as_conversion)]
-impl TryFrom<Event> for Command {
-    type Error = String;
-
-    fn try_from(event: Event) -> Result<Self, Self::Error> {
-        match event {
-            Event::Key(key_event) => Edit::try_from(key_event)
-                .map(Command::Edit)
-                .or_else(|_| Move::try_from(key_event).map(Command::Move))
-                .or_else(|_| System::try_from(key_event).map(Command::System))
-                .map_err(|_err| format!("Event not supported: {key_event:?}")),
-            Event::Resize(width_u16, height_u16) => Ok(Self::System(System::Resize(Size {
-                height: height_u16 as usize,
-                width: width_u16 as usize,
-            }))),
-            _ => Err(format!("Event not supported: {event:?}")),
-        }
-    }
+/// Changes to whole lines: the caret's line, or every line the selection
+/// touches.
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum Lines {
+    /// Comments the lines out, or back in when all of them are comments.
+    ToggleComment,
+    MoveUp,
+    MoveDown,
+    Duplicate,
+    /// Joins the lines, or the caret's line with the next, into one.
+    Join,
+    Delete,
+    /// Sorts the selected lines. Without a selection it fails, as there is
+    /// nothing to sort.
+    Sort(SortOptions),
+    /// Rewraps the selected lines, or the caret's paragraph, to the text
+    /// width.
+    Reflow,
 }
 
+/// Going back and forth through the changes to the text.
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum History {
+    /// Takes back the last change: everything one key did, at every caret
+    /// and on every line, or a run of typed characters.
+    Undo,
+    Redo,
+}
 
+/// Selecting a block of columns, and copying it around as a rectangle.
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum Block {
+    /// Starts selecting a block at the caret, which moving the caret then
+    /// extends, or turns the selection into one. Ends it if there is one.
+    ToggleSelection,
+    Copy,
+    Cut,
+    /// Puts the block copied or cut last into the lines from the caret down.
+    Paste,
+}
 
+/// How to compare lines when sorting them.
+#[derive(Clone, Copy, Default, Eq, PartialEq, Debug)]
+pub enum SortKey {
+    #[default]
+    Lexical,
+    IgnoreCase,
+    /// By the first number in each line. Lines without one come first.
+    Numeric,
+}
 
+#[derive(Clone, Copy, Default, Eq, PartialEq, Debug)]
+pub struct SortOptions {
+    pub key: SortKey,
+    /// Keeps only the first of lines that compare equal.
+    pub unique: bool,
+    pub reverse: bool,
+}
 
+/// Commands that only mean something while a prompt is open.
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum Prompt {
+    Accept,
+    Next,
+    Previous,
+    Complete,
+}
 
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum Command {
+    Move(Move),
+    Edit(Edit),
+    System(System),
+    Caret(Caret),
+    Macro(Macro),
+    Lines(Lines),
+    Block(Block),
+    History(History),
+    Prompt(Prompt),
+}
 
-
-
-
-
+/// Every command that can be bound to a key, under the name used in the
+/// config file.
+const NAMED_COMMANDS: [(&str, Command); 54] = [
+    ("move_up", Command::Move(Move::Up)),
+    ("move_down", Command::Move(Move::Down)),
+    ("move_left", Command::Move(Move::Left)),
+    ("move_right", Command::Move(Move::Right)),
+    ("page_up", Command::Move(Move::PageUp)),
+    ("page_down", Command::Move(Move::PageDown)),
+    ("start_of_line", Command::Move(Move::StartOfLine)),
+    ("end_of_line", Command::Move(Move::EndOfLine)),
+    ("matching_bracket", Command::Move(Move::MatchingBracket)),
+    ("insert_newline", Command::Edit(Edit::InsertNewLine)),
+    ("insert_tab", Command::Edit(Edit::Insert('\t'))),
+    ("delete", Command::Edit(Edit::Delete)),
+    ("delete_backward", Command::Edit(Edit::DeleteBackward)),
+    ("undo", Command::History(History::Undo)),
+    ("redo", Command::History(History::Redo)),
+    ("save", Command::System(System::Save)),
+    ("quit", Command::System(System::Quit)),
+    ("dismiss", Command::System(System::Dismiss)),
+    ("search", Command::System(System::Search)),
+    ("cycle_theme", Command::System(System::CycleTheme)),
+    ("reload_config", Command::System(System::ReloadConfig)),
+    ("command_line", Command::System(System::CommandLine)),
+    ("command_palette", Command::System(System::CommandPalette)),
+    ("open_file", Command::System(System::OpenFile)),
+    ("browse", Command::System(System::Browse)),
+    ("grep", Command::System(System::Grep)),
+    ("add_caret_above", Command::Caret(Caret::Above)),
+    ("add_caret_below", Command::Caret(Caret::Below)),
+    ("add_caret_at_next_match", Command::Caret(Caret::AtNextMatch)),
+    ("add_carets_at_all_matches", Command::Caret(Caret::AtAllMatches)),
+    ("record_macro", Command::Macro(Macro::Record)),
+    ("play_macro", Command::Macro(Macro::Play)),
+    ("apply_macro_to_selection", Command::Macro(Macro::ApplyToSelection)),
+    ("save_macros", Command::Macro(Macro::Save)),
+    ("toggle_comment", Command::Lines(Lines::ToggleComment)),
+    ("move_lines_up", Command::Lines(Lines::MoveUp)),
+    ("move_lines_down", Command::Lines(Lines::MoveDown)),
+    ("duplicate_lines", Command::Lines(Lines::Duplicate)),
+    ("join_lines", Command::Lines(Lines::Join)),
+    ("delete_lines", Command::Lines(Lines::Delete)),
+    ("reflow", Command::Lines(Lines::Reflow)),
+    (
+        "sort_lines",
+        Command::Lines(Lines::Sort(SortOptions {
+            key: SortKey::Lexical,
+            unique: false,
+            reverse: false,
+        })),
+    ),
+    (
+        "sort_lines_numeric",
+        Command::Lines(Lines::Sort(SortOptions {
+            key: SortKey::Numeric,
+            unique: false,
+            reverse: false,
+        })),
+    ),
+    (
+        "sort_lines_ignore_case",
+        Command::Lines(Lines::Sort(SortOptions {
+            key: SortKey::IgnoreCase,
+            unique: false,
+            reverse: false,
+        })),
+    ),
+    (
+        "sort_lines_unique",
+        Command::Lines(Lines::Sort(SortOptions {
+            key: SortKey::Lexical,
+            unique: true,
+            reverse: false,
+        })),
+    ),
+    (
+        "sort_lines_reverse",
+        Command::Lines(Lines::Sort(SortOptions {
+            key: SortKey::Lexical,
+            unique: false,
+            reverse: true,
+        })),
+    ),
+    ("toggle_block_selection", Command::Block(Block::ToggleSelection)),
+    ("copy_block", Command::Block(Block::Copy)),
+    ("cut_block", Command::Block(Block::Cut)),
+    ("paste_block", Command::Block(Block::Paste)),
+    ("accept", Command::Prompt(Prompt::Accept)),
+    ("next", Command::Prompt(Prompt::Next)),
+    ("previous", Command::Prompt(Prompt::Previous)),
+    ("complete", Command::Prompt(Prompt::Complete)),
+];
+
+impl Command {
+    pub fn from_name(name: &str) -> Option<Self> {
+        NAMED_COMMANDS
+            .iter()
+            .find(|(command_name, _)| *command_name == name)
+            .map(|(_, command)| *command)
+    }
+    /// Every named command, in a stable order.
+    pub fn all_named() -> impl Iterator<Item = (&'static str, Self)> {
+        NAMED_COMMANDS.into_iter()
+    }
+    pub fn name(self) -> Option<&'static str> {
+        NAMED_COMMANDS
+            .iter()
+            .find(|(_, command)| *command == self)
+            .map(|(name, _)| *name)
+    }
+}
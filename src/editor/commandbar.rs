@@ -1,6 +1,6 @@
 use std::{cmp::min, io::Error};
 
-use super::{command::Edit, Line, Size, Terminal, UIComponent};
+use super::{command::Edit, Line, Screen, Size, Theme, ThemeElement, UIComponent};
 
 #[derive(Default)]
 pub struct CommandBar {
@@ -30,6 +30,10 @@ impl CommandBar {
     pub fn value(&self) -> String {
         self.value.to_string()
     }
+    pub fn set_value(&mut self, value: &str) {
+        self.value = Line::from(value);
+        self.set_needs_redraw(true);
+    }
     pub fn set_prompt(&mut self, prompt: &str) {
         self.prompt = prompt.to_string();
         self.set_needs_redraw(true);
@@ -50,7 +54,7 @@ impl UIComponent for CommandBar {
     fn set_size(&mut self, size: Size) {
         self.size = size;
     }
-    fn draw(&mut self, origin: usize) -> Result<(), Error> {
+    fn draw(&mut self, screen: &mut Screen, theme: &Theme, origin: usize) -> Result<(), Error> {
         let area_for_value = self.size.width.saturating_sub(self.prompt.len());
         let value_end = self.value.width();
         let value_start = value_end.saturating_sub(area_for_value);
@@ -67,7 +71,8 @@ impl UIComponent for CommandBar {
             String::new()
         };
 
-        Terminal::print_row(origin, &to_print)
+        screen.print_row(origin, &to_print, theme.style(ThemeElement::CommandBar));
+        Ok(())
     }
     
 }
@@ -0,0 +1,430 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::read_to_string,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+use super::{filetype::FileType, keymap::Keymaps, line::DEFAULT_TAB_WIDTH, paths};
+
+mod editorconfig;
+
+const CONFIG_FILE: &str = "config.toml";
+const MAX_TAB_WIDTH: usize = 16;
+const DEFAULT_TEXT_WIDTH: usize = 79;
+
+/// Settings that apply to a single buffer and can be overridden per file type.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct BufferSettings {
+    pub tab_width: usize,
+    pub expand_tabs: bool,
+    /// Typing an opening bracket or a quote also types its closing partner.
+    pub auto_pairs: bool,
+    /// The width reflowing wraps lines at.
+    pub text_width: usize,
+    /// Typing past `text_width` wraps the line.
+    pub auto_wrap: bool,
+    pub save_hooks: SaveHooks,
+    pub end_of_line: LineEnding,
+    /// The charset to write the file in, instead of the one it was read in.
+    /// Only `.editorconfig` files set it.
+    pub charset: Option<Charset>,
+}
+
+/// The line break written after each line.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+    Cr,
+}
+
+impl LineEnding {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::Crlf => "\r\n",
+            Self::Cr => "\r",
+        }
+    }
+}
+
+/// The encoding of a file. Reading recognizes it by the byte order mark, or
+/// falls back to Latin-1 for text that isn't UTF-8, and saving keeps it.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Deserialize)]
+pub enum Charset {
+    #[default]
+    #[serde(rename = "utf-8")]
+    Utf8,
+    #[serde(rename = "utf-8-bom")]
+    Utf8Bom,
+    #[serde(rename = "latin1")]
+    Latin1,
+    #[serde(rename = "utf-16be")]
+    Utf16Be,
+    #[serde(rename = "utf-16le")]
+    Utf16Le,
+}
+
+/// How the text is tidied up before it is written to the file.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct SaveHooks {
+    pub trim_trailing_whitespace: bool,
+    /// Removes the blank lines at the end of the file.
+    pub trim_trailing_blank_lines: bool,
+    /// Ends the last line with a line break even if the file was loaded
+    /// without one.
+    pub insert_final_newline: bool,
+}
+
+impl Default for SaveHooks {
+    fn default() -> Self {
+        Self {
+            trim_trailing_whitespace: false,
+            trim_trailing_blank_lines: false,
+            insert_final_newline: true,
+        }
+    }
+}
+
+impl Default for BufferSettings {
+    fn default() -> Self {
+        Self {
+            tab_width: DEFAULT_TAB_WIDTH,
+            expand_tabs: false,
+            auto_pairs: false,
+            text_width: DEFAULT_TEXT_WIDTH,
+            auto_wrap: false,
+            save_hooks: SaveHooks::default(),
+            end_of_line: LineEnding::default(),
+            charset: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+struct BufferSettingsOverride {
+    tab_width: Option<usize>,
+    expand_tabs: Option<bool>,
+    auto_pairs: Option<bool>,
+    text_width: Option<usize>,
+    auto_wrap: Option<bool>,
+    trim_trailing_whitespace: Option<bool>,
+    trim_trailing_blank_lines: Option<bool>,
+    insert_final_newline: Option<bool>,
+    end_of_line: Option<LineEnding>,
+    #[serde(skip)]
+    charset: Option<Charset>,
+}
+
+impl BufferSettingsOverride {
+    fn apply_to(&self, settings: &mut BufferSettings) {
+        if let Some(tab_width) = self.tab_width {
+            settings.tab_width = tab_width;
+        }
+        if let Some(expand_tabs) = self.expand_tabs {
+            settings.expand_tabs = expand_tabs;
+        }
+        if let Some(auto_pairs) = self.auto_pairs {
+            settings.auto_pairs = auto_pairs;
+        }
+        if let Some(text_width) = self.text_width {
+            settings.text_width = text_width;
+        }
+        if let Some(auto_wrap) = self.auto_wrap {
+            settings.auto_wrap = auto_wrap;
+        }
+        if let Some(trim) = self.trim_trailing_whitespace {
+            settings.save_hooks.trim_trailing_whitespace = trim;
+        }
+        if let Some(trim) = self.trim_trailing_blank_lines {
+            settings.save_hooks.trim_trailing_blank_lines = trim;
+        }
+        if let Some(insert) = self.insert_final_newline {
+            settings.save_hooks.insert_final_newline = insert;
+        }
+        if let Some(end_of_line) = self.end_of_line {
+            settings.end_of_line = end_of_line;
+        }
+        if let Some(charset) = self.charset {
+            settings.charset = Some(charset);
+        }
+    }
+    fn validate(&self) -> Result<(), String> {
+        if let Some(tab_width) = self.tab_width {
+            validate_tab_width(tab_width)?;
+        }
+        if let Some(text_width) = self.text_width {
+            validate_text_width(text_width)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn validate_tab_width(tab_width: usize) -> Result<(), String> {
+    if (1..=MAX_TAB_WIDTH).contains(&tab_width) {
+        Ok(())
+    } else {
+        Err(format!(
+            "tab_width must be between 1 and {MAX_TAB_WIDTH}, got {tab_width}"
+        ))
+    }
+}
+
+pub fn validate_text_width(text_width: usize) -> Result<(), String> {
+    if text_width > 0 {
+        Ok(())
+    } else {
+        Err(String::from("text_width must be at least 1"))
+    }
+}
+
+/// Key bindings per keymap, from key sequence to command name.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct KeysFile {
+    normal: BTreeMap<String, String>,
+    search: BTreeMap<String, String>,
+    save: BTreeMap<String, String>,
+    command: BTreeMap<String, String>,
+    picker: BTreeMap<String, String>,
+    confirm: BTreeMap<String, String>,
+}
+
+impl KeysFile {
+    fn keymaps(&self) -> Result<Keymaps, String> {
+        let mut keymaps = Keymaps::default();
+        for (name, keymap, bindings) in [
+            ("normal", &mut keymaps.normal, &self.normal),
+            ("search", &mut keymaps.search, &self.search),
+            ("save", &mut keymaps.save, &self.save),
+            ("command", &mut keymaps.command, &self.command),
+            ("picker", &mut keymaps.picker, &self.picker),
+            ("confirm", &mut keymaps.confirm, &self.confirm),
+        ] {
+            keymap
+                .apply(bindings)
+                .map_err(|err| format!("keys.{name}: {err}"))?;
+        }
+        Ok(keymaps)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    quit_times: u8,
+    message_duration: u64,
+    log_file: String,
+    theme: Option<String>,
+    autosave: u64,
+    vim_mode: bool,
+    mouse: bool,
+    tab_width: Option<usize>,
+    expand_tabs: Option<bool>,
+    auto_pairs: Option<bool>,
+    text_width: Option<usize>,
+    auto_wrap: Option<bool>,
+    trim_trailing_whitespace: Option<bool>,
+    trim_trailing_blank_lines: Option<bool>,
+    insert_final_newline: Option<bool>,
+    end_of_line: Option<LineEnding>,
+    file_type: HashMap<String, BufferSettingsOverride>,
+    keys: KeysFile,
+}
+
+impl ConfigFile {
+    /// The top level buffer settings, which apply to every file type.
+    fn buffer_settings(&self) -> BufferSettingsOverride {
+        BufferSettingsOverride {
+            tab_width: self.tab_width,
+            expand_tabs: self.expand_tabs,
+            auto_pairs: self.auto_pairs,
+            text_width: self.text_width,
+            auto_wrap: self.auto_wrap,
+            trim_trailing_whitespace: self.trim_trailing_whitespace,
+            trim_trailing_blank_lines: self.trim_trailing_blank_lines,
+            insert_final_newline: self.insert_final_newline,
+            end_of_line: self.end_of_line,
+            charset: None,
+        }
+    }
+}
+
+impl Default for ConfigFile {
+    fn default() -> Self {
+        Self {
+            quit_times: 3,
+            message_duration: 5,
+            log_file: String::from("debug.log"),
+            theme: None,
+            autosave: 0,
+            vim_mode: false,
+            mouse: true,
+            tab_width: None,
+            expand_tabs: None,
+            auto_pairs: None,
+            text_width: None,
+            auto_wrap: None,
+            trim_trailing_whitespace: None,
+            trim_trailing_blank_lines: None,
+            insert_final_newline: None,
+            end_of_line: None,
+            file_type: HashMap::new(),
+            keys: KeysFile::default(),
+        }
+    }
+}
+
+pub struct Config {
+    pub quit_times: u8,
+    pub message_duration: Duration,
+    pub log_file: Option<PathBuf>,
+    pub theme: Option<String>,
+    pub autosave: Option<Duration>,
+    /// Modal editing with Vim keys instead of the modeless default.
+    pub vim_mode: bool,
+    /// Clicks, drags and the wheel go to the editor instead of the terminal.
+    pub mouse: bool,
+    pub keymaps: Keymaps,
+    buffer: BufferSettingsOverride,
+    file_types: HashMap<String, BufferSettingsOverride>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::from_file(ConfigFile::default())
+    }
+}
+
+impl Config {
+    /// The user's config directory, which holds the config file, themes and
+    /// macros.
+    pub fn dir() -> Option<PathBuf> {
+        paths::config_dir()
+    }
+    /// Reads the config file in `config_dir`. A missing file yields the
+    /// defaults.
+    pub fn load(config_dir: Option<&Path>) -> Result<Self, String> {
+        let Some(path) = config_dir.map(|dir| dir.join(CONFIG_FILE)) else {
+            return Ok(Self::default());
+        };
+        match read_to_string(&path) {
+            Ok(source) => Self::from_toml(&source).map_err(|err| format!("{CONFIG_FILE}: {err}")),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(format!("{}: {err}", path.display())),
+        }
+    }
+    pub fn from_toml(source: &str) -> Result<Self, String> {
+        let file: ConfigFile = toml::from_str(source).map_err(|err| {
+            let line = err.span().map_or(0, |span| {
+                source
+                    .get(..span.start)
+                    .map_or(0, |before| before.matches('\n').count())
+                    .saturating_add(1)
+            });
+            format!("line {line}: {}", err.message())
+        })?;
+
+        if file.quit_times == 0 {
+            return Err(String::from("quit_times must be at least 1"));
+        }
+        file.buffer_settings().validate()?;
+        for (name, settings) in &file.file_type {
+            if FileType::from_name(name).is_none() {
+                return Err(format!("Unknown file type '{name}'"));
+            }
+            settings
+                .validate()
+                .map_err(|err| format!("file_type.{name}: {err}"))?;
+        }
+        let keymaps = file.keys.keymaps()?;
+
+        Ok(Self {
+            keymaps,
+            ..Self::from_file(file)
+        })
+    }
+    fn from_file(file: ConfigFile) -> Self {
+        let buffer = file.buffer_settings();
+        Self {
+            quit_times: file.quit_times,
+            message_duration: Duration::from_secs(file.message_duration),
+            log_file: (!file.log_file.is_empty()).then(|| PathBuf::from(file.log_file)),
+            theme: file.theme,
+            autosave: (file.autosave > 0).then(|| Duration::from_secs(file.autosave)),
+            vim_mode: file.vim_mode,
+            mouse: file.mouse,
+            keymaps: Keymaps::default(),
+            buffer,
+            file_types: file.file_type,
+        }
+    }
+    /// The global settings with the overrides for `file_type` applied, and
+    /// then those of the `.editorconfig` files for the file at `path`.
+    /// Auto-pairing is on by default for code, and off for prose.
+    pub fn buffer_settings(&self, file_type: &FileType, path: Option<&Path>) -> BufferSettings {
+        let mut settings = BufferSettings {
+            auto_pairs: file_type.syntax.is_some(),
+            ..BufferSettings::default()
+        };
+        self.buffer.apply_to(&mut settings);
+        if let Some(overrides) = self.file_types.get(file_type.name) {
+            overrides.apply_to(&mut settings);
+        }
+        if let Some(path) = path {
+            editorconfig::overrides_for(path).apply_to(&mut settings);
+        }
+        settings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> String {
+        Config::from_toml(source).err().unwrap_or_default()
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected_with_their_line() {
+        assert!(error("quit_times = 2\ntab_widht = 4").starts_with("line 2:"));
+        assert!(error("[file_type.rust]\nexpand_tab = true").starts_with("line 2:"));
+        assert!(!error("[keys.nonsense]\n\"ctrl-a\" = \"save\"").is_empty());
+    }
+
+    #[test]
+    fn unknown_file_types_are_rejected() {
+        assert_eq!(error("[file_type.cobol]\ntab_width = 4"), "Unknown file type 'cobol'");
+    }
+
+    #[test]
+    fn option_values_are_validated() {
+        assert_eq!(error("quit_times = 0"), "quit_times must be at least 1");
+        assert_eq!(error("tab_width = 0"), "tab_width must be between 1 and 16, got 0");
+        assert_eq!(error("tab_width = 17"), "tab_width must be between 1 and 16, got 17");
+        assert_eq!(error("text_width = 0"), "text_width must be at least 1");
+        assert_eq!(
+            error("[file_type.rust]\ntab_width = 40"),
+            "file_type.rust: tab_width must be between 1 and 16, got 40"
+        );
+        assert!(error("tab_width = \"four\"").starts_with("line 1:"));
+        assert!(error("end_of_line = \"lfcr\"").starts_with("line 1:"));
+    }
+
+    #[test]
+    fn file_type_overrides_apply_over_the_global_settings() {
+        let config = Config::from_toml("tab_width = 2\nexpand_tabs = true\n[file_type.rust]\ntab_width = 8").unwrap();
+        let rust = config.buffer_settings(FileType::from_name("rust").unwrap(), None);
+        assert_eq!((rust.tab_width, rust.expand_tabs), (8, true));
+        let text = config.buffer_settings(FileType::from_name("text").unwrap(), None);
+        assert_eq!((text.tab_width, text.auto_pairs), (2, false));
+    }
+}
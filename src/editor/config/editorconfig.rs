@@ -0,0 +1,280 @@
+use std::{
+    collections::HashMap,
+    fs::read_to_string,
+    path::{Path, absolute},
+};
+
+use regex::Regex;
+use serde::{Deserialize, de::IntoDeserializer, de::value::Error as ValueError};
+
+use super::{BufferSettingsOverride, validate_tab_width};
+
+const EDITORCONFIG_FILE: &str = ".editorconfig";
+
+/// The settings the `.editorconfig` files in the directories above `path`
+/// give the file. Closer files win over those further up, and later sections
+/// over earlier ones. The search stops at a file with `root = true`.
+/// Unreadable files, unknown properties and invalid values are ignored.
+pub fn overrides_for(path: &Path) -> BufferSettingsOverride {
+    let Ok(path) = absolute(path) else {
+        return BufferSettingsOverride::default();
+    };
+    let mut files = Vec::new();
+    for dir in path.ancestors().skip(1) {
+        let Ok(source) = read_to_string(dir.join(EDITORCONFIG_FILE)) else {
+            continue;
+        };
+        let file = parse(&source);
+        let is_root = file.is_root;
+        files.push((dir, file));
+        if is_root {
+            break;
+        }
+    }
+
+    let mut properties = HashMap::new();
+    for (dir, file) in files.iter().rev() {
+        let Ok(relative) = path.strip_prefix(dir) else {
+            continue;
+        };
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        for section in &file.sections {
+            if glob_matches(&section.glob, &relative) {
+                properties.extend(section.properties.iter().cloned());
+            }
+        }
+    }
+    properties.retain(|_, value| value != "unset");
+    to_overrides(&properties)
+}
+
+struct Section {
+    glob: String,
+    properties: Vec<(String, String)>,
+}
+
+struct EditorConfigFile {
+    is_root: bool,
+    sections: Vec<Section>,
+}
+
+fn parse(source: &str) -> EditorConfigFile {
+    let mut file = EditorConfigFile {
+        is_root: false,
+        sections: Vec::new(),
+    };
+    for line in source.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with(['#', ';']) {
+            continue;
+        }
+        if let Some(glob) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            file.sections.push(Section {
+                glob: glob.to_string(),
+                properties: Vec::new(),
+            });
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let value = value.trim().to_lowercase();
+        match file.sections.last_mut() {
+            Some(section) => section.properties.push((key, value)),
+            None if key == "root" => file.is_root = value == "true",
+            None => {}
+        }
+    }
+    file
+}
+
+/// Whether `glob` matches the file at `relative`, its path from the
+/// directory of the `.editorconfig` file. A glob without a `/` matches the
+/// file name in any directory below.
+fn glob_matches(glob: &str, relative: &str) -> bool {
+    let (pattern, ranges) = glob_to_regex(glob.strip_prefix('/').unwrap_or(glob));
+    let pattern = if glob.contains('/') {
+        format!("^{pattern}$")
+    } else {
+        format!("^(?:.*/)?{pattern}$")
+    };
+    let Some(captures) = Regex::new(&pattern).ok().and_then(|regex| regex.captures(relative)) else {
+        return false;
+    };
+    // Each `{start..end}` captures a number, which has to be in its range.
+    ranges.iter().zip(captures.iter().skip(1)).all(|((start, end), number)| {
+        number
+            .and_then(|number| number.as_str().parse::<i64>().ok())
+            .is_some_and(|number| (*start..=*end).contains(&number))
+    })
+}
+
+/// Translates an `.editorconfig` glob to a regular expression. `*` matches
+/// within a directory and `**` across them, `{a,b}` either alternative, and
+/// `{start..end}` a number, which is captured and checked against the
+/// returned ranges.
+fn glob_to_regex(glob: &str) -> (String, Vec<(i64, i64)>) {
+    let mut pattern = String::new();
+    let mut ranges = Vec::new();
+    let mut open_braces = 0_usize;
+    let mut rest = glob;
+    while let Some(ch) = rest.chars().next() {
+        rest = rest.get(ch.len_utf8()..).unwrap_or_default();
+        match ch {
+            '\\' => {
+                let escaped = rest.chars().next().unwrap_or('\\');
+                rest = rest.get(escaped.len_utf8()..).unwrap_or_default();
+                pattern.push_str(&regex::escape(&escaped.to_string()));
+            }
+            '*' if rest.starts_with("*/") => {
+                rest = rest.get(2..).unwrap_or_default();
+                pattern.push_str("(?:.*/)?");
+            }
+            '*' if rest.starts_with('*') => {
+                rest = rest.get(1..).unwrap_or_default();
+                pattern.push_str(".*");
+            }
+            '*' => pattern.push_str("[^/]*"),
+            '?' => pattern.push_str("[^/]"),
+            '[' if rest.contains(']') => {
+                let (class, after) = rest.split_once(']').unwrap_or_default();
+                rest = after;
+                let (negated, class) = match class.strip_prefix('!') {
+                    Some(class) => ("^", class),
+                    None => ("", class),
+                };
+                let class = class.replace('\\', "\\\\").replace('[', "\\[");
+                pattern.push_str(&["[", negated, &class, "]"].concat());
+            }
+            '{' => {
+                let body = rest.split_once('}').map_or("", |(body, _)| body);
+                if let Some((start, end)) = body.split_once("..")
+                    && let (Ok(start), Ok(end)) = (start.parse::<i64>(), end.parse::<i64>())
+                {
+                    rest = rest.get(body.len().saturating_add(1)..).unwrap_or_default();
+                    pattern.push_str("([+-]?[0-9]+)");
+                    ranges.push((start.min(end), start.max(end)));
+                } else if rest.contains('}') && (body.contains(',') || body.contains('{')) {
+                    open_braces = open_braces.saturating_add(1);
+                    pattern.push_str("(?:");
+                } else {
+                    pattern.push_str("\\{");
+                }
+            }
+            ',' if open_braces > 0 => pattern.push('|'),
+            '}' if open_braces > 0 => {
+                open_braces = open_braces.saturating_sub(1);
+                pattern.push(')');
+            }
+            _ => pattern.push_str(&regex::escape(&ch.to_string())),
+        }
+    }
+    (pattern, ranges)
+}
+
+fn to_overrides(properties: &HashMap<String, String>) -> BufferSettingsOverride {
+    let property = |key: &str| properties.get(key).map(String::as_str);
+    let number = |key: &str| property(key).and_then(|value| value.parse::<usize>().ok());
+    let boolean = |key: &str| match property(key) {
+        Some("true") => Some(true),
+        Some("false") => Some(false),
+        _ => None,
+    };
+
+    // `indent_size = tab` indents by the tab width.
+    let indent_size = match property("indent_size") {
+        Some("tab") => number("tab_width"),
+        _ => number("indent_size"),
+    };
+    let expand_tabs = match property("indent_style") {
+        Some("space") => Some(true),
+        Some("tab") => Some(false),
+        _ => None,
+    };
+    // hecto indents by the tab width, so spaces take `indent_size` while
+    // tabs keep to `tab_width`. Each defaults to the other.
+    let tab_width = if expand_tabs == Some(true) {
+        indent_size.or_else(|| number("tab_width"))
+    } else {
+        number("tab_width").or(indent_size)
+    };
+    BufferSettingsOverride {
+        tab_width: tab_width.filter(|tab_width| validate_tab_width(*tab_width).is_ok()),
+        expand_tabs,
+        trim_trailing_whitespace: boolean("trim_trailing_whitespace"),
+        insert_final_newline: boolean("insert_final_newline"),
+        end_of_line: property("end_of_line").and_then(from_name),
+        charset: property("charset").and_then(from_name),
+        ..BufferSettingsOverride::default()
+    }
+}
+
+/// The value of an enum setting, by the name the config file uses for it.
+fn from_name<'de, T: Deserialize<'de>>(name: &'de str) -> Option<T> {
+    T::deserialize(IntoDeserializer::<ValueError>::into_deserializer(name)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::config::Charset;
+
+    #[test]
+    fn globs_match_like_editorconfig() {
+        assert!(glob_matches("*.rs", "main.rs"));
+        assert!(glob_matches("*.rs", "src/editor/main.rs"));
+        assert!(!glob_matches("/*.rs", "src/main.rs"));
+        assert!(glob_matches("src/*.rs", "src/main.rs"));
+        assert!(!glob_matches("src/*.rs", "src/editor/main.rs"));
+        assert!(glob_matches("src/**/*.rs", "src/main.rs"));
+        assert!(glob_matches("src/**/*.rs", "src/editor/view/main.rs"));
+        assert!(glob_matches("*.{rs,py}", "a.py"));
+        assert!(glob_matches("{*.md,docs/{a,b}.txt}", "docs/b.txt"));
+        assert!(!glob_matches("*.{rs,py}", "a.c"));
+        assert!(glob_matches("[!a]?.txt", "b1.txt"));
+        assert!(!glob_matches("[!a]?.txt", "a1.txt"));
+        assert!(glob_matches("{single}.txt", "{single}.txt"));
+    }
+
+    #[test]
+    fn numeric_ranges_match_numbers_within_them() {
+        assert!(glob_matches("file{1..3}.txt", "file1.txt"));
+        assert!(glob_matches("file{1..3}.txt", "file3.txt"));
+        assert!(!glob_matches("file{1..3}.txt", "file4.txt"));
+        assert!(!glob_matches("file{1..3}.txt", "filea.txt"));
+        assert!(glob_matches("v{-5..5}/{10..20}.md", "v-2/15.md"));
+        assert!(!glob_matches("v{-5..5}/{10..20}.md", "v-2/21.md"));
+    }
+
+    fn overrides(source: &str) -> BufferSettingsOverride {
+        let file = parse(source);
+        let properties = file
+            .sections
+            .into_iter()
+            .flat_map(|section| section.properties)
+            .collect();
+        to_overrides(&properties)
+    }
+
+    #[test]
+    fn indent_size_tab_uses_tab_width() {
+        let settings = overrides("[*]\nindent_style = space\nindent_size = tab\ntab_width = 8\n");
+        assert_eq!(settings.tab_width, Some(8));
+        assert_eq!(settings.expand_tabs, Some(true));
+
+        let settings = overrides("[*]\nindent_style = space\nindent_size = 2\ntab_width = 8\n");
+        assert_eq!(settings.tab_width, Some(2));
+
+        let settings = overrides("[*]\nindent_style = tab\nindent_size = 2\n");
+        assert_eq!(settings.tab_width, Some(2));
+        assert_eq!(settings.expand_tabs, Some(false));
+    }
+
+    #[test]
+    fn invalid_values_are_ignored() {
+        let settings = overrides("[*]\nindent_size = 99\nend_of_line = lfcr\ncharset = UTF-8-BOM\n");
+        assert_eq!(settings.tab_width, None);
+        assert_eq!(settings.end_of_line, None);
+        assert_eq!(settings.charset, Some(Charset::Utf8Bom));
+    }
+}
@@ -0,0 +1,313 @@
+use std::{
+    cmp::Reverse,
+    env::current_dir,
+    fs::{read_dir, Metadata},
+    io::Error,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    command::Move, DocumentStatus, Position, Screen, Size, Theme, ThemeElement, UIComponent,
+};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Default, Clone, Copy, Eq, PartialEq)]
+pub enum SortOrder {
+    #[default]
+    Name,
+    /// Newest first.
+    Modified,
+    /// Largest first.
+    Size,
+}
+
+impl SortOrder {
+    const fn next(self) -> Self {
+        match self {
+            Self::Name => Self::Modified,
+            Self::Modified => Self::Size,
+            Self::Size => Self::Name,
+        }
+    }
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Modified => "modification time",
+            Self::Size => "size",
+        }
+    }
+}
+
+pub struct Entry {
+    pub name: String,
+    pub path: PathBuf,
+    pub is_dir: bool,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Entry {
+    fn new(name: String, path: PathBuf, metadata: Option<&Metadata>) -> Self {
+        Self {
+            name,
+            path,
+            is_dir: metadata.is_some_and(Metadata::is_dir),
+            size: metadata.map_or(0, Metadata::len),
+            modified: metadata.and_then(|metadata| metadata.modified().ok()),
+        }
+    }
+    /// `..` goes first, then directories, then files.
+    fn group(&self) -> u8 {
+        match (self.name.as_str(), self.is_dir) {
+            ("..", _) => 0,
+            (_, true) => 1,
+            (_, false) => 2,
+        }
+    }
+}
+
+/// A read-only listing of a directory, shown in place of the text. The
+/// selected entry can be opened, renamed or deleted.
+#[derive(Default)]
+pub struct DirBrowser {
+    dir: PathBuf,
+    entries: Vec<Entry>,
+    selected: usize,
+    scroll_offset: usize,
+    sort_order: SortOrder,
+    needs_redraw: bool,
+    size: Size,
+}
+
+impl DirBrowser {
+    pub fn open(dir: &Path) -> Result<Self, String> {
+        let mut browser = Self::default();
+        browser.change_dir(dir)?;
+        Ok(browser)
+    }
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+    /// Lists `dir` instead. When going up, the directory that was left is
+    /// selected.
+    pub fn change_dir(&mut self, dir: &Path) -> Result<(), String> {
+        let dir = dir
+            .canonicalize()
+            .map_err(|err| format!("Could not open directory {}: {err}", dir.display()))?;
+        let previous = self.dir.file_name().map(|name| name.to_string_lossy().into_owned());
+        let is_parent = self.dir.parent() == Some(dir.as_path());
+        self.dir = dir;
+        self.entries.clear();
+        self.selected = 0;
+        self.scroll_offset = 0;
+        self.refresh()?;
+        if is_parent && let Some(previous) = previous {
+            self.select_name(&previous);
+        }
+        Ok(())
+    }
+    /// Reads the directory again, keeping the selected entry if it still
+    /// exists, or else the selected position.
+    pub fn refresh(&mut self) -> Result<(), String> {
+        let selected_name = self.selected_entry().map(|entry| entry.name.clone());
+        let selected_idx = self.selected;
+        let read_error = |err| format!("Could not read directory {}: {err}", self.dir.display());
+        let mut entries: Vec<Entry> = read_dir(&self.dir)
+            .map_err(read_error)?
+            .filter_map(Result::ok)
+            .map(|dir_entry| {
+                // Following symlinks makes links to directories browsable.
+                let metadata = dir_entry.path().metadata().ok();
+                let name = dir_entry.file_name().to_string_lossy().into_owned();
+                Entry::new(name, dir_entry.path(), metadata.as_ref())
+            })
+            .collect();
+        if let Some(parent) = self.dir.parent() {
+            let metadata = parent.metadata().ok();
+            entries.push(Entry::new(String::from(".."), parent.to_path_buf(), metadata.as_ref()));
+        }
+        self.entries = entries;
+        self.sort();
+        self.select(selected_idx.min(self.entries.len().saturating_sub(1)));
+        if let Some(name) = selected_name {
+            self.select_name(&name);
+        }
+        Ok(())
+    }
+    pub fn cycle_sort_order(&mut self) -> SortOrder {
+        self.sort_order = self.sort_order.next();
+        let selected_name = self.selected_entry().map(|entry| entry.name.clone());
+        self.sort();
+        if let Some(name) = selected_name {
+            self.select_name(&name);
+        }
+        self.set_needs_redraw(true);
+        self.sort_order
+    }
+    fn sort(&mut self) {
+        let by_name = |entry: &Entry| entry.name.to_lowercase();
+        match self.sort_order {
+            SortOrder::Name => self.entries.sort_by_key(|entry| (entry.group(), by_name(entry))),
+            SortOrder::Modified => self
+                .entries
+                .sort_by_key(|entry| (entry.group(), Reverse(entry.modified), by_name(entry))),
+            SortOrder::Size => self
+                .entries
+                .sort_by_key(|entry| (entry.group(), Reverse(entry.size), by_name(entry))),
+        }
+    }
+    pub fn select_name(&mut self, name: &str) {
+        if let Some(idx) = self.entries.iter().position(|entry| entry.name == name) {
+            self.select(idx);
+        }
+    }
+    pub fn selected_entry(&self) -> Option<&Entry> {
+        self.entries.get(self.selected)
+    }
+    pub fn handle_move_command(&mut self, command: Move) {
+        let last = self.entries.len().saturating_sub(1);
+        let page = self.size.height.saturating_sub(1).max(1);
+        let selected = match command {
+            Move::Up | Move::Left => self.selected.saturating_sub(1),
+            Move::Down | Move::Right => self.selected.saturating_add(1).min(last),
+            Move::PageUp => self.selected.saturating_sub(page),
+            Move::PageDown => self.selected.saturating_add(page).min(last),
+            Move::StartOfLine => 0,
+            Move::EndOfLine => last,
+            Move::MatchingBracket => self.selected,
+        };
+        self.select(selected);
+    }
+    fn select(&mut self, idx: usize) {
+        self.selected = idx;
+        if self.selected < self.scroll_offset {
+            self.scroll_offset = self.selected;
+        } else if self.selected >= self.scroll_offset.saturating_add(self.size.height) {
+            self.scroll_offset = self
+                .selected
+                .saturating_add(1)
+                .saturating_sub(self.size.height);
+        }
+        self.set_needs_redraw(true);
+    }
+    pub fn caret_position(&self) -> Position {
+        Position {
+            row: self.selected.saturating_sub(self.scroll_offset),
+            col: 0,
+        }
+    }
+    /// `path` relative to the current directory where possible, the way a
+    /// user would type it.
+    pub fn display_path(path: &Path) -> String {
+        let cwd = current_dir().and_then(|cwd| cwd.canonicalize());
+        match cwd.as_deref().map(|cwd| path.strip_prefix(cwd)) {
+            Ok(Ok(relative)) if relative.as_os_str().is_empty() => String::from("."),
+            Ok(Ok(relative)) => relative.to_string_lossy().into_owned(),
+            _ => path.to_string_lossy().into_owned(),
+        }
+    }
+    pub fn get_status(&self) -> DocumentStatus {
+        DocumentStatus {
+            total_lines: self.entries.len(),
+            current_line_idx: self.selected,
+            is_modified: false,
+            file_name: format!(
+                "{}/ (by {})",
+                Self::display_path(&self.dir).trim_end_matches('/'),
+                self.sort_order.name()
+            ),
+            mode: Some("BROWSE"),
+        }
+    }
+}
+
+impl UIComponent for DirBrowser {
+    fn set_needs_redraw(&mut self, value: bool) {
+        self.needs_redraw = value;
+    }
+    fn needs_redraw(&self) -> bool {
+        self.needs_redraw
+    }
+    fn set_size(&mut self, size: Size) {
+        self.size = size;
+        self.select(self.selected);
+    }
+    fn draw(&mut self, screen: &mut Screen, theme: &Theme, origin_row: usize) -> Result<(), Error> {
+        let end_row = origin_row.saturating_add(self.size.height);
+        for (row, idx) in (origin_row..end_row).zip(self.scroll_offset..) {
+            let Some(entry) = self.entries.get(idx) else {
+                screen.print_row(row, "", theme.style(ThemeElement::Text));
+                continue;
+            };
+            let style = theme.style(if idx == self.selected {
+                ThemeElement::Selection
+            } else {
+                ThemeElement::Text
+            });
+            let name = if entry.is_dir {
+                format!("{}/", entry.name)
+            } else {
+                entry.name.clone()
+            };
+            let size = if entry.is_dir { String::new() } else { format_size(entry.size) };
+            let modified = entry.modified.map(format_time).unwrap_or_default();
+            let details = format!("{size:>7}  {modified}");
+
+            screen.print_row(row, &name, style);
+            if entry.name == ".." {
+                continue;
+            }
+            let details_col = self.size.width.saturating_sub(details.len());
+            if details_col > name.len().saturating_add(1) {
+                screen.print_at(row, details_col, &details, style);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sizes in bytes, KiB, MiB or GiB, with one decimal for the larger units.
+#[allow(clippy::arithmetic_side_effects, clippy::integer_division)]
+fn format_size(bytes: u64) -> String {
+    let mut unit_size: u64 = 1;
+    for unit in ["B", "K", "M", "G"] {
+        if bytes < unit_size * 1024 || unit == "G" {
+            if unit_size == 1 {
+                return format!("{bytes}{unit}");
+            }
+            let tenths = bytes * 10 / unit_size;
+            return format!("{}.{}{unit}", tenths / 10, tenths % 10);
+        }
+        unit_size *= 1024;
+    }
+    String::new()
+}
+
+/// Formats a time as `YYYY-MM-DD HH:MM` in UTC.
+#[allow(clippy::arithmetic_side_effects, clippy::integer_division)]
+fn format_time(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (days, seconds_of_day) = (seconds / SECONDS_PER_DAY, seconds % SECONDS_PER_DAY);
+
+    // Converts days since 1970-01-01 into a civil date, counting in 400 year
+    // eras that start on March 1st so that leap days come last.
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}",
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60
+    )
+}
@@ -4,6 +4,8 @@ pub struct DocumentStatus {
     pub current_line_idx: usize,
     pub is_modified: bool,
     pub file_name: String,
+    /// The editing mode, when the editor is modal.
+    pub mode: Option<&'static str>,
 }
 
 impl DocumentStatus {
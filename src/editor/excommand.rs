@@ -0,0 +1,437 @@
+use std::{
+    fs::read_dir,
+    ops::RangeInclusive,
+    path::{Path, MAIN_SEPARATOR},
+};
+
+use regex::{Regex, RegexBuilder};
+
+use super::command::{SortKey, SortOptions};
+
+/// The command names offered by completion. Each can be shortened to its
+/// first letter, except for `set`, `sort` and `wq`.
+const COMMAND_NAMES: [&str; 8] = ["edit", "goto", "quit", "set", "sort", "substitute", "write", "wq"];
+/// The options `set` knows, as offered by completion.
+const OPTION_NAMES: [&str; 8] = [
+    "auto_pairs", "auto_wrap", "expand_tabs", "mouse", "tab_width=", "text_width=", "theme=", "vim_mode",
+];
+
+/// A line in a range, as typed.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+enum Address {
+    /// A 1-based line number.
+    Line(usize),
+    /// `.`
+    Current,
+    /// `$`
+    Last,
+}
+
+impl Address {
+    fn parse(text: &str) -> Result<Self, String> {
+        match text {
+            "." => Ok(Self::Current),
+            "$" => Ok(Self::Last),
+            _ => text
+                .parse::<usize>()
+                .map(Self::Line)
+                .map_err(|_| format!("Invalid address '{text}'")),
+        }
+    }
+    fn resolve(self, current_line_idx: usize, height: usize) -> usize {
+        match self {
+            Self::Line(line) => line.saturating_sub(1),
+            Self::Current => current_line_idx,
+            Self::Last => height.saturating_sub(1),
+        }
+    }
+}
+
+/// The lines a command applies to. Without a range, that is the current line.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct LineRange {
+    start: Address,
+    end: Address,
+}
+
+impl Default for LineRange {
+    fn default() -> Self {
+        Self {
+            start: Address::Current,
+            end: Address::Current,
+        }
+    }
+}
+
+impl LineRange {
+    /// Splits a leading `%`, `N`, or `N,M` off the input.
+    fn parse_prefix(input: &str) -> Result<(Option<Self>, &str), String> {
+        if let Some(rest) = input.strip_prefix('%') {
+            return Ok((
+                Some(Self {
+                    start: Address::Line(1),
+                    end: Address::Last,
+                }),
+                rest,
+            ));
+        }
+        let address_len = input
+            .find(|ch: char| !(ch.is_ascii_digit() || matches!(ch, '.' | '$' | ',')))
+            .unwrap_or(input.len());
+        let (range, rest) = input.split_at(address_len);
+        if range.is_empty() {
+            return Ok((None, input));
+        }
+        let (start, end) = range.split_once(',').unwrap_or((range, range));
+        Ok((
+            Some(Self {
+                start: Address::parse(start)?,
+                end: Address::parse(end)?,
+            }),
+            rest,
+        ))
+    }
+    /// The 0-based line indices, or an error for lines past the end.
+    pub fn resolve(self, current_line_idx: usize, height: usize) -> Result<RangeInclusive<usize>, String> {
+        let start = self.start.resolve(current_line_idx, height);
+        let end = self.end.resolve(current_line_idx, height);
+        if start > end {
+            return Err(String::from("Backwards range"));
+        }
+        if end >= height {
+            return Err(String::from("Invalid range"));
+        }
+        Ok(start..=end)
+    }
+}
+
+pub enum ExCommand {
+    Write(Option<String>),
+    WriteQuit,
+    Quit { force: bool },
+    Edit { file_name: Option<String>, force: bool },
+    /// Option names with their values, if given. Boolean options are set
+    /// without a value, and cleared with a `no` prefix.
+    Set(Vec<(String, Option<String>)>),
+    /// A 1-based line number.
+    Goto(usize),
+    Substitute {
+        range: LineRange,
+        regex: Regex,
+        replacement: String,
+        global: bool,
+    },
+    /// Without a range, sorts every line.
+    Sort {
+        range: Option<LineRange>,
+        options: SortOptions,
+    },
+}
+
+impl ExCommand {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let input = input.trim();
+        let (range, rest) = LineRange::parse_prefix(input)?;
+        if let Some(range) = range {
+            if rest.is_empty() {
+                return match range.start {
+                    Address::Line(line) => Ok(Self::Goto(line)),
+                    _ => Err(format!("Not a line number: '{input}'")),
+                };
+            }
+            let name_len = rest.find(|ch: char| !ch.is_ascii_alphabetic()).unwrap_or(rest.len());
+            return match rest.split_at(name_len) {
+                ("s" | "substitute", arguments) => Self::parse_substitute(range, arguments),
+                ("sort", arguments) => Self::parse_sort(Some(range), arguments),
+                _ => Err(format!("Ranges are only supported by substitute and sort: '{input}'")),
+            };
+        }
+
+        let name_len = input
+            .find(|ch: char| !ch.is_ascii_alphabetic())
+            .unwrap_or(input.len());
+        let (name, rest) = input.split_at(name_len);
+        let (force, argument) = match rest.strip_prefix('!') {
+            Some(rest) => (true, rest.trim()),
+            None => (false, rest.trim()),
+        };
+        let file_name = (!argument.is_empty()).then(|| argument.to_string());
+
+        match name {
+            "w" | "write" => Ok(Self::Write(file_name)),
+            "wq" | "x" if !force && file_name.is_none() => Ok(Self::WriteQuit),
+            "q" | "quit" if file_name.is_none() => Ok(Self::Quit { force }),
+            "e" | "edit" => Ok(Self::Edit { file_name, force }),
+            "set" if !force => Self::parse_set(argument),
+            "g" | "goto" if !force => argument
+                .parse()
+                .map(Self::Goto)
+                .map_err(|_| format!("Not a line number: '{argument}'")),
+            "s" | "substitute" => Self::parse_substitute(LineRange::default(), rest),
+            "sort" => Self::parse_sort(None, rest),
+            "" => Err(format!("Not a command: '{input}'")),
+            _ => Err(format!("Unknown command '{name}'")),
+        }
+    }
+    fn parse_set(arguments: &str) -> Result<Self, String> {
+        if arguments.is_empty() {
+            return Err(String::from("set needs an option"));
+        }
+        let options = arguments
+            .split_whitespace()
+            .map(|option| match option.split_once('=') {
+                Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                None => (option.to_string(), None),
+            })
+            .collect();
+        Ok(Self::Set(options))
+    }
+    /// Parses the flags of `sort`: `n` for numbers, `i` to ignore case, `u`
+    /// to drop duplicates and `r`, or `!` right after the name, to reverse.
+    fn parse_sort(range: Option<LineRange>, arguments: &str) -> Result<Self, String> {
+        let mut options = SortOptions::default();
+        let flags = match arguments.strip_prefix('!') {
+            Some(flags) => {
+                options.reverse = true;
+                flags
+            }
+            None => arguments,
+        };
+        for flag in flags.chars().filter(|ch| !ch.is_whitespace()) {
+            match flag {
+                'n' => options.key = SortKey::Numeric,
+                'i' => options.key = SortKey::IgnoreCase,
+                'u' => options.unique = true,
+                'r' => options.reverse = true,
+                _ => return Err(format!("Unknown flag '{flag}'")),
+            }
+        }
+        Ok(Self::Sort { range, options })
+    }
+    /// Parses `/pattern/replacement/flags`, where any punctuation can stand
+    /// in for the `/`.
+    fn parse_substitute(range: LineRange, arguments: &str) -> Result<Self, String> {
+        let mut chars = arguments.chars();
+        let delimiter = chars
+            .next()
+            .filter(|ch| !ch.is_alphanumeric() && !ch.is_whitespace() && *ch != '\\')
+            .ok_or_else(|| String::from("Usage: s/pattern/replacement/flags"))?;
+        let parts = split_unescaped(chars.as_str(), delimiter);
+        let (pattern, replacement, flags) = match parts.as_slice() {
+            [pattern] => (pattern.as_str(), "", ""),
+            [pattern, replacement] => (pattern.as_str(), replacement.as_str(), ""),
+            [pattern, replacement, flags] => (pattern.as_str(), replacement.as_str(), flags.as_str()),
+            _ => return Err(String::from("Usage: s/pattern/replacement/flags")),
+        };
+        if pattern.is_empty() {
+            return Err(String::from("Empty pattern"));
+        }
+        let mut global = false;
+        let mut case_insensitive = false;
+        for flag in flags.chars() {
+            match flag {
+                'g' => global = true,
+                'i' => case_insensitive = true,
+                _ => return Err(format!("Unknown flag '{flag}'")),
+            }
+        }
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(case_insensitive)
+            .build()
+            .map_err(|err| match err {
+                regex::Error::Syntax(_) => format!("Invalid pattern '{pattern}'"),
+                _ => err.to_string(),
+            })?;
+        Ok(Self::Substitute {
+            range,
+            regex,
+            replacement: translate_replacement(replacement),
+            global,
+        })
+    }
+}
+
+/// Splits at every `delimiter` not preceded by a backslash, and drops the
+/// backslash of escaped delimiters.
+fn split_unescaped(text: &str, delimiter: char) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch == '\\' && chars.peek() == Some(&delimiter) {
+            chars.next();
+            part.push(delimiter);
+        } else if ch == delimiter {
+            parts.push(part);
+            part = String::new();
+        } else {
+            part.push(ch);
+        }
+    }
+    parts.push(part);
+    parts
+}
+
+/// Turns Vim's replacement syntax, where `&` is the whole match and `\1` a
+/// group, into the syntax of the regex crate.
+fn translate_replacement(replacement: &str) -> String {
+    let mut translated = String::new();
+    let mut chars = replacement.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '&' => translated.push_str("${0}"),
+            '$' => translated.push_str("$$"),
+            '\\' => match chars.next() {
+                Some(digit) if digit.is_ascii_digit() => {
+                    translated.push_str("${");
+                    translated.push(digit);
+                    translated.push('}');
+                }
+                Some('n') => translated.push('\n'),
+                Some('t') => translated.push('\t'),
+                Some('$') => translated.push_str("$$"),
+                Some(other) => translated.push(other),
+                None => translated.push('\\'),
+            },
+            _ => translated.push(ch),
+        }
+    }
+    translated
+}
+
+/// The completions of a partly typed command line, each a full command line.
+pub fn complete(input: &str) -> Vec<String> {
+    let Some((name, argument)) = input.split_once(' ') else {
+        return COMMAND_NAMES
+            .iter()
+            .filter(|name| name.starts_with(input))
+            .map(ToString::to_string)
+            .collect();
+    };
+    let argument = argument.trim_start();
+    let candidates = match name {
+        "e" | "edit" | "e!" | "edit!" | "w" | "write" => complete_path(argument),
+        "set" => {
+            let (done, last) = argument.rsplit_once(' ').unwrap_or(("", argument));
+            return OPTION_NAMES
+                .iter()
+                .filter(|option| option.starts_with(last))
+                .map(|option| {
+                    let done = if done.is_empty() { String::new() } else { format!("{done} ") };
+                    format!("{name} {done}{option}")
+                })
+                .collect();
+        }
+        _ => Vec::new(),
+    };
+    candidates
+        .into_iter()
+        .map(|candidate| format!("{name} {candidate}"))
+        .collect()
+}
+
+/// Paths starting with `partial`, directories with a trailing separator.
+fn complete_path(partial: &str) -> Vec<String> {
+    let (dir, prefix) = match partial.rfind(MAIN_SEPARATOR) {
+        Some(idx) => partial.split_at(idx.saturating_add(1)),
+        None => ("", partial),
+    };
+    let Ok(entries) = read_dir(if dir.is_empty() { Path::new(".") } else { Path::new(dir) }) else {
+        return Vec::new();
+    };
+    let mut candidates: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+            let separator = if is_dir { MAIN_SEPARATOR.to_string() } else { String::new() };
+            Some(format!("{dir}{name}{separator}"))
+        })
+        .collect();
+    candidates.sort();
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sort_options(input: &str) -> Option<SortOptions> {
+        match ExCommand::parse(input) {
+            Ok(ExCommand::Sort { options, .. }) => Some(options),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn commands_can_be_shortened_to_their_first_letter() {
+        assert!(matches!(ExCommand::parse("w"), Ok(ExCommand::Write(None))));
+        assert!(matches!(ExCommand::parse("write"), Ok(ExCommand::Write(None))));
+        assert!(matches!(ExCommand::parse("q"), Ok(ExCommand::Quit { force: false })));
+        assert!(matches!(ExCommand::parse("x"), Ok(ExCommand::WriteQuit)));
+        assert!(matches!(ExCommand::parse("g 12"), Ok(ExCommand::Goto(12))));
+        assert!(matches!(ExCommand::parse("goto 12"), Ok(ExCommand::Goto(12))));
+        assert!(matches!(
+            ExCommand::parse("e notes.txt"),
+            Ok(ExCommand::Edit { file_name: Some(name), force: false }) if name == "notes.txt"
+        ));
+        assert!(matches!(ExCommand::parse("s/a/b/"), Ok(ExCommand::Substitute { global: false, .. })));
+        assert_eq!(ExCommand::parse("wqa").err().unwrap_or_default(), "Unknown command 'wqa'");
+        assert!(ExCommand::parse("se tab_width=4").is_err());
+    }
+
+    #[test]
+    fn a_bang_forces_the_command() {
+        assert!(matches!(ExCommand::parse("q!"), Ok(ExCommand::Quit { force: true })));
+        assert!(matches!(ExCommand::parse("e!"), Ok(ExCommand::Edit { file_name: None, force: true })));
+        assert!(matches!(
+            ExCommand::parse("w! other.txt"),
+            Ok(ExCommand::Write(Some(name))) if name == "other.txt"
+        ));
+        assert!(ExCommand::parse("wq!").is_err());
+        assert!(ExCommand::parse("set! mouse").is_err());
+    }
+
+    #[test]
+    fn ranges_resolve_to_line_indices() {
+        let range = |input: &str| match ExCommand::parse(input) {
+            Ok(ExCommand::Substitute { range, .. }) => range.resolve(4, 10),
+            _ => Err(String::from("not a substitution")),
+        };
+        assert_eq!(range("s/a/b/"), Ok(4..=4));
+        assert_eq!(range("%s/a/b/"), Ok(0..=9));
+        assert_eq!(range("2,5s/a/b/"), Ok(1..=4));
+        assert_eq!(range(".,$s/a/b/"), Ok(4..=9));
+        assert_eq!(range("7s/a/b/"), Ok(6..=6));
+        assert_eq!(range("5,2s/a/b/"), Err(String::from("Backwards range")));
+        assert_eq!(range("3,11s/a/b/"), Err(String::from("Invalid range")));
+        assert!(matches!(ExCommand::parse("42"), Ok(ExCommand::Goto(42))));
+        assert!(ExCommand::parse("$").is_err());
+        assert!(ExCommand::parse("1,2w").is_err());
+    }
+
+    #[test]
+    fn sort_takes_flags_and_a_bang_to_reverse() {
+        assert_eq!(sort_options("sort"), Some(SortOptions::default()));
+        let numeric_unique = SortOptions {
+            key: SortKey::Numeric,
+            unique: true,
+            reverse: false,
+        };
+        assert_eq!(sort_options("sort nu"), Some(numeric_unique));
+        assert_eq!(sort_options("sort n u"), Some(numeric_unique));
+        let reversed_ignoring_case = SortOptions {
+            key: SortKey::IgnoreCase,
+            unique: false,
+            reverse: true,
+        };
+        assert_eq!(sort_options("sort! i"), Some(reversed_ignoring_case));
+        assert_eq!(sort_options("sort ir"), Some(reversed_ignoring_case));
+        assert!(matches!(ExCommand::parse("%sort"), Ok(ExCommand::Sort { range: Some(_), .. })));
+        assert!(matches!(ExCommand::parse("sort"), Ok(ExCommand::Sort { range: None, .. })));
+        assert_eq!(ExCommand::parse("sort x").err().unwrap_or_default(), "Unknown flag 'x'");
+    }
+}
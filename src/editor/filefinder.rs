@@ -0,0 +1,88 @@
+use std::{
+    fs::File,
+    io::{Error, Read},
+    path::{Path, PathBuf},
+};
+
+use ignore::WalkBuilder;
+
+use super::{Line, Screen, Size, Theme, ThemeElement, UIComponent};
+
+/// Walking stops after this many files, so that opening the finder in a huge
+/// directory does not hang the editor.
+const MAX_FILES: usize = 50_000;
+/// Only the start of a file is read for its preview.
+const PREVIEW_BYTES: u64 = 64 * 1024;
+
+/// The files below `root`, relative to it and sorted. Hidden files and files
+/// matched by `.gitignore` and similar ignore files are left out.
+pub fn list_files(root: &Path) -> Vec<String> {
+    let mut files: Vec<String> = WalkBuilder::new(root)
+        .require_git(false)
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_some_and(|file_type| file_type.is_file()))
+        .filter_map(|entry| {
+            let path = entry.path().strip_prefix(root).ok()?;
+            path.to_str().map(String::from)
+        })
+        .take(MAX_FILES)
+        .collect();
+    files.sort();
+    files
+}
+
+/// The first lines of the file selected in the finder, shown above the list.
+#[derive(Default)]
+pub struct FilePreview {
+    path: Option<PathBuf>,
+    lines: Vec<Line>,
+    needs_redraw: bool,
+    size: Size,
+}
+
+impl FilePreview {
+    pub fn set_path(&mut self, path: Option<&Path>) {
+        if self.path.as_deref() == path {
+            return;
+        }
+        self.path = path.map(Path::to_path_buf);
+        self.lines = path.map(Self::read_lines).unwrap_or_default();
+        self.set_needs_redraw(true);
+    }
+    fn read_lines(path: &Path) -> Vec<Line> {
+        let mut bytes = Vec::new();
+        let result = File::open(path).and_then(|file| file.take(PREVIEW_BYTES).read_to_end(&mut bytes));
+        if result.is_err() {
+            return vec![Line::from("(cannot read file)")];
+        }
+        if bytes.contains(&0) {
+            return vec![Line::from("(binary file)")];
+        }
+        String::from_utf8_lossy(&bytes).lines().map(Line::from).collect()
+    }
+}
+
+impl UIComponent for FilePreview {
+    fn set_needs_redraw(&mut self, value: bool) {
+        self.needs_redraw = value;
+    }
+    fn needs_redraw(&self) -> bool {
+        self.needs_redraw
+    }
+    fn set_size(&mut self, size: Size) {
+        self.size = size;
+    }
+    fn draw(&mut self, screen: &mut Screen, theme: &Theme, origin_row: usize) -> Result<(), Error> {
+        let style = theme.style(ThemeElement::Text);
+        for (row, line_idx) in (origin_row..origin_row.saturating_add(self.size.height)).zip(0..) {
+            let text = self
+                .lines
+                .get(line_idx)
+                .map(|line| line.get_visible_graphemes(0..self.size.width))
+                .unwrap_or_default();
+            screen.print_row(row, &text, style);
+        }
+        Ok(())
+    }
+}
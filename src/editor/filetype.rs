@@ -0,0 +1,115 @@
+use std::path::Path;
+
+pub struct FileType {
+    pub name: &'static str,
+    extensions: &'static [&'static str],
+    file_names: &'static [&'static str],
+    pub syntax: Option<&'static Syntax>,
+}
+
+/// Where a file type has comments and strings, so that brackets in them can
+/// be told apart from the ones in code.
+pub struct Syntax {
+    pub line_comment: Option<&'static str>,
+    pub block_comment: Option<(&'static str, &'static str)>,
+    pub quotes: &'static [char],
+    /// Whether strings go on past the end of the line they start on.
+    pub multiline_strings: bool,
+    /// Whether `'` starts a character literal such as `'{'`, and is left
+    /// alone otherwise, as in Rust's lifetimes.
+    pub char_literals: bool,
+}
+
+static RUST: Syntax = Syntax {
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"'],
+    multiline_strings: true,
+    char_literals: true,
+};
+static C_LIKE: Syntax = Syntax {
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\''],
+    multiline_strings: false,
+    char_literals: false,
+};
+static SCRIPT: Syntax = Syntax {
+    quotes: &['"', '\'', '`'],
+    ..C_LIKE
+};
+static HASH_COMMENTS: Syntax = Syntax {
+    line_comment: Some("#"),
+    block_comment: None,
+    quotes: &['"', '\''],
+    multiline_strings: false,
+    char_literals: false,
+};
+static LUA: Syntax = Syntax {
+    line_comment: Some("--"),
+    block_comment: Some(("--[[", "]]")),
+    ..HASH_COMMENTS
+};
+static SQL: Syntax = Syntax {
+    line_comment: Some("--"),
+    block_comment: Some(("/*", "*/")),
+    quotes: &['\''],
+    ..HASH_COMMENTS
+};
+static CSS: Syntax = Syntax {
+    line_comment: None,
+    block_comment: Some(("/*", "*/")),
+    ..HASH_COMMENTS
+};
+static JSON: Syntax = Syntax {
+    line_comment: None,
+    block_comment: None,
+    quotes: &['"'],
+    ..HASH_COMMENTS
+};
+
+static FILE_TYPES: [FileType; 16] = [
+    FileType { name: "rust", extensions: &["rs"], file_names: &[], syntax: Some(&RUST) },
+    FileType { name: "c", extensions: &["c", "h"], file_names: &[], syntax: Some(&C_LIKE) },
+    FileType { name: "cpp", extensions: &["cpp", "cc", "cxx", "hpp", "hh"], file_names: &[], syntax: Some(&C_LIKE) },
+    FileType { name: "go", extensions: &["go"], file_names: &[], syntax: Some(&SCRIPT) },
+    FileType { name: "javascript", extensions: &["js", "mjs", "cjs"], file_names: &[], syntax: Some(&SCRIPT) },
+    FileType { name: "typescript", extensions: &["ts", "tsx"], file_names: &[], syntax: Some(&SCRIPT) },
+    FileType { name: "python", extensions: &["py"], file_names: &[], syntax: Some(&HASH_COMMENTS) },
+    FileType { name: "shell", extensions: &["sh", "bash", "zsh"], file_names: &[".bashrc", ".zshrc"], syntax: Some(&HASH_COMMENTS) },
+    FileType { name: "toml", extensions: &["toml"], file_names: &[], syntax: Some(&HASH_COMMENTS) },
+    FileType { name: "yaml", extensions: &["yml", "yaml"], file_names: &[], syntax: Some(&HASH_COMMENTS) },
+    FileType { name: "json", extensions: &["json"], file_names: &[], syntax: Some(&JSON) },
+    FileType { name: "css", extensions: &["css"], file_names: &[], syntax: Some(&CSS) },
+    FileType { name: "lua", extensions: &["lua"], file_names: &[], syntax: Some(&LUA) },
+    FileType { name: "sql", extensions: &["sql"], file_names: &[], syntax: Some(&SQL) },
+    FileType { name: "make", extensions: &["mk"], file_names: &["Makefile", "makefile", "GNUmakefile"], syntax: Some(&HASH_COMMENTS) },
+    FileType { name: "markdown", extensions: &["md", "markdown"], file_names: &[], syntax: None },
+];
+
+/// Anything not recognized is treated as plain text.
+static TEXT: FileType = FileType { name: "text", extensions: &["txt"], file_names: &[], syntax: None };
+
+impl FileType {
+    pub fn detect(path: Option<&Path>) -> &'static Self {
+        let Some(path) = path else {
+            return &TEXT;
+        };
+        let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        let extension = path.extension().and_then(|extension| extension.to_str());
+
+        FILE_TYPES
+            .iter()
+            .find(|file_type| {
+                file_type.file_names.contains(&file_name)
+                    || extension.is_some_and(|extension| file_type.extensions.contains(&extension))
+            })
+            .unwrap_or(&TEXT)
+    }
+    pub fn from_name(name: &str) -> Option<&'static Self> {
+        FILE_TYPES
+            .iter()
+            .chain(std::iter::once(&TEXT))
+            .find(|file_type| file_type.name == name)
+    }
+}
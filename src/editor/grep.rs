@@ -0,0 +1,278 @@
+use std::{
+    fs::File,
+    io::{Error, Read},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, TryRecvError},
+    thread,
+};
+
+use ignore::WalkBuilder;
+use regex::{Regex, RegexBuilder};
+
+use super::{
+    annotatedstring::{AnnotatedString, AnnotationType},
+    command::Move,
+    DocumentStatus, Line, Position, Screen, Size, Theme, UIComponent,
+};
+
+/// The search stops after this many matching lines.
+const MAX_MATCHES: usize = 10_000;
+/// Files this large are most likely not source code.
+const MAX_FILE_SIZE: u64 = 4 * 1024 * 1024;
+/// Only this much of a matching line is kept for display.
+const MAX_LINE_LEN: usize = 500;
+
+/// A line that matched, with the byte ranges of the matches in `text`.
+pub struct GrepMatch {
+    pub path: String,
+    pub line_idx: usize,
+    text: String,
+    ranges: Vec<Range<usize>>,
+}
+
+enum Message {
+    Match(GrepMatch),
+    /// The search finished, or stopped at `MAX_MATCHES`.
+    Done { files_searched: usize },
+}
+
+/// Searches the files below `root` on a separate thread. Results arrive as
+/// they are found; dropping the receiver stops the search.
+fn spawn_search(root: PathBuf, regex: Regex) -> Receiver<Message> {
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        let mut files_searched: usize = 0;
+        let mut matches: usize = 0;
+        let files = WalkBuilder::new(&root)
+            .require_git(false)
+            .build()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_some_and(|file_type| file_type.is_file()));
+        for entry in files {
+            let path = entry.path();
+            let display_path = path.strip_prefix(&root).unwrap_or(path).to_string_lossy();
+            let Some(text) = read_text(path) else {
+                continue;
+            };
+            files_searched = files_searched.saturating_add(1);
+            for grep_match in search_text(&text, &regex, &display_path) {
+                matches = matches.saturating_add(1);
+                if sender.send(Message::Match(grep_match)).is_err() {
+                    return;
+                }
+                if matches >= MAX_MATCHES {
+                    let _ = sender.send(Message::Done { files_searched });
+                    return;
+                }
+            }
+        }
+        let _ = sender.send(Message::Done { files_searched });
+    });
+    receiver
+}
+
+/// The contents of a file, unless it is too large, unreadable or binary.
+fn read_text(path: &Path) -> Option<String> {
+    let mut file = File::open(path).ok()?;
+    if file.metadata().ok()?.len() > MAX_FILE_SIZE {
+        return None;
+    }
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).ok()?;
+    if bytes.contains(&0) {
+        return None;
+    }
+    String::from_utf8(bytes).ok()
+}
+
+fn search_text(text: &str, regex: &Regex, path: &str) -> Vec<GrepMatch> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(line_idx, line)| {
+            let trimmed = line.trim_start();
+            let offset = line.len().saturating_sub(trimmed.len());
+            let mut end = trimmed.len().min(MAX_LINE_LEN);
+            while !trimmed.is_char_boundary(end) {
+                end = end.saturating_sub(1);
+            }
+            let ranges: Vec<Range<usize>> = regex
+                .find_iter(line)
+                .filter(|found| !found.is_empty())
+                .map(|found| found.start().saturating_sub(offset)..found.end().saturating_sub(offset))
+                .filter(|range| range.end <= end)
+                .collect();
+            if ranges.is_empty() && !regex.is_match(line) {
+                return None;
+            }
+            Some(GrepMatch {
+                path: path.to_string(),
+                line_idx,
+                text: trimmed.get(..end).unwrap_or_default().to_string(),
+                ranges,
+            })
+        })
+        .collect()
+}
+
+/// The lines matching a pattern across the project, shown in place of the
+/// text while the search still runs.
+pub struct GrepResults {
+    pattern: String,
+    matches: Vec<GrepMatch>,
+    receiver: Option<Receiver<Message>>,
+    files_searched: usize,
+    selected: usize,
+    scroll_offset: usize,
+    needs_redraw: bool,
+    size: Size,
+}
+
+impl GrepResults {
+    /// Starts searching the files below `root`. Patterns are regular
+    /// expressions, which ignore case unless they contain an upper case
+    /// letter.
+    pub fn start(pattern: &str, root: PathBuf) -> Result<Self, String> {
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(!pattern.chars().any(char::is_uppercase))
+            .build()
+            .map_err(|_| format!("Invalid pattern '{pattern}'"))?;
+        Ok(Self {
+            pattern: pattern.to_string(),
+            matches: Vec::new(),
+            receiver: Some(spawn_search(root, regex)),
+            files_searched: 0,
+            selected: 0,
+            scroll_offset: 0,
+            needs_redraw: true,
+            size: Size::default(),
+        })
+    }
+    pub fn is_running(&self) -> bool {
+        self.receiver.is_some()
+    }
+    /// Takes over the results found since the last call.
+    pub fn poll(&mut self) {
+        let Some(receiver) = &self.receiver else {
+            return;
+        };
+        let height = self.matches.len();
+        loop {
+            match receiver.try_recv() {
+                Ok(Message::Match(grep_match)) => self.matches.push(grep_match),
+                Ok(Message::Done { files_searched }) => {
+                    self.files_searched = files_searched;
+                    self.receiver = None;
+                    self.set_needs_redraw(true);
+                    break;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.receiver = None;
+                    break;
+                }
+            }
+        }
+        if self.matches.len() != height {
+            self.set_needs_redraw(true);
+        }
+    }
+    pub fn selected_match(&self) -> Option<&GrepMatch> {
+        self.matches.get(self.selected)
+    }
+    pub fn handle_move_command(&mut self, command: Move) {
+        let last = self.matches.len().saturating_sub(1);
+        let page = self.size.height.saturating_sub(1).max(1);
+        self.selected = match command {
+            Move::Up | Move::Left => self.selected.saturating_sub(1),
+            Move::Down | Move::Right => self.selected.saturating_add(1).min(last),
+            Move::PageUp => self.selected.saturating_sub(page),
+            Move::PageDown => self.selected.saturating_add(page).min(last),
+            Move::StartOfLine => 0,
+            Move::EndOfLine => last,
+            Move::MatchingBracket => self.selected,
+        };
+        self.scroll_selected_into_view();
+        self.set_needs_redraw(true);
+    }
+    fn scroll_selected_into_view(&mut self) {
+        if self.selected < self.scroll_offset {
+            self.scroll_offset = self.selected;
+        } else if self.selected >= self.scroll_offset.saturating_add(self.size.height) {
+            self.scroll_offset = self
+                .selected
+                .saturating_add(1)
+                .saturating_sub(self.size.height);
+        }
+    }
+    pub fn caret_position(&self) -> Position {
+        Position {
+            row: self.selected.saturating_sub(self.scroll_offset),
+            col: 0,
+        }
+    }
+    pub fn get_status(&self) -> DocumentStatus {
+        let progress = if self.is_running() {
+            String::from("searching")
+        } else {
+            format!("{} files", self.files_searched)
+        };
+        DocumentStatus {
+            total_lines: self.matches.len(),
+            current_line_idx: self.selected,
+            is_modified: false,
+            file_name: format!("grep '{}' ({progress})", self.pattern),
+            mode: Some("GREP"),
+        }
+    }
+    fn row_text(grep_match: &GrepMatch, is_selected: bool) -> AnnotatedString {
+        let plain = if is_selected {
+            AnnotationType::Selection
+        } else {
+            AnnotationType::None
+        };
+        // Tabs and control characters are shown the same way as in the text.
+        let visible = |text: &str| Line::from(text).get_visible_graphemes(0..usize::MAX);
+        let mut row = AnnotatedString::default();
+        row.push(
+            &format!("{}:{}: ", grep_match.path, grep_match.line_idx.saturating_add(1)),
+            plain,
+        );
+        let mut end = 0;
+        for range in &grep_match.ranges {
+            row.push(&visible(grep_match.text.get(end..range.start).unwrap_or_default()), plain);
+            row.push(
+                &visible(grep_match.text.get(range.clone()).unwrap_or_default()),
+                AnnotationType::Highlight,
+            );
+            end = range.end;
+        }
+        row.push(&visible(grep_match.text.get(end..).unwrap_or_default()), plain);
+        row
+    }
+}
+
+impl UIComponent for GrepResults {
+    fn set_needs_redraw(&mut self, value: bool) {
+        self.needs_redraw = value;
+    }
+    fn needs_redraw(&self) -> bool {
+        self.needs_redraw
+    }
+    fn set_size(&mut self, size: Size) {
+        self.size = size;
+        self.scroll_selected_into_view();
+    }
+    fn draw(&mut self, screen: &mut Screen, theme: &Theme, origin_row: usize) -> Result<(), Error> {
+        let end_row = origin_row.saturating_add(self.size.height);
+        for (row, idx) in (origin_row..end_row).zip(self.scroll_offset..) {
+            let text = self
+                .matches
+                .get(idx)
+                .map(|grep_match| Self::row_text(grep_match, idx == self.selected))
+                .unwrap_or_default();
+            screen.print_annotated_row(row, &text, theme);
+        }
+        Ok(())
+    }
+}
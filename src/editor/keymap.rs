@@ -0,0 +1,370 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use super::command::{
+    Block::{Copy as CopyBlock, Cut as CutBlock, Paste as PasteBlock, ToggleSelection},
+    Caret::{Above, AtAllMatches, AtNextMatch, Below},
+    Command::{self, Block, Caret, Edit, History, Lines, Macro, Move, Prompt, System},
+    Edit::{Delete, DeleteBackward, Insert, InsertNewLine},
+    History::{Redo, Undo},
+    Lines::{Delete as DeleteLines, Duplicate, Join, MoveDown, MoveUp, Reflow, ToggleComment},
+    Macro::{Play, Record},
+    Move::{Down, EndOfLine, Left, MatchingBracket, PageDown, PageUp, Right, StartOfLine, Up},
+    Prompt::{Accept, Complete, Next, Previous},
+    System::{
+        Browse, CommandLine, CommandPalette, CycleTheme, Dismiss, Grep, OpenFile, Quit, ReloadConfig, Save,
+        Search,
+    },
+};
+
+/// Function keys above this are not worth naming in a config file.
+const MAX_FUNCTION_KEY: u8 = 24;
+
+/// A single key press together with its modifiers.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct KeyChord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        Self { code, modifiers }.normalized()
+    }
+    /// Terminals report Shift through the character itself, and cannot tell
+    /// Ctrl-F from Ctrl-Shift-F. Chords are compared in that reduced form.
+    fn normalized(self) -> Self {
+        let Self { code, mut modifiers } = self;
+        let code = match code {
+            KeyCode::Char(character) => {
+                let shifted = modifiers.contains(KeyModifiers::SHIFT);
+                modifiers.remove(KeyModifiers::SHIFT);
+                if modifiers.contains(KeyModifiers::CONTROL) && character == '7' {
+                    // Terminals send Ctrl-/ as the same byte as Ctrl-7.
+                    KeyCode::Char('/')
+                } else if modifiers.contains(KeyModifiers::CONTROL) {
+                    KeyCode::Char(character.to_ascii_lowercase())
+                } else if shifted {
+                    KeyCode::Char(character.to_ascii_uppercase())
+                } else {
+                    code
+                }
+            }
+            KeyCode::BackTab => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                code
+            }
+            _ => code,
+        };
+        let modifiers = modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        Self { code, modifiers }
+    }
+    /// Whether this is Ctrl and `character`.
+    pub fn is_ctrl(self, character: char) -> bool {
+        self == Self::new(KeyCode::Char(character), KeyModifiers::CONTROL)
+    }
+    /// The character this chord types, if it types one at all.
+    pub fn as_char(self) -> Option<char> {
+        match self.code {
+            KeyCode::Char(character) if self.modifiers.is_empty() => Some(character),
+            _ => None,
+        }
+    }
+    /// Parses chords such as `ctrl-s`, `Alt+Left`, `shift-f5` or `esc`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = text;
+        while let Some((prefix, remainder)) = rest.split_once(['-', '+']) {
+            if remainder.is_empty() {
+                break;
+            }
+            modifiers |= match prefix.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" | "c" => KeyModifiers::CONTROL,
+                "alt" | "meta" | "a" | "m" => KeyModifiers::ALT,
+                "shift" | "s" => KeyModifiers::SHIFT,
+                _ => break,
+            };
+            rest = remainder;
+        }
+
+        let code = parse_key_code(rest).ok_or_else(|| format!("Unknown key '{text}'"))?;
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl From<KeyEvent> for KeyChord {
+    fn from(event: KeyEvent) -> Self {
+        Self::new(event.code, event.modifiers)
+    }
+}
+
+impl Display for KeyChord {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "Ctrl-"),
+            (KeyModifiers::ALT, "Alt-"),
+            (KeyModifiers::SHIFT, "Shift-"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                write!(formatter, "{name}")?;
+            }
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(formatter, "Space"),
+            KeyCode::Char(character) if self.modifiers.is_empty() => {
+                write!(formatter, "{character}")
+            }
+            KeyCode::Char(character) => write!(formatter, "{}", character.to_ascii_uppercase()),
+            KeyCode::F(number) => write!(formatter, "F{number}"),
+            KeyCode::Esc => write!(formatter, "Esc"),
+            KeyCode::PageUp => write!(formatter, "PageUp"),
+            KeyCode::PageDown => write!(formatter, "PageDown"),
+            code => write!(formatter, "{code:?}"),
+        }
+    }
+}
+
+fn parse_key_code(name: &str) -> Option<KeyCode> {
+    let mut characters = name.chars();
+    if let (Some(character), None) = (characters.next(), characters.next()) {
+        return Some(KeyCode::Char(character));
+    }
+    let code = match name.to_ascii_lowercase().as_str() {
+        "space" => KeyCode::Char(' '),
+        "enter" | "return" => KeyCode::Enter,
+        "esc" | "escape" => KeyCode::Esc,
+        "tab" => KeyCode::Tab,
+        "backtab" => KeyCode::BackTab,
+        "backspace" => KeyCode::Backspace,
+        "delete" | "del" => KeyCode::Delete,
+        "insert" | "ins" => KeyCode::Insert,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        lower => {
+            let number = lower.strip_prefix('f')?.parse::<u8>().ok()?;
+            if !(1..=MAX_FUNCTION_KEY).contains(&number) {
+                return None;
+            }
+            KeyCode::F(number)
+        }
+    };
+    Some(code)
+}
+
+/// Parses a whitespace separated sequence of chords, e.g. `ctrl-k ctrl-c`.
+pub fn parse_sequence(text: &str) -> Result<Vec<KeyChord>, String> {
+    let sequence = text
+        .split_whitespace()
+        .map(KeyChord::parse)
+        .collect::<Result<Vec<_>, _>>()?;
+    if sequence.is_empty() {
+        return Err(String::from("Empty key sequence"));
+    }
+    Ok(sequence)
+}
+
+pub fn format_sequence(sequence: &[KeyChord]) -> String {
+    sequence
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub enum Lookup {
+    Command(Command),
+    /// The keys so far start one or more longer sequences.
+    Prefix,
+    Unbound,
+}
+
+/// Maps key sequences to commands. A bound sequence is never the prefix of
+/// another one, so a lookup is never ambiguous.
+#[derive(Clone, Default)]
+pub struct Keymap {
+    bindings: Vec<(Vec<KeyChord>, Command)>,
+}
+
+impl Keymap {
+    /// Binds `sequence`, replacing every binding it would make ambiguous.
+    pub fn bind(&mut self, sequence: Vec<KeyChord>, command: Command) {
+        self.unbind(&sequence);
+        self.bindings.push((sequence, command));
+    }
+    /// Removes the binding for `sequence` along with any binding it is a
+    /// prefix of, or that is a prefix of it.
+    pub fn unbind(&mut self, sequence: &[KeyChord]) {
+        self.bindings
+            .retain(|(bound, _)| !bound.starts_with(sequence) && !sequence.starts_with(bound));
+    }
+    pub fn lookup(&self, keys: &[KeyChord]) -> Lookup {
+        let mut is_prefix = false;
+        for (sequence, command) in &self.bindings {
+            if sequence.as_slice() == keys {
+                return Lookup::Command(*command);
+            }
+            is_prefix |= sequence.starts_with(keys);
+        }
+        if is_prefix { Lookup::Prefix } else { Lookup::Unbound }
+    }
+    /// The first sequence bound to `command`, formatted for display.
+    pub fn keys_for(&self, command: Command) -> Option<String> {
+        self.bindings
+            .iter()
+            .find(|(_, bound)| *bound == command)
+            .map(|(sequence, _)| format_sequence(sequence))
+    }
+    /// Applies the bindings from the config file on top of this keymap. The
+    /// command `none` removes a binding.
+    pub fn apply(&mut self, bindings: &BTreeMap<String, String>) -> Result<(), String> {
+        let mut sequences: Vec<Vec<KeyChord>> = Vec::new();
+        for (keys, command_name) in bindings {
+            let sequence = parse_sequence(keys)?;
+            if let Some(other) = sequences
+                .iter()
+                .find(|other| other.starts_with(&sequence) || sequence.starts_with(other))
+            {
+                return Err(format!(
+                    "'{keys}' conflicts with '{}'",
+                    format_sequence(other)
+                ));
+            }
+            if command_name == "none" {
+                self.unbind(&sequence);
+            } else {
+                let command = Command::from_name(command_name)
+                    .ok_or_else(|| format!("Unknown command '{command_name}'"))?;
+                self.bind(sequence.clone(), command);
+            }
+            sequences.push(sequence);
+        }
+        Ok(())
+    }
+    fn from_defaults(defaults: &[(KeyCode, KeyModifiers, Command)]) -> Self {
+        let mut keymap = Self::default();
+        for (code, modifiers, command) in defaults {
+            keymap.bind(vec![KeyChord::new(*code, *modifiers)], *command);
+        }
+        keymap
+    }
+}
+
+/// The keymaps for editing text and for each prompt. Each prompt has its own
+/// keymap, so that the same keys can mean different things there.
+#[derive(Clone)]
+pub struct Keymaps {
+    pub normal: Keymap,
+    pub search: Keymap,
+    pub save: Keymap,
+    pub command: Keymap,
+    /// The command palette and the file finder.
+    pub picker: Keymap,
+    /// Yes or no questions, answered by typing `y` or `n`.
+    pub confirm: Keymap,
+}
+
+impl Default for Keymaps {
+    fn default() -> Self {
+        const NONE: KeyModifiers = KeyModifiers::NONE;
+        const CTRL: KeyModifiers = KeyModifiers::CONTROL;
+        const ALT: KeyModifiers = KeyModifiers::ALT;
+        const ALT_SHIFT: KeyModifiers = KeyModifiers::ALT.union(KeyModifiers::SHIFT);
+        const CTRL_ALT: KeyModifiers = KeyModifiers::CONTROL.union(KeyModifiers::ALT);
+        let normal = Keymap::from_defaults(&[
+            (KeyCode::Up, NONE, Move(Up)),
+            (KeyCode::Down, NONE, Move(Down)),
+            (KeyCode::Left, NONE, Move(Left)),
+            (KeyCode::Right, NONE, Move(Right)),
+            (KeyCode::PageUp, NONE, Move(PageUp)),
+            (KeyCode::PageDown, NONE, Move(PageDown)),
+            (KeyCode::Home, NONE, Move(StartOfLine)),
+            (KeyCode::End, NONE, Move(EndOfLine)),
+            (KeyCode::Char('m'), ALT, Move(MatchingBracket)),
+            (KeyCode::Enter, NONE, Edit(InsertNewLine)),
+            (KeyCode::Tab, NONE, Edit(Insert('\t'))),
+            (KeyCode::Backspace, NONE, Edit(DeleteBackward)),
+            (KeyCode::Delete, NONE, Edit(Delete)),
+            (KeyCode::Esc, NONE, System(Dismiss)),
+            (KeyCode::Char('z'), CTRL, History(Undo)),
+            (KeyCode::Char('y'), CTRL, History(Redo)),
+            (KeyCode::Char('q'), CTRL, System(Quit)),
+            (KeyCode::Char('f'), CTRL, System(Search)),
+            (KeyCode::Char('s'), CTRL, System(Save)),
+            (KeyCode::Char('t'), CTRL, System(CycleTheme)),
+            (KeyCode::Char('r'), CTRL, System(ReloadConfig)),
+            (KeyCode::Char('e'), CTRL, System(CommandLine)),
+            (KeyCode::Char('p'), CTRL, System(CommandPalette)),
+            (KeyCode::Char('o'), CTRL, System(OpenFile)),
+            (KeyCode::Char('b'), CTRL, System(Browse)),
+            (KeyCode::Char('g'), CTRL, System(Grep)),
+            (KeyCode::Up, CTRL_ALT, Caret(Above)),
+            (KeyCode::Down, CTRL_ALT, Caret(Below)),
+            (KeyCode::Char('d'), CTRL, Caret(AtNextMatch)),
+            (KeyCode::Char('d'), CTRL_ALT, Caret(AtAllMatches)),
+            (KeyCode::F(3), NONE, Macro(Record)),
+            (KeyCode::F(4), NONE, Macro(Play)),
+            (KeyCode::Char('/'), CTRL, Lines(ToggleComment)),
+            (KeyCode::Up, ALT, Lines(MoveUp)),
+            (KeyCode::Down, ALT, Lines(MoveDown)),
+            (KeyCode::Down, ALT_SHIFT, Lines(Duplicate)),
+            (KeyCode::Char('j'), ALT, Lines(Join)),
+            (KeyCode::Char('k'), CTRL, Lines(DeleteLines)),
+            (KeyCode::Char('q'), ALT, Lines(Reflow)),
+            (KeyCode::Char('b'), ALT, Block(ToggleSelection)),
+            (KeyCode::Char('c'), ALT, Block(CopyBlock)),
+            (KeyCode::Char('x'), ALT, Block(CutBlock)),
+            (KeyCode::Char('v'), ALT, Block(PasteBlock)),
+        ]);
+        let search = Keymap::from_defaults(&[
+            (KeyCode::Enter, NONE, Prompt(Accept)),
+            (KeyCode::Esc, NONE, System(Dismiss)),
+            (KeyCode::Backspace, NONE, Edit(DeleteBackward)),
+            (KeyCode::Right, NONE, Prompt(Next)),
+            (KeyCode::Down, NONE, Prompt(Next)),
+            (KeyCode::Left, NONE, Prompt(Previous)),
+            (KeyCode::Up, NONE, Prompt(Previous)),
+        ]);
+        let save = Keymap::from_defaults(&[
+            (KeyCode::Enter, NONE, Prompt(Accept)),
+            (KeyCode::Esc, NONE, System(Dismiss)),
+            (KeyCode::Backspace, NONE, Edit(DeleteBackward)),
+        ]);
+        let command = Keymap::from_defaults(&[
+            (KeyCode::Enter, NONE, Prompt(Accept)),
+            (KeyCode::Esc, NONE, System(Dismiss)),
+            (KeyCode::Backspace, NONE, Edit(DeleteBackward)),
+            (KeyCode::Tab, NONE, Prompt(Complete)),
+        ]);
+        let picker = Keymap::from_defaults(&[
+            (KeyCode::Enter, NONE, Prompt(Accept)),
+            (KeyCode::Esc, NONE, System(Dismiss)),
+            (KeyCode::Backspace, NONE, Edit(DeleteBackward)),
+            (KeyCode::Down, NONE, Prompt(Next)),
+            (KeyCode::Char('n'), CTRL, Prompt(Next)),
+            (KeyCode::Up, NONE, Prompt(Previous)),
+            (KeyCode::Char('p'), CTRL, Prompt(Previous)),
+        ]);
+        let confirm = Keymap::from_defaults(&[
+            (KeyCode::Enter, NONE, System(Dismiss)),
+            (KeyCode::Esc, NONE, System(Dismiss)),
+        ]);
+        Self {
+            normal,
+            search,
+            save,
+            command,
+            picker,
+            confirm,
+        }
+    }
+}
@@ -3,7 +3,7 @@ use std::{
     ops::{Deref, Range},
 };
 
-use super::annotatedstring::{AnnotatedString, AnnotationType, Annotation};
+use super::annotatedstring::{AnnotatedString, AnnotationType};
 
 use unicode_segmentation::UnicodeSegmentation;
 use unicode_width::UnicodeWidthStr;
@@ -17,14 +17,7 @@ pub enum GraphemeWidth {
     Full,
 }
 
-impl GraphemeWidth {
-    const fn saturating_add(self, other: usize) -> usize {
-        match self {
-            Self::Half => other.saturating_add(1),
-            Self::Full => other.saturating_add(2),
-        }
-    }
-}
+pub const DEFAULT_TAB_WIDTH: usize = 4;
 
 #[derive(Clone, Debug)]
 pub struct TextFragment {
@@ -34,11 +27,22 @@ pub struct TextFragment {
     start_byte_idx: usize,
 }
 
-#[derive(Default, Clone)]
+#[derive(Clone)]
 pub struct Line {
     fragments: Vec<TextFragment>,
     //annotated_string: AnnotatedString,
     string: String,
+    tab_width: usize,
+}
+
+impl Default for Line {
+    fn default() -> Self {
+        Self {
+            fragments: Vec::new(),
+            string: String::new(),
+            tab_width: DEFAULT_TAB_WIDTH,
+        }
+    }
 }
 
 impl Line {
@@ -49,8 +53,22 @@ impl Line {
         let line_str = line_str.replace('\u{200D}', "");
         Self { 
             fragments,
-            string: String::from(line_str),
+            string: line_str,
             //annotated_string: AnnotatedString::new(line_str),
+            tab_width: DEFAULT_TAB_WIDTH,
+        }
+    }
+    /// Tabs take up this many columns on screen.
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        self.tab_width = tab_width;
+    }
+    fn fragment_width(&self, fragment: &TextFragment) -> usize {
+        if fragment.grapheme == "\t" {
+            return self.tab_width;
+        }
+        match fragment.rendered_width {
+            GraphemeWidth::Half => 1,
+            GraphemeWidth::Full => 2,
         }
     }
     fn str_to_fragments(line_str: &str) -> Vec<TextFragment> {
@@ -97,19 +115,21 @@ impl Line {
             _ if width > 0 && for_str.trim().is_empty() => Some('␣'),
             _ if width == 0 => {
                 let mut chars = for_str.chars();
-                if let Some(ch) = chars.next() {
-                    if ch.is_control() && chars.next().is_none() {
-                        return Some('▯');
-                    }
+                if let Some(ch) = chars.next()
+                    && ch.is_control()
+                    && chars.next().is_none()
+                {
+                    return Some('▯');
                 }
                 Some('·')
             },
             _ => {
                 let mut chars = for_str.chars();
-                if let Some(ch) = chars.next() {
-                    if ch.is_control() && chars.next().is_none() {
-                        return Some('▯');
-                    }
+                if let Some(ch) = chars.next()
+                    && ch.is_control()
+                    && chars.next().is_none()
+                {
+                    return Some('▯');
                 }
                 None
             }
@@ -152,40 +172,58 @@ impl Line {
         if let Some(fragment) = self.fragments.get(at) {
             let remainder = self.string.split_off(fragment.start_byte_idx);
             self.rebuild_fragments();
-            Self::from(&remainder)
+            let mut new_line = Self::from(&remainder);
+            new_line.set_tab_width(self.tab_width);
+            new_line
         } else {
-            Self::default()
+            let mut new_line = Self::default();
+            new_line.set_tab_width(self.tab_width);
+            new_line
         }
     }
     pub fn get_visible_graphemes(&self, range: Range<GraphemeIdx>) -> String {
+        let mut result = String::new();
+        self.for_each_visible_fragment(range, |_, text| result.push_str(&text));
+        result
+    }
+    /// Calls `visit` with the index and on-screen text of every fragment that
+    /// is at least partly visible within the column `range`.
+    fn for_each_visible_fragment(
+        &self,
+        range: Range<GraphemeIdx>,
+        mut visit: impl FnMut(GraphemeIdx, String),
+    ) {
         if range.start >= range.end {
-            return String::new();
+            return;
         }
 
-        let mut result = String::new();
         let mut current_pos = 0;
 
-        for fragment in &self.fragments {
-            let fragment_end = fragment.rendered_width.saturating_add(current_pos);
+        for (idx, fragment) in self.fragments.iter().enumerate() {
+            let fragment_end = self.fragment_width(fragment).saturating_add(current_pos);
             if current_pos >= range.end {
                 break
             }
             if fragment_end > range.start {
-                if fragment_end > range.end || current_pos < range.start {
-                    result.push('⋯');
+                let visible_width = fragment_end
+                    .min(range.end)
+                    .saturating_sub(current_pos.max(range.start));
+                let text = if fragment.grapheme == "\t" {
+                    // A tab is blank all the way, so it can be cut anywhere.
+                    " ".repeat(visible_width)
+                } else if fragment_end > range.end || current_pos < range.start {
+                    String::from('⋯')
                 } else if let Some(char) = fragment.replacement {
-                    result.push(char);
+                    String::from(char)
                 } else {
-                    result.push_str(&fragment.grapheme);
-                }
+                    fragment.grapheme.clone()
+                };
+                visit(idx, text);
             }
 
             current_pos = fragment_end;
 
         }
-
-        result
-
     }
     pub fn grapheme_count(&self) -> GraphemeIdx {
         self.fragments.len()
@@ -194,21 +232,53 @@ impl Line {
         self.fragments
             .iter()
             .take(grapheme_index)
-            .map(|fragment| match fragment.rendered_width {
-                GraphemeWidth::Half => 1,
-                GraphemeWidth::Full => 2,
-            })
+            .map(|fragment| self.fragment_width(fragment))
             .sum()
     }
     pub fn width(&self) -> GraphemeIdx {
         self.width_until(self.grapheme_count())
     }
-    fn byte_idx_to_grapheme_idx(&self, byte_idx: ByteIdx) -> GraphemeIdx {
+    /// The grapheme shown at column `col`, or the grapheme count for columns
+    /// past the end of the line.
+    pub fn grapheme_idx_at(&self, col: usize) -> GraphemeIdx {
+        let mut end = 0;
+        for (idx, fragment) in self.fragments.iter().enumerate() {
+            end = self.fragment_width(fragment).saturating_add(end);
+            if col < end {
+                return idx;
+            }
+        }
+        self.grapheme_count()
+    }
+    /// The graphemes shown within the columns `cols`, including those that
+    /// are only partly inside, such as a wide character or a tab.
+    pub fn grapheme_range_in_cols(&self, cols: Range<usize>) -> Range<GraphemeIdx> {
+        let start = self.grapheme_idx_at(cols.start);
+        if cols.is_empty() {
+            return start..start;
+        }
+        let end = self
+            .grapheme_idx_at(cols.end.saturating_sub(1))
+            .saturating_add(1)
+            .min(self.grapheme_count());
+        start..end.max(start)
+    }
+    /// How many columns the grapheme at `idx` takes up, or 1 past the end of
+    /// the line.
+    pub fn width_at(&self, idx: GraphemeIdx) -> usize {
+        self.fragments
+            .get(idx)
+            .map_or(1, |fragment| self.fragment_width(fragment))
+    }
+    pub fn byte_idx_to_grapheme_idx(&self, byte_idx: ByteIdx) -> GraphemeIdx {
         debug_assert!(byte_idx <= self.string.len());
+        if byte_idx == self.string.len() {
+            return self.grapheme_count();
+        }
         self.fragments
             .iter()
             .position(|fragment| fragment.start_byte_idx >= byte_idx)
-            .map_or_else(|| {
+            .unwrap_or_else(|| {
                 #[cfg(debug_assertions)]
                 {
                     panic!("Fragment not found for byte index: {byte_idx:?}");
@@ -217,7 +287,7 @@ impl Line {
                 {
                     0
                 }
-            }, |grapheme_idx| grapheme_idx)
+            })
     }
     fn grapheme_idx_to_byte_idx(&self, grapheme_idx: GraphemeIdx) -> ByteIdx {
         debug_assert!(grapheme_idx <= self.grapheme_count());
@@ -237,56 +307,77 @@ impl Line {
                 }
             }, |fragment| fragment.start_byte_idx)
     }
-    pub fn get_annotated_visible_substr(&self, range: Range<GraphemeIdx>, query: &str) -> AnnotatedString {
-        let sub_str = self.get_visible_graphemes(range);
-        //let sub_str_fragments = Self::string_to_fragments();
-
-        let mut annotated_string = AnnotatedString::new(&sub_str);
-        let search_results = self.search(&query);
-
-        let mut last_index = 0;
-        
-        for (index, fragment) in self.fragments.iter().enumerate() {
-            log::debug!("Fragment {index}: {:?}", fragment);
-        }
-        for annotation in search_results {
-
-            let annotation_byte_idx = self.grapheme_idx_to_byte_idx(annotation);
-
-            if last_index < annotation_byte_idx {
-                annotated_string.add_annotation(Annotation::new(
-                    last_index,
-                    annotation_byte_idx - 1,
-                    AnnotationType::None
-                ));
-            }
-
-            // we add an annotation to take care of the current bit.
-            annotated_string.add_annotation(Annotation::new(
-                annotation_byte_idx, 
-                annotation_byte_idx + query.len() - 1,
+    /// The visible part of the line with search matches for `query`, the
+    /// graphemes in `selection`, those under `carets`, the `brackets` and the
+    /// `trailing` whitespace annotated. Carets win over the selection, which
+    /// wins over brackets, then matches, then trailing whitespace. A caret at
+    /// the end of the line shows as a blank cell.
+    pub fn get_annotated_visible_substr(
+        &self,
+        range: Range<GraphemeIdx>,
+        query: Option<&str>,
+        selection: Option<&Range<GraphemeIdx>>,
+        carets: &[GraphemeIdx],
+        brackets: &[GraphemeIdx],
+        trailing: Option<&Range<GraphemeIdx>>,
+    ) -> AnnotatedString {
+        let matches = query.map(|query| self.match_ranges(query)).unwrap_or_default();
+
+        let mut annotated_string = AnnotatedString::default();
+        let end_col = self.width();
+        let shows_end = (range.start..range.end).contains(&end_col);
+        self.for_each_visible_fragment(range, |idx, text| {
+            let annotation_type = if carets.contains(&idx) {
+                AnnotationType::Caret
+            } else if selection.is_some_and(|selection| selection.contains(&idx)) {
+                AnnotationType::Selection
+            } else if brackets.contains(&idx) {
+                AnnotationType::MatchingBracket
+            } else if matches.iter().any(|matched| matched.contains(&idx)) {
                 AnnotationType::Highlight
-            ));
-            
-            last_index = annotation_byte_idx + query.len();
-        }
-
-        if last_index < sub_str.len().saturating_sub(1) {
-            annotated_string.add_annotation(Annotation::new(
-                last_index,
-                sub_str.len().saturating_sub(1), // - last_index).saturating_sub(1), //query.len() - 1,
+            } else if trailing.is_some_and(|trailing| trailing.contains(&idx)) {
+                AnnotationType::TrailingWhitespace
+            } else {
                 AnnotationType::None
-            ));
+            };
+            annotated_string.push(&text, annotation_type);
+        });
+        if shows_end && carets.contains(&self.grapheme_count()) {
+            annotated_string.push(" ", AnnotationType::Caret);
         }
-
         annotated_string
     }
-    pub fn search(&self, query: &str) -> Vec<GraphemeIdx> {
+    /// The graphemes of the whitespace at the end of the line.
+    pub fn trailing_whitespace(&self) -> Range<GraphemeIdx> {
+        self.byte_idx_to_grapheme_idx(self.string.trim_end().len())..self.grapheme_count()
+    }
+    /// The grapheme ranges covered by each occurrence of `query`.
+    pub fn match_ranges(&self, query: &str) -> Vec<Range<GraphemeIdx>> {
+        if query.is_empty() {
+            return Vec::new();
+        }
         self.string
             .match_indices(query)
-            .map(|(index, _)| self.byte_idx_to_grapheme_idx(index))
+            .map(|(byte_idx, matched)| {
+                let end = byte_idx.saturating_add(matched.len());
+                self.byte_idx_to_grapheme_idx(byte_idx)..self.byte_idx_to_grapheme_idx(end)
+            })
             .collect()
     }
+    pub fn grapheme(&self, idx: GraphemeIdx) -> Option<&str> {
+        self.fragments.get(idx).map(|fragment| fragment.grapheme.as_str())
+    }
+    /// The text of the graphemes in `range`.
+    pub fn slice(&self, range: Range<GraphemeIdx>) -> &str {
+        let byte_idx = |grapheme_idx: GraphemeIdx| {
+            self.fragments
+                .get(grapheme_idx)
+                .map_or(self.string.len(), |fragment| fragment.start_byte_idx)
+        };
+        self.string
+            .get(byte_idx(range.start)..byte_idx(range.end))
+            .unwrap_or_default()
+    }
     pub fn search_forward(&self, query: &str, from_grapheme_idx: GraphemeIdx) -> Option<GraphemeIdx> {
         debug_assert!(from_grapheme_idx <= self.grapheme_count());
         if from_grapheme_idx == self.grapheme_count() {
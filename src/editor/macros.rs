@@ -0,0 +1,151 @@
+use std::{
+    collections::BTreeMap,
+    fs::{create_dir_all, read_to_string, write},
+    io::ErrorKind,
+    mem::take,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::command::{Command, Edit, System};
+
+const MACROS_FILE: &str = "macros.toml";
+/// Saved steps that type text start with this, the others are command names.
+const TEXT_PREFIX: &str = "text:";
+
+/// The file macros are saved in, with each macro as a list of steps.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MacrosFile {
+    #[serde(default)]
+    macros: BTreeMap<String, Vec<String>>,
+}
+
+/// Commands recorded into registers named by a letter or digit, to be played
+/// back later.
+#[derive(Default)]
+pub struct Macros {
+    registers: BTreeMap<char, Vec<Command>>,
+    recording: Option<(char, Vec<Command>)>,
+    last_played: Option<char>,
+}
+
+impl Macros {
+    /// Reads the macros saved in `config_dir`. A missing file yields none.
+    pub fn load(config_dir: Option<&Path>) -> Result<Self, String> {
+        let Some(path) = config_dir.map(|dir| dir.join(MACROS_FILE)) else {
+            return Ok(Self::default());
+        };
+        match read_to_string(&path) {
+            Ok(source) => Self::from_toml(&source).map_err(|err| format!("{MACROS_FILE}: {err}")),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(format!("{}: {err}", path.display())),
+        }
+    }
+    fn from_toml(source: &str) -> Result<Self, String> {
+        let file: MacrosFile = toml::from_str(source).map_err(|err| err.message().to_string())?;
+        let mut registers = BTreeMap::new();
+        for (name, steps) in file.macros {
+            let mut chars = name.chars();
+            let register = match (chars.next(), chars.next()) {
+                (Some(register), None) if Self::is_register(register) => register,
+                _ => return Err(format!("Invalid register '{name}'")),
+            };
+            let commands = steps
+                .iter()
+                .map(|step| Self::parse_step(step).ok_or_else(|| format!("macros.{name}: Unknown command '{step}'")))
+                .collect::<Result<Vec<_>, _>>()?;
+            registers.insert(register, commands.concat());
+        }
+        Ok(Self {
+            registers,
+            ..Self::default()
+        })
+    }
+    fn parse_step(step: &str) -> Option<Vec<Command>> {
+        match step.strip_prefix(TEXT_PREFIX) {
+            Some(text) => Some(text.chars().map(|character| Command::Edit(Edit::Insert(character))).collect()),
+            None => Command::from_name(step).map(|command| vec![command]),
+        }
+    }
+    /// Writes every macro to `config_dir`, and returns where.
+    pub fn save(&self, config_dir: Option<&Path>) -> Result<PathBuf, String> {
+        let path = config_dir
+            .map(|dir| dir.join(MACROS_FILE))
+            .ok_or_else(|| String::from("No config directory"))?;
+        let file = MacrosFile {
+            macros: self
+                .registers
+                .iter()
+                .map(|(register, commands)| (register.to_string(), Self::steps(commands)))
+                .collect(),
+        };
+        let source = toml::to_string(&file).map_err(|err| err.to_string())?;
+        if let Some(dir) = path.parent() {
+            create_dir_all(dir).map_err(|err| format!("{}: {err}", dir.display()))?;
+        }
+        write(&path, source).map_err(|err| format!("{}: {err}", path.display()))?;
+        Ok(path)
+    }
+    /// The commands as saved: runs of typed characters become one text step.
+    fn steps(commands: &[Command]) -> Vec<String> {
+        let mut steps: Vec<String> = Vec::new();
+        let mut text = String::new();
+        for command in commands {
+            match (command, command.name()) {
+                (Command::Edit(Edit::Insert(character)), None) => text.push(*character),
+                (_, name) => {
+                    if !text.is_empty() {
+                        steps.push(format!("{TEXT_PREFIX}{}", take(&mut text)));
+                    }
+                    steps.extend(name.map(String::from));
+                }
+            }
+        }
+        if !text.is_empty() {
+            steps.push(format!("{TEXT_PREFIX}{text}"));
+        }
+        steps
+    }
+    pub fn is_register(register: char) -> bool {
+        register.is_ascii_alphanumeric()
+    }
+    pub fn start_recording(&mut self, register: char) {
+        self.recording = Some((register, Vec::new()));
+    }
+    pub fn recording(&self) -> Option<char> {
+        self.recording.as_ref().map(|(register, _)| *register)
+    }
+    pub fn record(&mut self, command: Command) {
+        if let Some((_, commands)) = &mut self.recording
+            && !matches!(command, Command::System(System::Resize(_)))
+        {
+            commands.push(command);
+        }
+    }
+    /// Stores what was recorded, leaving out `stop`, the command that ended
+    /// the recording. Returns the register and the number of commands.
+    pub fn stop_recording(&mut self, stop: Command) -> Option<(char, usize)> {
+        let (register, mut commands) = self.recording.take()?;
+        if commands.last() == Some(&stop) {
+            commands.pop();
+        }
+        let len = commands.len();
+        self.registers.insert(register, commands);
+        Some((register, len))
+    }
+    /// The commands in `register`, or in the one played last for `None`.
+    pub fn play(&mut self, register: Option<char>) -> Result<Vec<Command>, String> {
+        let register = register
+            .or(self.last_played)
+            .ok_or_else(|| String::from("No macro played yet"))?;
+        let commands = self
+            .registers
+            .get(&register)
+            .filter(|commands| !commands.is_empty())
+            .ok_or_else(|| format!("Register {register} is empty"))?;
+        self.last_played = Some(register);
+        Ok(commands.clone())
+    }
+}
@@ -3,7 +3,7 @@ use std::{
     time::{Duration, Instant}
 };
 
-use super::{Size, Terminal, UIComponent};
+use super::{Screen, Size, Theme, ThemeElement, UIComponent};
 
 const DEFAULT_DURATION: Duration = Duration::new(5, 0);
 
@@ -22,19 +22,40 @@ impl Default for Message {
 }
 
 impl Message {
-    fn is_expired(&self) -> bool {
-        Instant::now().duration_since(self.time) > DEFAULT_DURATION
+    fn expires_at(&self, duration: Duration) -> Instant {
+        self.time.checked_add(duration).unwrap_or(self.time)
+    }
+    fn is_expired(&self, duration: Duration) -> bool {
+        Instant::now() > self.expires_at(duration)
     }
 }
 
-#[derive(Default)]
 pub struct MessageBar {
     current_message: Message,
     needs_redraw: bool,
     cleared_after_expiry: bool,
+    duration: Duration,
+}
+
+impl Default for MessageBar {
+    fn default() -> Self {
+        Self {
+            current_message: Message::default(),
+            needs_redraw: false,
+            cleared_after_expiry: false,
+            duration: DEFAULT_DURATION,
+        }
+    }
 }
 
 impl MessageBar {
+    /// How long messages stay visible.
+    pub fn set_duration(&mut self, duration: Duration) {
+        self.duration = duration;
+    }
+    fn is_expired(&self) -> bool {
+        self.current_message.is_expired(self.duration)
+    }
     pub fn update_message(&mut self, new_message: &str) {
         self.current_message = Message {
             text: new_message.to_string(),
@@ -44,6 +65,10 @@ impl MessageBar {
         self.cleared_after_expiry = false;
         self.set_needs_redraw(true);
     }
+    /// The moment the current message has to be cleared from the screen, if any.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        (!self.cleared_after_expiry).then(|| self.current_message.expires_at(self.duration))
+    }
 }
 
 impl UIComponent for MessageBar {
@@ -51,20 +76,21 @@ impl UIComponent for MessageBar {
         self.needs_redraw = value;
     }
     fn needs_redraw(&self) -> bool {
-       (!self.cleared_after_expiry && self.current_message.is_expired()) || self.needs_redraw 
+       (!self.cleared_after_expiry && self.is_expired()) || self.needs_redraw 
     }
     fn set_size(&mut self, _: Size) {}
-    fn draw(&mut self, origin: usize) -> Result<(), Error> {
-        if self.current_message.is_expired() {
+    fn draw(&mut self, screen: &mut Screen, theme: &Theme, origin: usize) -> Result<(), Error> {
+        if self.is_expired() {
             self.cleared_after_expiry = true;
         }
 
-        let message = if self.current_message.is_expired() {
+        let message = if self.is_expired() {
             ""
         } else {
             &self.current_message.text
         };
 
-        Terminal::print_row(origin, message)
+        screen.print_row(origin, message, theme.style(ThemeElement::MessageBar));
+        Ok(())
     }
 }
@@ -0,0 +1,12 @@
+use std::{env, path::PathBuf};
+
+use super::NAME;
+
+/// `$XDG_CONFIG_HOME/hecto`, falling back to `~/.config/hecto`.
+pub fn config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join(NAME))
+}
@@ -0,0 +1,250 @@
+use std::{cmp::Reverse, io::Error};
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use super::{Screen, Size, Theme, ThemeElement, UIComponent};
+
+/// The most rows the list takes up, however many items match.
+const MAX_ROWS: usize = 10;
+
+const MATCH_SCORE: i64 = 16;
+/// Matches at the start of a word, e.g. the `t` in `cycle_theme`.
+const WORD_START_BONUS: i64 = 8;
+/// Matches right after the previous one.
+const CONSECUTIVE_BONUS: i64 = 12;
+/// Subtracted for every skipped character between two matches.
+const GAP_PENALTY: i64 = 1;
+
+pub struct PickerItem<T> {
+    pub label: String,
+    /// Shown right aligned, e.g. the key bound to a command.
+    pub detail: String,
+    pub value: T,
+}
+
+struct Match {
+    item_idx: usize,
+    /// The indices of the label's chars that matched the query.
+    positions: Vec<usize>,
+}
+
+/// A popup list of items, narrowed down by fuzzy matching as the user types.
+pub struct Picker<T> {
+    items: Vec<PickerItem<T>>,
+    matches: Vec<Match>,
+    selected: usize,
+    scroll_offset: usize,
+    needs_redraw: bool,
+    size: Size,
+}
+
+impl<T> Default for Picker<T> {
+    fn default() -> Self {
+        Self {
+            items: Vec::new(),
+            matches: Vec::new(),
+            selected: 0,
+            scroll_offset: 0,
+            needs_redraw: false,
+            size: Size::default(),
+        }
+    }
+}
+
+impl<T> Picker<T> {
+    /// Replaces the items, and shows all of them.
+    pub fn set_items(&mut self, items: Vec<PickerItem<T>>) {
+        self.items = items;
+        self.filter("");
+    }
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.matches.clear();
+        self.set_needs_redraw(true);
+    }
+    /// Keeps the items matching `query`, best first, and selects the first.
+    pub fn filter(&mut self, query: &str) {
+        let mut scored: Vec<(i64, Match)> = self
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(item_idx, item)| {
+                let (score, positions) = fuzzy_match(query, &item.label)?;
+                Some((score, Match { item_idx, positions }))
+            })
+            .collect();
+        // The sort is stable, so equally good matches keep the item order.
+        scored.sort_by_key(|(score, _)| Reverse(*score));
+        self.matches = scored.into_iter().map(|(_, matched)| matched).collect();
+        self.selected = 0;
+        self.scroll_offset = 0;
+        self.set_needs_redraw(true);
+    }
+    pub fn select_next(&mut self) {
+        let next = self.selected.saturating_add(1);
+        self.select(if next < self.matches.len() { next } else { 0 });
+    }
+    pub fn select_previous(&mut self) {
+        let previous = self
+            .selected
+            .checked_sub(1)
+            .unwrap_or_else(|| self.matches.len().saturating_sub(1));
+        self.select(previous);
+    }
+    fn select(&mut self, idx: usize) {
+        self.selected = idx;
+        let height = self.height();
+        if self.selected < self.scroll_offset {
+            self.scroll_offset = self.selected;
+        } else if self.selected >= self.scroll_offset.saturating_add(height) {
+            self.scroll_offset = self.selected.saturating_add(1).saturating_sub(height);
+        }
+        self.set_needs_redraw(true);
+    }
+    pub fn selected(&self) -> Option<&T> {
+        self.matches
+            .get(self.selected)
+            .and_then(|matched| self.items.get(matched.item_idx))
+            .map(|item| &item.value)
+    }
+    /// The rows the list needs on screen.
+    pub fn height(&self) -> usize {
+        self.matches.len().min(MAX_ROWS).min(self.size.height)
+    }
+    /// Renders the list so that it ends right above `bottom_row`, and returns
+    /// the row it starts at.
+    pub fn render_above(&mut self, screen: &mut Screen, theme: &Theme, bottom_row: usize) -> usize {
+        let origin_row = bottom_row.saturating_sub(self.height());
+        self.render(screen, theme, origin_row);
+        origin_row
+    }
+}
+
+impl<T> UIComponent for Picker<T> {
+    fn set_needs_redraw(&mut self, value: bool) {
+        self.needs_redraw = value;
+    }
+    fn needs_redraw(&self) -> bool {
+        self.needs_redraw
+    }
+    fn set_size(&mut self, size: Size) {
+        self.size = size;
+        self.select(self.selected);
+    }
+    fn draw(&mut self, screen: &mut Screen, theme: &Theme, origin_row: usize) -> Result<(), Error> {
+        let visible = self
+            .matches
+            .iter()
+            .enumerate()
+            .skip(self.scroll_offset)
+            .take(self.height());
+        for (row, (idx, matched)) in (origin_row..).zip(visible) {
+            let Some(item) = self.items.get(matched.item_idx) else {
+                continue;
+            };
+            let style = theme.style(if idx == self.selected {
+                ThemeElement::PopupSelection
+            } else {
+                ThemeElement::Popup
+            });
+            let match_style = theme.style(ThemeElement::PopupMatch).or(style);
+            screen.clear_row(row, style);
+
+            let mut col = 1;
+            let mut char_idx: usize = 0;
+            for grapheme in item.label.graphemes(true) {
+                let char_count = grapheme.chars().count();
+                let is_match = matched
+                    .positions
+                    .iter()
+                    .any(|position| (char_idx..char_idx.saturating_add(char_count)).contains(position));
+                col = screen.print_at(row, col, grapheme, if is_match { match_style } else { style });
+                char_idx = char_idx.saturating_add(char_count);
+            }
+
+            let detail_col = self
+                .size
+                .width
+                .saturating_sub(item.detail.width().saturating_add(1));
+            if !item.detail.is_empty() && detail_col > col {
+                screen.print_at(row, detail_col, &item.detail, style);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Matches the chars of `query` in order anywhere in `candidate`, ignoring
+/// case. Returns a score, higher for matches that are consecutive or start
+/// words, along with the indices of the matched chars. An empty query
+/// matches everything equally.
+#[allow(clippy::arithmetic_side_effects)]
+fn fuzzy_match(query: &str, candidate: &str) -> Option<(i64, Vec<usize>)> {
+    let query: Vec<char> = query.chars().flat_map(char::to_lowercase).collect();
+    let chars: Vec<char> = candidate.chars().collect();
+    if query.is_empty() {
+        return Some((0, Vec::new()));
+    }
+    let is_word_start = |idx: usize| match idx.checked_sub(1).and_then(|previous| chars.get(previous)) {
+        None => true,
+        Some(previous) => {
+            !previous.is_alphanumeric()
+                || (previous.is_lowercase() && chars.get(idx).is_some_and(|ch| ch.is_uppercase()))
+        }
+    };
+    let offset = |idx: usize| i64::try_from(idx).unwrap_or(i64::from(u32::MAX));
+
+    // scores[i][j] is the best score for the first i + 1 query chars with
+    // the last one matched at char j, along with where the one before it
+    // was matched.
+    let mut scores: Vec<Vec<Option<(i64, usize)>>> = Vec::with_capacity(query.len());
+    for query_char in &query {
+        let previous_row = scores.last();
+        let mut row = Vec::with_capacity(chars.len());
+        // The best match of the previous query char before the current
+        // char, scored as if the gap to the current char were free.
+        let mut best_before: Option<(i64, usize)> = None;
+        for (idx, ch) in chars.iter().enumerate() {
+            let bonus = MATCH_SCORE + if is_word_start(idx) { WORD_START_BONUS } else { 0 };
+            let score = if !ch.to_lowercase().eq([*query_char]) {
+                None
+            } else if let Some(previous_row) = previous_row {
+                let after_gap = best_before.map(|(score, from)| {
+                    (score - (offset(idx) - 1) * GAP_PENALTY + bonus, from)
+                });
+                let consecutive = idx
+                    .checked_sub(1)
+                    .and_then(|previous| previous_row.get(previous).copied().flatten())
+                    .map(|(score, _)| (score + bonus + CONSECUTIVE_BONUS, idx - 1));
+                after_gap.max(consecutive)
+            } else {
+                // Matches further into the candidate start out a bit lower.
+                Some((bonus - offset(idx).min(MATCH_SCORE) * GAP_PENALTY, idx))
+            };
+            row.push(score);
+
+            if let Some((score, _)) = previous_row.and_then(|previous_row| previous_row.get(idx).copied().flatten()) {
+                let candidate = score + offset(idx) * GAP_PENALTY;
+                if best_before.is_none_or(|(best, _)| best < candidate) {
+                    best_before = Some((candidate, idx));
+                }
+            }
+        }
+        scores.push(row);
+    }
+
+    let (mut idx, score) = scores
+        .last()?
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, cell)| cell.map(|(score, _)| (idx, score)))
+        .max_by_key(|(idx, score)| (*score, Reverse(*idx)))?;
+    let mut positions = Vec::with_capacity(query.len());
+    for row in scores.iter().rev() {
+        positions.push(idx);
+        idx = row.get(idx).copied().flatten().map_or(idx, |(_, from)| from);
+    }
+    positions.reverse();
+    Some((score, positions))
+}
@@ -1,7 +1,7 @@
 pub type Row = usize;
 pub type Col = usize;
 
-#[derive(Copy, Clone, Default)]
+#[derive(Copy, Clone, Default, Eq, PartialEq, Debug)]
 pub struct Position {
     pub col: Col,
     pub row: Row,
@@ -0,0 +1,384 @@
+use std::{
+    mem::take,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    command::{
+        self,
+        Command::{self, Edit, Prompt, System},
+        Edit::Insert,
+        System::{CommandPalette, Dismiss},
+    },
+    excommand, filefinder,
+    macros::Macros,
+    picker::PickerItem,
+    Editor, UIComponent,
+};
+
+#[derive(Eq, PartialEq, Default)]
+pub enum PromptType {
+    Search,
+    Save,
+    Command,
+    Palette,
+    OpenFile,
+    /// Asks whether to go ahead with the `pending` action.
+    Confirm,
+    /// The name for a new entry in the directory browser.
+    NewEntry,
+    /// The new name for the selected entry in the directory browser.
+    Rename,
+    /// The pattern to search the project for.
+    Grep,
+    /// The register to record a macro into.
+    RecordMacro,
+    /// The register of the macro to play, with an optional count in front.
+    PlayMacro,
+    /// The register of the macro to play on every selected line.
+    ApplyMacro,
+    #[default]
+    None,
+}
+
+impl PromptType {
+    pub fn is_none(&self) -> bool {
+        *self == Self::None
+    }
+    /// Prompts that show a list above the bars.
+    pub fn has_popup(&self) -> bool {
+        matches!(self, Self::Palette | Self::OpenFile)
+    }
+}
+
+/// Actions that wait for a yes in the confirm prompt.
+pub enum PendingAction {
+    /// Opening a file, which drops the unsaved changes, and going to a line
+    /// in it.
+    Open(String, Option<usize>),
+    Delete(PathBuf),
+}
+
+/// The commands a prompt responds to. Any other command does nothing while
+/// a prompt is open.
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum PromptCommand {
+    Dismiss,
+    Accept,
+    Next,
+    Previous,
+    Complete,
+    Edit(command::Edit),
+}
+
+impl PromptCommand {
+    fn from_command(command: Command) -> Option<Self> {
+        match command {
+            System(Dismiss) => Some(Self::Dismiss),
+            Prompt(command::Prompt::Accept) => Some(Self::Accept),
+            Prompt(command::Prompt::Next) => Some(Self::Next),
+            Prompt(command::Prompt::Previous) => Some(Self::Previous),
+            Prompt(command::Prompt::Complete) => Some(Self::Complete),
+            Edit(edit) => Some(Self::Edit(edit)),
+            _ => None,
+        }
+    }
+}
+
+impl Editor {
+    pub(super) fn in_prompt(&self) -> bool {
+        !self.prompt_type.is_none()
+    }
+    pub(super) fn set_prompt(&mut self, prompt_type: PromptType) {
+        match prompt_type {
+            PromptType::None => {
+                if self.prompt_type.has_popup() {
+                    self.palette.clear();
+                    self.file_finder.clear();
+                    self.file_preview.set_path(None);
+                    self.view.set_needs_redraw(true);
+                    if let Some(listing) = &mut self.listing {
+                        listing.set_needs_redraw(true);
+                    }
+                }
+                self.message_bar.set_needs_redraw(true);
+            }
+            PromptType::OpenFile => {
+                self.file_finder.set_items(
+                    filefinder::list_files(self.root_dir.as_deref().unwrap_or(Path::new(".")))
+                        .into_iter()
+                        .map(|file_name| PickerItem {
+                            label: file_name.clone(),
+                            detail: String::new(),
+                            value: file_name,
+                        })
+                        .collect(),
+                );
+                self.file_preview.set_path(self.file_finder.selected().map(|file_name| self.in_root_dir(file_name)).as_deref());
+                self.command_bar.set_prompt("Open: ");
+            }
+            PromptType::Confirm => {
+                let question = match &self.pending {
+                    Some(PendingAction::Delete(path)) => format!(
+                        "Delete {}? (y/n) ",
+                        path.file_name().unwrap_or_default().to_string_lossy()
+                    ),
+                    Some(PendingAction::Open(..)) | None => String::from("Discard unsaved changes? (y/n) "),
+                };
+                self.command_bar.set_prompt(&question);
+            }
+            PromptType::NewEntry => self.command_bar.set_prompt("New file (end with / for a directory): "),
+            PromptType::Rename => self.command_bar.set_prompt("Rename to: "),
+            PromptType::Grep => self.command_bar.set_prompt("Grep: "),
+            PromptType::RecordMacro => self.command_bar.set_prompt("Record macro into register: "),
+            PromptType::PlayMacro => self.command_bar.set_prompt("Play macro ([count]register): "),
+            PromptType::ApplyMacro => self.command_bar.set_prompt("Play macro on each line: "),
+            PromptType::Palette => {
+                self.palette.set_items(self.palette_items());
+                self.command_bar.set_prompt("> ");
+            }
+            PromptType::Save => self.command_bar.set_prompt("Save as: "),
+            PromptType::Command => self.command_bar.set_prompt(":"),
+            PromptType::Search => {
+                self.view.enter_search();
+                self.command_bar.set_prompt("Search (Esc to cancel, Arrows to navigate): ");
+            },
+        }
+        self.command_bar.clear_prompt();
+        self.prompt_type = prompt_type;
+    }
+    /// Hands `command` to the open prompt. The confirm prompt takes any
+    /// command as an answer; the others ignore what they don't respond to.
+    pub(super) fn process_command_during_prompt(&mut self, command: Command) {
+        let prompt_command = PromptCommand::from_command(command);
+        if self.prompt_type == PromptType::Confirm {
+            self.process_command_during_confirm(prompt_command);
+            return;
+        }
+        let Some(prompt_command) = prompt_command else {
+            return;
+        };
+        match self.prompt_type {
+            PromptType::Search => self.process_command_during_search(prompt_command),
+            PromptType::Save => self.process_command_during_save(prompt_command),
+            PromptType::Command => self.process_command_during_command_line(prompt_command),
+            PromptType::Palette => self.process_command_during_palette(prompt_command),
+            PromptType::OpenFile => self.process_command_during_file_finder(prompt_command),
+            PromptType::NewEntry | PromptType::Rename => self.process_command_during_entry_name(prompt_command),
+            PromptType::Grep => self.process_command_during_grep(prompt_command),
+            PromptType::RecordMacro | PromptType::PlayMacro | PromptType::ApplyMacro => {
+                self.process_command_during_macro_prompt(prompt_command);
+            }
+            PromptType::Confirm | PromptType::None => {}
+        }
+    }
+    fn process_command_during_save(&mut self, command: PromptCommand) {
+        match command {
+            PromptCommand::Dismiss => {
+                self.set_prompt(PromptType::None);
+                self.update_message("Save aborted.");
+            }
+            PromptCommand::Accept => {
+                let file_name = self.command_bar.value();
+                self.save(Some(&file_name));
+                self.set_prompt(PromptType::None);
+            }
+            PromptCommand::Edit(edit_command) => self.command_bar.handle_edit_command(edit_command),
+            PromptCommand::Next | PromptCommand::Previous | PromptCommand::Complete => {}
+        }
+    }
+    fn process_command_during_search(&mut self, command: PromptCommand) {
+        match command {
+            PromptCommand::Dismiss => {
+                self.set_prompt(PromptType::None);
+                self.view.dismiss_search();
+            }
+            PromptCommand::Accept => {
+                self.set_prompt(PromptType::None);
+                self.view.exit_search();
+            }
+            PromptCommand::Edit(edit_command) => {
+                self.command_bar.handle_edit_command(edit_command);
+                let query = self.command_bar.value();
+                self.view.search(&query);
+            }
+            PromptCommand::Next => self.view.search_next(),
+            PromptCommand::Previous => self.view.search_prev(),
+            PromptCommand::Complete => {}
+        }
+    }
+    fn process_command_during_command_line(&mut self, command: PromptCommand) {
+        if command != PromptCommand::Complete {
+            self.completion = None;
+        }
+        match command {
+            PromptCommand::Dismiss => self.set_prompt(PromptType::None),
+            PromptCommand::Accept => {
+                let input = self.command_bar.value();
+                self.set_prompt(PromptType::None);
+                if !input.trim().is_empty() {
+                    self.run_ex_command(&input);
+                }
+            }
+            PromptCommand::Complete => self.complete_command_line(),
+            PromptCommand::Edit(edit_command) => self.command_bar.handle_edit_command(edit_command),
+            PromptCommand::Next | PromptCommand::Previous => {}
+        }
+    }
+    /// The first Tab completes to the first candidate, each further Tab moves
+    /// on to the next one.
+    fn complete_command_line(&mut self) {
+        let (candidates, idx) = match self.completion.take() {
+            Some((candidates, idx)) => {
+                let next = idx.saturating_add(1);
+                let idx = if next < candidates.len() { next } else { 0 };
+                (candidates, idx)
+            }
+            None => (excommand::complete(&self.command_bar.value()), 0),
+        };
+        if let Some(candidate) = candidates.get(idx) {
+            self.command_bar.set_value(candidate);
+            self.completion = Some((candidates, idx));
+        }
+    }
+    fn process_command_during_palette(&mut self, command: PromptCommand) {
+        match command {
+            PromptCommand::Dismiss => self.set_prompt(PromptType::None),
+            PromptCommand::Accept => {
+                let selected = self.palette.selected().copied();
+                self.set_prompt(PromptType::None);
+                if let Some(command) = selected {
+                    self.process_command(command);
+                }
+            }
+            PromptCommand::Next => self.palette.select_next(),
+            PromptCommand::Previous => self.palette.select_previous(),
+            PromptCommand::Edit(edit_command) => {
+                self.command_bar.handle_edit_command(edit_command);
+                self.palette.filter(&self.command_bar.value());
+            }
+            PromptCommand::Complete => {}
+        }
+    }
+    /// Every command that makes sense outside of a prompt, with its keys.
+    fn palette_items(&self) -> Vec<PickerItem<Command>> {
+        Command::all_named()
+            .filter(|(_, command)| !matches!(command, Prompt(_) | System(CommandPalette)))
+            .map(|(name, command)| PickerItem {
+                label: name.replace('_', " "),
+                detail: self.config.keymaps.normal.keys_for(command).unwrap_or_default(),
+                value: command,
+            })
+            .collect()
+    }
+    fn process_command_during_file_finder(&mut self, command: PromptCommand) {
+        match command {
+            PromptCommand::Dismiss => self.set_prompt(PromptType::None),
+            PromptCommand::Accept => {
+                let selected = self.file_finder.selected().map(|file_name| self.in_root_dir(file_name));
+                self.set_prompt(PromptType::None);
+                if let Some(path) = selected {
+                    self.request_open(path.to_string_lossy().into_owned(), None);
+                }
+            }
+            PromptCommand::Next => self.file_finder.select_next(),
+            PromptCommand::Previous => self.file_finder.select_previous(),
+            PromptCommand::Edit(edit_command) => {
+                self.command_bar.handle_edit_command(edit_command);
+                self.file_finder.filter(&self.command_bar.value());
+            }
+            PromptCommand::Complete => {}
+        }
+        self.file_preview.set_path(self.file_finder.selected().map(|file_name| self.in_root_dir(file_name)).as_deref());
+    }
+    /// Typing `y` goes ahead with the pending action, any other key drops it.
+    fn process_command_during_confirm(&mut self, command: Option<PromptCommand>) {
+        let pending = self.pending.take();
+        self.set_prompt(PromptType::None);
+        let confirmed = matches!(command, Some(PromptCommand::Edit(Insert('y' | 'Y'))));
+        match (pending, confirmed) {
+            (Some(PendingAction::Open(file_name, line_idx)), true) => self.open_and_report(&file_name, line_idx),
+            (Some(PendingAction::Delete(path)), true) => self.delete_entry(&path),
+            (Some(PendingAction::Open(..)), false) => self.update_message("Open aborted."),
+            (Some(PendingAction::Delete(_)), false) => self.update_message("Delete aborted."),
+            (None, _) => {}
+        }
+    }
+    fn process_command_during_entry_name(&mut self, command: PromptCommand) {
+        match command {
+            PromptCommand::Dismiss => self.set_prompt(PromptType::None),
+            PromptCommand::Accept => {
+                let name = self.command_bar.value();
+                let prompt_type = take(&mut self.prompt_type);
+                self.set_prompt(PromptType::None);
+                let result = if prompt_type == PromptType::Rename {
+                    self.rename_selected_entry(&name)
+                } else {
+                    self.create_entry(&name)
+                };
+                if let Err(err) = result {
+                    self.update_message(&format!("ERR: {err}"));
+                }
+            }
+            PromptCommand::Edit(edit_command) => self.command_bar.handle_edit_command(edit_command),
+            PromptCommand::Next | PromptCommand::Previous | PromptCommand::Complete => {}
+        }
+    }
+    fn process_command_during_grep(&mut self, command: PromptCommand) {
+        match command {
+            PromptCommand::Dismiss => self.set_prompt(PromptType::None),
+            PromptCommand::Accept => {
+                let pattern = self.command_bar.value();
+                self.set_prompt(PromptType::None);
+                if !pattern.is_empty() {
+                    self.grep(&pattern);
+                }
+            }
+            PromptCommand::Edit(edit_command) => self.command_bar.handle_edit_command(edit_command),
+            PromptCommand::Next | PromptCommand::Previous | PromptCommand::Complete => {}
+        }
+    }
+    fn process_command_during_macro_prompt(&mut self, command: PromptCommand) {
+        match command {
+            PromptCommand::Dismiss => self.set_prompt(PromptType::None),
+            PromptCommand::Accept => {
+                let input = self.command_bar.value();
+                let prompt_type = take(&mut self.prompt_type);
+                self.set_prompt(PromptType::None);
+                let result = parse_macro_input(&input).and_then(|(count, register)| match prompt_type {
+                    PromptType::RecordMacro => match (count, register) {
+                        (None, Some(register)) => {
+                            self.start_recording(register);
+                            Ok(())
+                        }
+                        _ => Err(String::from("Record needs a register, such as a")),
+                    },
+                    PromptType::ApplyMacro => self.apply_macro_to_selection(register),
+                    _ => self.play_macro(register, count.unwrap_or(1)),
+                });
+                if let Err(err) = result {
+                    self.update_message(&format!("ERR: {err}"));
+                }
+            }
+            PromptCommand::Edit(edit_command) => self.command_bar.handle_edit_command(edit_command),
+            PromptCommand::Next | PromptCommand::Previous | PromptCommand::Complete => {}
+        }
+    }
+}
+
+/// Splits macro prompt input such as `3a` into the count and the register.
+/// Without a register, the macro played last is meant.
+fn parse_macro_input(input: &str) -> Result<(Option<usize>, Option<char>), String> {
+    let input = input.trim();
+    let digits = input.find(|ch: char| !ch.is_ascii_digit()).unwrap_or(input.len());
+    let (count, register) = input.split_at(digits);
+    let count = (!count.is_empty())
+        .then(|| count.parse::<usize>().map_err(|_| format!("Invalid count '{count}'")))
+        .transpose()?;
+    let mut chars = register.chars();
+    match (chars.next(), chars.next()) {
+        (None, _) => Ok((count, None)),
+        (Some(register), None) if Macros::is_register(register) => Ok((count, Some(register))),
+        _ => Err(format!("Invalid register '{register}'")),
+    }
+}
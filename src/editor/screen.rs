@@ -0,0 +1,179 @@
+use crossterm::style::{Attributes, Color};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use super::{
+    annotatedstring::AnnotatedString,
+    theme::{Theme, ThemeElement},
+    Col, Row, Size,
+};
+
+#[derive(Default, Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Style {
+    pub foreground: Option<Color>,
+    pub background: Option<Color>,
+    pub attributes: Attributes,
+}
+
+impl Style {
+    /// Fills in whatever this style leaves unspecified from `base`.
+    pub fn or(self, base: Self) -> Self {
+        let mut attributes = base.attributes;
+        attributes.extend(self.attributes);
+        Self {
+            foreground: self.foreground.or(base.foreground),
+            background: self.background.or(base.background),
+            attributes,
+        }
+    }
+}
+
+/// A single terminal cell. The cell following a double width grapheme holds
+/// an empty string, as the grapheme already covers it on screen.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Cell {
+    pub grapheme: String,
+    pub style: Style,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            grapheme: String::from(" "),
+            style: Style::default(),
+        }
+    }
+}
+
+impl Cell {
+    pub fn is_continuation(&self) -> bool {
+        self.grapheme.is_empty()
+    }
+}
+
+/// An in-memory frame. Components draw into it, and the terminal only emits
+/// the cells that differ from the previously presented frame.
+#[derive(Clone, Default)]
+pub struct Screen {
+    size: Size,
+    cells: Vec<Cell>,
+}
+
+impl Screen {
+    pub fn new(size: Size) -> Self {
+        Self {
+            size,
+            cells: vec![Cell::default(); size.width.saturating_mul(size.height)],
+        }
+    }
+    pub const fn size(&self) -> Size {
+        self.size
+    }
+    pub fn row(&self, row: Row) -> &[Cell] {
+        let start = row.saturating_mul(self.size.width);
+        let end = start.saturating_add(self.size.width);
+        self.cells.get(start..end).unwrap_or_default()
+    }
+    fn cell_mut(&mut self, row: Row, col: Col) -> Option<&mut Cell> {
+        if col >= self.size.width {
+            return None;
+        }
+        let idx = row.saturating_mul(self.size.width).saturating_add(col);
+        self.cells.get_mut(idx)
+    }
+    /// Fills the row with blanks in the given style.
+    pub fn clear_row(&mut self, row: Row, style: Style) {
+        for col in 0..self.size.width {
+            if let Some(cell) = self.cell_mut(row, col) {
+                *cell = Cell {
+                    grapheme: String::from(" "),
+                    style,
+                };
+            }
+        }
+    }
+    /// Writes `text` starting at the given column, clipping at the right edge.
+    /// Returns the column after the last written cell.
+    pub fn print_at(&mut self, row: Row, col: Col, text: &str, style: Style) -> Col {
+        let mut col = col;
+        for grapheme in text.graphemes(true) {
+            let width = grapheme.width().max(1);
+            if col.saturating_add(width) > self.size.width {
+                break;
+            }
+            self.blank_cut_wide_graphemes(row, col, col.saturating_add(width));
+            if let Some(cell) = self.cell_mut(row, col) {
+                *cell = Cell {
+                    grapheme: grapheme.to_string(),
+                    style,
+                };
+            }
+            for continuation_col in col.saturating_add(1)..col.saturating_add(width) {
+                if let Some(cell) = self.cell_mut(row, continuation_col) {
+                    *cell = Cell {
+                        grapheme: String::new(),
+                        style,
+                    };
+                }
+            }
+            col = col.saturating_add(width);
+        }
+        col
+    }
+    /// Blanks what is left of the double width graphemes that writing the
+    /// cells from `start` to `end` cuts in half: the grapheme before a
+    /// continuation cell at `start`, and the continuation cells at `end`.
+    fn blank_cut_wide_graphemes(&mut self, row: Row, start: Col, end: Col) {
+        let is_continuation = |screen: &mut Self, col: Col| screen.cell_mut(row, col).is_some_and(|cell| cell.is_continuation());
+        let mut col = start;
+        while col > 0 && is_continuation(self, col) {
+            col = col.saturating_sub(1);
+            if let Some(cell) = self.cell_mut(row, col) {
+                cell.grapheme = String::from(" ");
+            }
+        }
+        let mut col = end;
+        while is_continuation(self, col) {
+            if let Some(cell) = self.cell_mut(row, col) {
+                cell.grapheme = String::from(" ");
+            }
+            col = col.saturating_add(1);
+        }
+    }
+    /// Replaces the row with `text`, filling the remainder in the same style.
+    pub fn print_row(&mut self, row: Row, text: &str, style: Style) {
+        self.clear_row(row, style);
+        self.print_at(row, 0, text, style);
+    }
+    pub fn print_annotated_row(&mut self, row: Row, annotated_string: &AnnotatedString, theme: &Theme) {
+        self.clear_row(row, theme.style(ThemeElement::Text));
+        let mut col = 0;
+        for fragment in annotated_string.get_annotated_fragments() {
+            let style = theme.style(ThemeElement::from(fragment.annotation_type));
+            col = self.print_at(row, col, &fragment.string, style);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row_text(screen: &Screen, row: Row) -> String {
+        screen.row(row).iter().map(|cell| cell.grapheme.as_str()).collect()
+    }
+
+    #[test]
+    fn overwriting_half_of_a_wide_grapheme_blanks_the_other_half() {
+        let mut screen = Screen::new(Size { width: 6, height: 1 });
+        screen.print_at(0, 0, "a\u{4f60}\u{597d}b", Style::default());
+        assert_eq!(row_text(&screen, 0), "a\u{4f60}\u{597d}b");
+
+        screen.print_at(0, 2, "x", Style::default());
+        assert_eq!(row_text(&screen, 0), "a x\u{597d}b");
+
+        screen.print_at(0, 3, "y", Style::default());
+        assert_eq!(row_text(&screen, 0), "a xy b");
+        assert!(!screen.row(0).iter().any(Cell::is_continuation));
+    }
+}
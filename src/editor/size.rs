@@ -1,4 +1,4 @@
-#[derive(Default, Copy, Clone, Eq, PartialEq)]
+#[derive(Default, Copy, Clone, Eq, PartialEq, Debug)]
 pub struct Size {
     pub width: usize,
     pub height: usize,
@@ -1,6 +1,6 @@
 use std::io::Error;
 
-use super::{DocumentStatus, Size, Terminal, UIComponent};
+use super::{DocumentStatus, Screen, Size, Theme, ThemeElement, UIComponent};
 
 #[derive(Default)]
 pub struct StatusBar {
@@ -28,13 +28,18 @@ impl UIComponent for StatusBar {
     fn set_size(&mut self, size: Size) {
         self.size = size;
     }
-    fn draw(&mut self, origin_row: usize) -> Result<(), Error> {
+    fn draw(&mut self, screen: &mut Screen, theme: &Theme, origin_row: usize) -> Result<(), Error> {
 
         let line_count = self.current_status.line_count_to_string();
         let modified_indicator = self.current_status.modified_indicator_to_string();
 
+        let mode = self
+            .current_status
+            .mode
+            .map(|mode| format!("[{mode}] "))
+            .unwrap_or_default();
         let beginning = format!(
-            "{} - {line_count} {modified_indicator}",
+            "{mode}{} - {line_count} {modified_indicator}",
             self.current_status.file_name
         );
 
@@ -48,7 +53,7 @@ impl UIComponent for StatusBar {
             String::new()
         };
 
-        Terminal::print_inverted_row(origin_row, &to_print)?;
+        screen.print_row(origin_row, &to_print, theme.style(ThemeElement::StatusBar));
 
         Ok(())
     }
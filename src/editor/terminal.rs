@@ -1,156 +1,98 @@
-use std::io::{stdout, Write, Error};
-use crossterm::terminal::{
-    disable_raw_mode, enable_raw_mode, size, Clear, ClearType, 
-    EnterAlternateScreen, LeaveAlternateScreen, 
-    EnableLineWrap, DisableLineWrap, SetTitle
-};
-use crossterm::cursor::{MoveTo, Hide, Show};
-use crossterm::style::{ResetColor, Attribute, Print, Color, SetForegroundColor, SetBackgroundColor};
-use crossterm::{queue, Command};
-use super::{Position, Size, AnnotatedString};
-use crate::editor::annotatedstring::AnnotationType;
+use std::io::Error;
+use std::time::Duration;
+use crossterm::event::Event;
+use unicode_width::UnicodeWidthStr;
+use super::{ColorDepth, Position, Screen, Size, Style};
 
+mod backend;
+pub use backend::Backend;
+mod crosstermbackend;
+pub use crosstermbackend::CrosstermBackend;
+mod headlessbackend;
+pub use headlessbackend::HeadlessBackend;
 
 pub struct Terminal {
+    backend: Box<dyn Backend>,
 }
 
-impl Terminal {
-    pub fn terminate() -> Result<(), Error> {
-        Self::leave_alternate_screen()?;
-        Self::enable_line_wrap()?;
-        Self::show_caret()?;
-        Self::execute()?;
-        disable_raw_mode()?;
-        Ok(())
-    }
-    pub fn initialize() -> Result<(), Error> {
-        // Move to the alternate screen first.
-        enable_raw_mode()?;
-        Self::enter_alternate_screen()?;
-        Self::disable_line_wrap()?;
-        Self::clear_screen()?;
-        Self::move_caret_to(Position {col: 0, row: 0})?;
-        Self::execute()?;
-        Ok(())
-    }
-    pub fn clear_line() -> Result<(), Error> {
-        Self::queue_command(Clear(ClearType::CurrentLine))?;
-        Ok(())
-    }
-    pub fn hide_caret() -> Result<(), Error> {
-        Self::queue_command(Hide)
-    }
-    pub fn show_caret() -> Result<(), Error> {
-        Self::queue_command(Show)
-    }
-    pub fn set_title(title: &str) -> Result<(), Error> {
-        Self::queue_command(SetTitle(title))?;
-        Ok(())
-    }
-    pub fn enable_line_wrap() -> Result<(), Error> {
-        Self::queue_command(EnableLineWrap)?;
-        Ok(())
-    }
-    pub fn disable_line_wrap() -> Result<(), Error> {
-        Self::queue_command(DisableLineWrap)?;
-        Ok(())
-    }
-    pub fn enter_alternate_screen() -> Result<(), Error> {
-        Self::queue_command(EnterAlternateScreen)?;
-        Ok(())
-    }
-    pub fn leave_alternate_screen() -> Result<(), Error> {
-        Self::queue_command(LeaveAlternateScreen)?;
-        Ok(())
-    }
-    pub fn clear_screen() -> Result<(), Error> {
-        Self::queue_command(Clear(ClearType::All))?;
-        Ok(())
-    }
-    pub fn move_caret_to(position: Position) -> Result<(), Error> {
-        #[allow(clippy::as_conversions,clippy::cast_possible_truncation)]
-        Self::queue_command(MoveTo(position.col as u16, position.row as u16))
-    }
-    pub fn print(string: &str) -> Result<(), Error> {
-        Self::queue_command(Print(string))
-    }
-    pub fn reset_color() -> Result<(), Error> {
-        Self::queue_command(ResetColor)
-    }
-    pub fn print_row(row: usize, line_text: &str) -> Result<(), Error> {
-        Self::move_caret_to(Position { col: 0, row: row, })?;
-        Self::clear_line()?;
-        Self::print(line_text)?;
-        Ok(())
-    }
-    pub fn print_annotated_row(row: usize, annotated_string: &AnnotatedString) -> Result<(), Error> {
-        log::debug!("Print Annotated Row: {}", row);
-        Self::move_caret_to(Position { col: 0, row: row, })?;
-        Self::clear_line()?;
-
-        let annotated_fragments = annotated_string.get_annotated_fragments();
+impl Default for Terminal {
+    fn default() -> Self {
+        Self::new(CrosstermBackend::default())
+    }
+}
 
-        for fragment in annotated_fragments {
-            let colors = match fragment.annotation_type {
-                AnnotationType::Highlight => Some((Color::Rgb {
-                    r: 0,
-                    g: 0,
-                    b: 0,
-                }, Color::Rgb {
-                    r: 255,
-                    g: 255,
-                    b: 0,
-                })),
-                AnnotationType::None => None/*(Color::Rgb {
-                    r: 255,
-                    g: 255,
-                    b: 255,
-                }, Color::Rgb {
-                    r: 0,
-                    g: 0,
-                    b: 0,
-                })*/
-            };
+impl Terminal {
+    pub fn new(backend: impl Backend + 'static) -> Self {
+        Self {
+            backend: Box::new(backend),
+        }
+    }
+    pub fn terminate(&mut self) -> Result<(), Error> {
+        self.backend.terminate()
+    }
+    pub fn initialize(&mut self) -> Result<(), Error> {
+        self.backend.initialize()
+    }
+    pub fn hide_caret(&mut self) -> Result<(), Error> {
+        self.backend.hide_caret()
+    }
+    pub fn show_caret(&mut self) -> Result<(), Error> {
+        self.backend.show_caret()
+    }
+    pub fn set_title(&mut self, title: &str) -> Result<(), Error> {
+        self.backend.set_title(title)
+    }
+    pub fn set_mouse_capture(&mut self, enabled: bool) -> Result<(), Error> {
+        self.backend.set_mouse_capture(enabled)
+    }
+    pub fn move_caret_to(&mut self, position: Position) -> Result<(), Error> {
+        self.backend.move_caret_to(position)
+    }
+    pub fn print(&mut self, string: &str) -> Result<(), Error> {
+        self.backend.print(string, Style::default())
+    }
+    /// Emits the cells of `next` that differ from `previous`, wrapped in a
+    /// synchronized update so that terminals supporting it never show a half
+    /// drawn frame. Without a previous frame the whole screen is repainted.
+    pub fn present(&mut self, previous: Option<&Screen>, next: &Screen) -> Result<(), Error> {
+        self.backend.begin_frame()?;
+        if previous.is_none() {
+            self.backend.clear_screen()?;
+        }
 
-            if let Some((foreground_color, background_color)) = colors {
-                Self::queue_command(SetForegroundColor(foreground_color));
-                Self::queue_command(SetBackgroundColor(background_color));
+        for row in 0..next.size().height {
+            let previous_row = previous.map(|screen| screen.row(row));
+            let mut caret_col = None;
+            for (col, cell) in next.row(row).iter().enumerate() {
+                let unchanged = previous_row
+                    .and_then(|cells| cells.get(col))
+                    .is_some_and(|previous_cell| previous_cell == cell);
+                if unchanged || cell.is_continuation() {
+                    continue;
+                }
+                if caret_col != Some(col) {
+                    self.backend.move_caret_to(Position { col, row })?;
+                }
+                self.backend.print(&cell.grapheme, cell.style)?;
+                caret_col = Some(col.saturating_add(cell.grapheme.width().max(1)));
             }
-            Self::print(&fragment.string)?;
-            Self::queue_command(ResetColor);
         }
 
-        Ok(())
-    }
-    pub fn print_inverted_row(row: usize, line_text: &str) -> Result<(), Error> {
-        let width = Self::size()?.width;
-        Self::print_row(
-            row,
-            &format!(
-                "{}{:width$.width$}{}",
-                Attribute::Reverse,
-                line_text,
-                Attribute::Reset
-            ),
-        )
-    }
-    pub fn size() -> Result<Size, Error> {
-        let (width_u16, height_u16) = size()?;
-
-        #[allow(clippy::as_conversions)]
-        let height = height_u16 as usize;
-
-        #[allow(clippy::as_conversions)]
-        let width = width_u16 as usize;
-
-        Ok(Size { width, height })
+        self.backend.end_frame()
+    }
+    pub fn size(&self) -> Result<Size, Error> {
+        self.backend.size()
+    }
+    pub fn color_depth(&self) -> ColorDepth {
+        self.backend.color_depth()
+    }
+    pub fn execute(&mut self) -> Result<(), Error> {
+        self.backend.flush()
     }
-    pub fn execute() -> Result<(), Error> {
-        stdout().flush()?;
-        Ok(())
+    pub fn poll_event(&mut self, timeout: Option<Duration>) -> Result<bool, Error> {
+        self.backend.poll_event(timeout)
     }
-    pub fn queue_command<T:Command>(command: T) -> Result<(), Error> {
-        queue!(stdout(), command)?;
-        Ok(())
+    pub fn read_event(&mut self) -> Result<Event, Error> {
+        self.backend.read_event()
     }
 }
@@ -0,0 +1,40 @@
+use std::{io::Error, time::Duration};
+
+use crossterm::event::Event;
+
+use super::{ColorDepth, Position, Size, Style};
+
+/// Everything the editor needs from the terminal it runs in. `Terminal`
+/// builds frames and caret handling on top of these primitives.
+pub trait Backend {
+    fn initialize(&mut self) -> Result<(), Error>;
+    fn terminate(&mut self) -> Result<(), Error>;
+    fn size(&self) -> Result<Size, Error>;
+    fn color_depth(&self) -> ColorDepth {
+        ColorDepth::TrueColor
+    }
+    fn set_title(&mut self, title: &str) -> Result<(), Error>;
+    /// Whether mouse events are reported, rather than left to the terminal
+    /// for its own selection.
+    fn set_mouse_capture(&mut self, enabled: bool) -> Result<(), Error> {
+        let _ = enabled;
+        Ok(())
+    }
+    fn clear_screen(&mut self) -> Result<(), Error>;
+    fn move_caret_to(&mut self, position: Position) -> Result<(), Error>;
+    fn hide_caret(&mut self) -> Result<(), Error>;
+    fn show_caret(&mut self) -> Result<(), Error>;
+    /// Prints `text` at the caret, which then advances past it.
+    fn print(&mut self, text: &str, style: Style) -> Result<(), Error>;
+    fn begin_frame(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+    fn end_frame(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+    fn flush(&mut self) -> Result<(), Error>;
+    /// Waits until an event is available, for at most `timeout` if given.
+    /// Returns an `UnexpectedEof` error once no more input can arrive.
+    fn poll_event(&mut self, timeout: Option<Duration>) -> Result<bool, Error>;
+    fn read_event(&mut self) -> Result<Event, Error>;
+}
@@ -0,0 +1,152 @@
+use std::fs::{File, OpenOptions};
+use std::io::{stdout, BufWriter, Error, IsTerminal, Write};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use crossterm::event::{poll, read, DisableMouseCapture, EnableMouseCapture, Event};
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, size, Clear, ClearType, 
+    EnterAlternateScreen, LeaveAlternateScreen, 
+    EnableLineWrap, DisableLineWrap, SetTitle,
+    BeginSynchronizedUpdate, EndSynchronizedUpdate,
+};
+use crossterm::cursor::{MoveTo, Hide, Show};
+use crossterm::style::{
+    Attribute, Print, SetAttribute, SetAttributes, SetBackgroundColor, SetForegroundColor,
+};
+use crossterm::{Command, QueueableCommand};
+
+use super::{Backend, ColorDepth, Position, Size, Style};
+
+/// When stdout is redirected (e.g. `hecto - | sort`), the UI is drawn to the
+/// controlling terminal instead so that stdout stays free for the buffer.
+static TTY_OUTPUT: OnceLock<Mutex<BufWriter<File>>> = OnceLock::new();
+
+/// The backend driving a real terminal through crossterm.
+#[derive(Default)]
+pub struct CrosstermBackend {
+    current_style: Option<Style>,
+}
+
+impl CrosstermBackend {
+    fn use_tty_output() -> Result<(), Error> {
+        let tty = OpenOptions::new().write(true).open("/dev/tty")?;
+        let _ = TTY_OUTPUT.set(Mutex::new(BufWriter::new(tty)));
+        Ok(())
+    }
+    fn with_output<F>(write: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut dyn Write) -> Result<(), Error>,
+    {
+        if let Some(tty) = TTY_OUTPUT.get() {
+            let mut tty = tty
+                .lock()
+                .map_err(|_| Error::other("Terminal output lock poisoned"))?;
+            write(&mut *tty)
+        } else {
+            write(&mut stdout().lock())
+        }
+    }
+    fn queue_command<T:Command>(command: T) -> Result<(), Error> {
+        Self::with_output(|output| output.queue(command).map(|_| ()))
+    }
+    fn set_style(&mut self, style: Style) -> Result<(), Error> {
+        if self.current_style == Some(style) {
+            return Ok(());
+        }
+        Self::queue_command(SetAttribute(Attribute::Reset))?;
+        if let Some(foreground_color) = style.foreground {
+            Self::queue_command(SetForegroundColor(foreground_color))?;
+        }
+        if let Some(background_color) = style.background {
+            Self::queue_command(SetBackgroundColor(background_color))?;
+        }
+        Self::queue_command(SetAttributes(style.attributes))?;
+        self.current_style = Some(style);
+        Ok(())
+    }
+}
+
+impl Backend for CrosstermBackend {
+    fn initialize(&mut self) -> Result<(), Error> {
+        if !stdout().is_terminal() {
+            Self::use_tty_output()?;
+        }
+        // Move to the alternate screen first.
+        enable_raw_mode()?;
+        Self::queue_command(EnterAlternateScreen)?;
+        Self::queue_command(DisableLineWrap)?;
+        self.clear_screen()?;
+        self.move_caret_to(Position {col: 0, row: 0})?;
+        self.flush()
+    }
+    fn terminate(&mut self) -> Result<(), Error> {
+        // Also runs from the panic hook, which cannot know whether capture
+        // is on; turning it off twice does no harm.
+        Self::queue_command(DisableMouseCapture)?;
+        Self::queue_command(LeaveAlternateScreen)?;
+        Self::queue_command(EnableLineWrap)?;
+        self.show_caret()?;
+        self.flush()?;
+        disable_raw_mode()
+    }
+    fn size(&self) -> Result<Size, Error> {
+        let (width_u16, height_u16) = size()?;
+
+        #[allow(clippy::as_conversions)]
+        let height = height_u16 as usize;
+
+        #[allow(clippy::as_conversions)]
+        let width = width_u16 as usize;
+
+        Ok(Size { width, height })
+    }
+    fn color_depth(&self) -> ColorDepth {
+        ColorDepth::detect()
+    }
+    fn set_title(&mut self, title: &str) -> Result<(), Error> {
+        Self::queue_command(SetTitle(title))
+    }
+    fn set_mouse_capture(&mut self, enabled: bool) -> Result<(), Error> {
+        if enabled {
+            Self::queue_command(EnableMouseCapture)?;
+        } else {
+            Self::queue_command(DisableMouseCapture)?;
+        }
+        self.flush()
+    }
+    fn clear_screen(&mut self) -> Result<(), Error> {
+        Self::queue_command(Clear(ClearType::All))
+    }
+    fn move_caret_to(&mut self, position: Position) -> Result<(), Error> {
+        #[allow(clippy::as_conversions,clippy::cast_possible_truncation)]
+        Self::queue_command(MoveTo(position.col as u16, position.row as u16))
+    }
+    fn hide_caret(&mut self) -> Result<(), Error> {
+        Self::queue_command(Hide)
+    }
+    fn show_caret(&mut self) -> Result<(), Error> {
+        Self::queue_command(Show)
+    }
+    fn print(&mut self, text: &str, style: Style) -> Result<(), Error> {
+        self.set_style(style)?;
+        Self::queue_command(Print(text))
+    }
+    fn begin_frame(&mut self) -> Result<(), Error> {
+        Self::queue_command(BeginSynchronizedUpdate)
+    }
+    fn end_frame(&mut self) -> Result<(), Error> {
+        Self::queue_command(SetAttribute(Attribute::Reset))?;
+        self.current_style = None;
+        Self::queue_command(EndSynchronizedUpdate)
+    }
+    fn flush(&mut self) -> Result<(), Error> {
+        Self::with_output(|output| output.flush())
+    }
+    fn poll_event(&mut self, timeout: Option<Duration>) -> Result<bool, Error> {
+        // Without a timeout the following read blocks until input arrives.
+        timeout.map_or(Ok(true), poll)
+    }
+    fn read_event(&mut self) -> Result<Event, Error> {
+        read()
+    }
+}
@@ -0,0 +1,126 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    io::{Error, ErrorKind},
+    rc::Rc,
+    time::Duration,
+};
+
+use crossterm::event::Event;
+
+use super::{Backend, Position, Screen, Size, Style};
+
+#[derive(Default)]
+struct HeadlessState {
+    screen: Screen,
+    caret: Position,
+    caret_visible: bool,
+    title: String,
+    events: VecDeque<Event>,
+}
+
+/// An in-memory backend for driving the editor without a terminal. Clones
+/// share their state, so a test can keep one handle to script events and
+/// inspect the recorded screen while the editor owns another.
+#[derive(Clone, Default)]
+pub struct HeadlessBackend {
+    state: Rc<RefCell<HeadlessState>>,
+}
+
+impl HeadlessBackend {
+    pub fn new(size: Size) -> Self {
+        let backend = Self::default();
+        backend.state.borrow_mut().screen = Screen::new(size);
+        backend
+    }
+    pub fn push_event(&self, event: Event) {
+        self.state.borrow_mut().events.push_back(event);
+    }
+    pub fn push_events(&self, events: impl IntoIterator<Item = Event>) {
+        self.state.borrow_mut().events.extend(events);
+    }
+    /// The text shown on the given row, without trailing blanks.
+    pub fn row_text(&self, row: usize) -> String {
+        let state = self.state.borrow();
+        let text: String = state
+            .screen
+            .row(row)
+            .iter()
+            .map(|cell| cell.grapheme.as_str())
+            .collect();
+        text.trim_end().to_string()
+    }
+    pub fn style_at(&self, position: Position) -> Option<Style> {
+        let state = self.state.borrow();
+        state
+            .screen
+            .row(position.row)
+            .get(position.col)
+            .map(|cell| cell.style)
+    }
+    pub fn caret_position(&self) -> Option<Position> {
+        let state = self.state.borrow();
+        state.caret_visible.then_some(state.caret)
+    }
+    pub fn title(&self) -> String {
+        self.state.borrow().title.clone()
+    }
+}
+
+impl Backend for HeadlessBackend {
+    fn initialize(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+    fn terminate(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+    fn size(&self) -> Result<Size, Error> {
+        Ok(self.state.borrow().screen.size())
+    }
+    fn set_title(&mut self, title: &str) -> Result<(), Error> {
+        self.state.borrow_mut().title = title.to_string();
+        Ok(())
+    }
+    fn clear_screen(&mut self) -> Result<(), Error> {
+        let mut state = self.state.borrow_mut();
+        let size = state.screen.size();
+        state.screen = Screen::new(size);
+        Ok(())
+    }
+    fn move_caret_to(&mut self, position: Position) -> Result<(), Error> {
+        self.state.borrow_mut().caret = position;
+        Ok(())
+    }
+    fn hide_caret(&mut self) -> Result<(), Error> {
+        self.state.borrow_mut().caret_visible = false;
+        Ok(())
+    }
+    fn show_caret(&mut self) -> Result<(), Error> {
+        self.state.borrow_mut().caret_visible = true;
+        Ok(())
+    }
+    fn print(&mut self, text: &str, style: Style) -> Result<(), Error> {
+        let mut state = self.state.borrow_mut();
+        let Position { row, col } = state.caret;
+        let col = state.screen.print_at(row, col, text, style);
+        state.caret.col = col;
+        Ok(())
+    }
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+    fn poll_event(&mut self, _: Option<Duration>) -> Result<bool, Error> {
+        if self.state.borrow().events.is_empty() {
+            Err(Error::new(ErrorKind::UnexpectedEof, "No more scripted events"))
+        } else {
+            Ok(true)
+        }
+    }
+    fn read_event(&mut self) -> Result<Event, Error> {
+        self.state
+            .borrow_mut()
+            .events
+            .pop_front()
+            .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "No more scripted events"))
+    }
+}
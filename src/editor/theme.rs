@@ -0,0 +1,379 @@
+use std::{
+    collections::HashMap,
+    fs::{read_dir, read_to_string},
+    path::Path,
+};
+
+use crossterm::style::{Attribute, Attributes, Color};
+use serde::Deserialize;
+
+use super::{annotatedstring::AnnotationType, colordepth::ColorDepth, Style};
+
+const DEFAULT_THEME: &str = include_str!("../../themes/default.toml");
+const BUILT_IN_THEMES: [&str; 3] = [
+    DEFAULT_THEME,
+    include_str!("../../themes/dark.toml"),
+    include_str!("../../themes/light.toml"),
+];
+const THEMES_DIR: &str = "themes";
+
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum ThemeElement {
+    Text,
+    SearchHighlight,
+    Selection,
+    /// Carets other than the one the terminal shows.
+    Caret,
+    /// The brackets of the pair at the caret.
+    MatchingBracket,
+    /// Spaces and tabs at the end of a line.
+    TrailingWhitespace,
+    /// The `~` markers in front of rows past the end of the buffer.
+    Gutter,
+    Welcome,
+    StatusBar,
+    MessageBar,
+    CommandBar,
+    /// Lists shown above the bars, such as the command palette.
+    Popup,
+    PopupSelection,
+    /// The characters of a list entry that match what was typed.
+    PopupMatch,
+}
+
+impl ThemeElement {
+    const ALL: [Self; 14] = [
+        Self::Text,
+        Self::SearchHighlight,
+        Self::Selection,
+        Self::Caret,
+        Self::MatchingBracket,
+        Self::TrailingWhitespace,
+        Self::Gutter,
+        Self::Welcome,
+        Self::StatusBar,
+        Self::MessageBar,
+        Self::CommandBar,
+        Self::Popup,
+        Self::PopupSelection,
+        Self::PopupMatch,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Text => "text",
+            Self::SearchHighlight => "search_highlight",
+            Self::Selection => "selection",
+            Self::Caret => "caret",
+            Self::MatchingBracket => "matching_bracket",
+            Self::TrailingWhitespace => "trailing_whitespace",
+            Self::Gutter => "gutter",
+            Self::Welcome => "welcome",
+            Self::StatusBar => "status_bar",
+            Self::MessageBar => "message_bar",
+            Self::CommandBar => "command_bar",
+            Self::Popup => "popup",
+            Self::PopupSelection => "popup_selection",
+            Self::PopupMatch => "popup_match",
+        }
+    }
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|element| element.name() == name)
+    }
+    /// Elements that mostly stand out through their background, and are
+    /// shown in reverse video when colors are unavailable.
+    const fn is_highlight(self) -> bool {
+        matches!(
+            self,
+            Self::SearchHighlight
+                | Self::Selection
+                | Self::Caret
+                | Self::TrailingWhitespace
+                | Self::StatusBar
+                | Self::PopupSelection
+        )
+    }
+    /// Elements drawn inside the text area take the colors they leave out
+    /// from the text style.
+    const fn inherits_text_style(self) -> bool {
+        matches!(
+            self,
+            Self::SearchHighlight
+                | Self::Selection
+                | Self::Caret
+                | Self::MatchingBracket
+                | Self::TrailingWhitespace
+                | Self::Gutter
+                | Self::Welcome
+        )
+    }
+}
+
+impl From<AnnotationType> for ThemeElement {
+    fn from(annotation_type: AnnotationType) -> Self {
+        match annotation_type {
+            AnnotationType::None => Self::Text,
+            AnnotationType::Highlight => Self::SearchHighlight,
+            AnnotationType::Selection => Self::Selection,
+            AnnotationType::Caret => Self::Caret,
+            AnnotationType::MatchingBracket => Self::MatchingBracket,
+            AnnotationType::TrailingWhitespace => Self::TrailingWhitespace,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StyleDefinition {
+    foreground: Option<String>,
+    background: Option<String>,
+    #[serde(default)]
+    attributes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ThemeDefinition {
+    name: String,
+    #[serde(flatten)]
+    elements: HashMap<String, StyleDefinition>,
+}
+
+#[derive(Clone)]
+pub struct Theme {
+    name: String,
+    styles: HashMap<ThemeElement, Style>,
+    color_depth: ColorDepth,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::parse(DEFAULT_THEME, &HashMap::new())
+            .unwrap_or_else(|err| panic!("Built-in default theme is invalid: {err}"))
+    }
+}
+
+impl Theme {
+    /// Parses a theme from TOML. Elements the theme leaves out keep their
+    /// style from the default theme.
+    pub fn from_toml(source: &str) -> Result<Self, String> {
+        Self::parse(source, &Self::default().styles)
+    }
+    fn parse(source: &str, base: &HashMap<ThemeElement, Style>) -> Result<Self, String> {
+        let definition: ThemeDefinition = toml::from_str(source).map_err(|err| err.to_string())?;
+        let mut styles = base.clone();
+
+        for (element_name, style_definition) in definition.elements {
+            let element = ThemeElement::from_name(&element_name)
+                .ok_or_else(|| format!("Unknown theme element '{element_name}'"))?;
+            let style = Self::parse_style(&style_definition)
+                .map_err(|err| format!("{element_name}: {err}"))?;
+            styles.insert(element, style);
+        }
+
+        Ok(Self {
+            name: definition.name,
+            styles,
+            color_depth: ColorDepth::default(),
+        })
+    }
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let source = read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        Self::from_toml(&source).map_err(|err| format!("{}: {err}", path.display()))
+    }
+    fn parse_style(definition: &StyleDefinition) -> Result<Style, String> {
+        let foreground = definition.foreground.as_deref().map(parse_color).transpose()?;
+        let background = definition.background.as_deref().map(parse_color).transpose()?;
+        let mut attributes = Attributes::default();
+        for name in &definition.attributes {
+            attributes.set(parse_attribute(name)?);
+        }
+        Ok(Style {
+            foreground,
+            background,
+            attributes,
+        })
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn style(&self, element: ThemeElement) -> Style {
+        let style = self.styles.get(&element).copied().unwrap_or_default();
+        let style = if element.inherits_text_style() {
+            style.or(self.styles.get(&ThemeElement::Text).copied().unwrap_or_default())
+        } else {
+            style
+        };
+        self.adapt_to_color_depth(element, style)
+    }
+    fn adapt_to_color_depth(&self, element: ThemeElement, style: Style) -> Style {
+        let depth = self.color_depth;
+        let mut attributes = style.attributes;
+        if depth == ColorDepth::None && element.is_highlight() && style.background.is_some() {
+            attributes.set(Attribute::Reverse);
+        }
+        Style {
+            foreground: style.foreground.and_then(|color| depth.adapt(color)),
+            background: style.background.and_then(|color| depth.adapt(color)),
+            attributes,
+        }
+    }
+}
+
+fn parse_color(value: &str) -> Result<Color, String> {
+    if let Some(hex) = value.strip_prefix('#') {
+        let channel = |range| {
+            hex.get(range)
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .ok_or_else(|| format!("Invalid color '{value}', expected #rrggbb"))
+        };
+        if hex.len() != 6 {
+            return Err(format!("Invalid color '{value}', expected #rrggbb"));
+        }
+        return Ok(Color::Rgb {
+            r: channel(0..2)?,
+            g: channel(2..4)?,
+            b: channel(4..6)?,
+        });
+    }
+    if let Ok(ansi_value) = value.parse::<u8>() {
+        return Ok(Color::AnsiValue(ansi_value));
+    }
+    Color::try_from(value).map_err(|()| format!("Unknown color '{value}'"))
+}
+
+fn parse_attribute(name: &str) -> Result<Attribute, String> {
+    match name {
+        "bold" => Ok(Attribute::Bold),
+        "dim" => Ok(Attribute::Dim),
+        "italic" => Ok(Attribute::Italic),
+        "underlined" => Ok(Attribute::Underlined),
+        "reverse" => Ok(Attribute::Reverse),
+        "crossed_out" => Ok(Attribute::CrossedOut),
+        _ => Err(format!("Unknown attribute '{name}'")),
+    }
+}
+
+/// The built-in themes followed by the ones found in the config directory.
+pub struct Themes {
+    themes: Vec<Theme>,
+    current: usize,
+}
+
+impl Default for Themes {
+    fn default() -> Self {
+        let themes = BUILT_IN_THEMES
+            .iter()
+            .map(|source| {
+                Theme::from_toml(source)
+                    .unwrap_or_else(|err| panic!("Built-in theme is invalid: {err}"))
+            })
+            .collect();
+        Self { themes, current: 0 }
+    }
+}
+
+impl Themes {
+    /// Loads the themes in `config_dir`. Themes that fail to load are skipped
+    /// and their errors returned.
+    pub fn load(config_dir: Option<&Path>) -> (Self, Vec<String>) {
+        let mut themes = Self::default();
+        let mut errors = Vec::new();
+
+        let Some(entries) = config_dir
+            .map(|dir| dir.join(THEMES_DIR))
+            .and_then(|dir| read_dir(dir).ok())
+        else {
+            return (themes, errors);
+        };
+
+        let mut paths: Vec<_> = entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "toml"))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            match Theme::load(&path) {
+                Ok(theme) => themes.add(theme),
+                Err(err) => errors.push(err),
+            }
+        }
+        (themes, errors)
+    }
+    /// User themes replace built-in ones of the same name.
+    fn add(&mut self, theme: Theme) {
+        if let Some(existing) = self.themes.iter_mut().find(|existing| existing.name == theme.name) {
+            *existing = theme;
+        } else {
+            self.themes.push(theme);
+        }
+    }
+    pub fn set_color_depth(&mut self, color_depth: ColorDepth) {
+        for theme in &mut self.themes {
+            theme.color_depth = color_depth;
+        }
+    }
+    pub fn current(&self) -> &Theme {
+        debug_assert!(self.current < self.themes.len());
+        self.themes.get(self.current).unwrap_or_else(|| {
+            panic!("Theme index {} out of range", self.current)
+        })
+    }
+    pub fn select(&mut self, name: &str) -> bool {
+        if let Some(idx) = self.themes.iter().position(|theme| theme.name == name) {
+            self.current = idx;
+            true
+        } else {
+            false
+        }
+    }
+    pub fn select_next(&mut self) {
+        self.current = self.current.saturating_add(1);
+        if self.current >= self.themes.len() {
+            self.current = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_parse_from_hex_palette_indices_and_names() {
+        assert_eq!(parse_color("#1c2b3a"), Ok(Color::Rgb { r: 0x1c, g: 0x2b, b: 0x3a }));
+        assert_eq!(parse_color("208"), Ok(Color::AnsiValue(208)));
+        assert_eq!(parse_color("dark_red"), Ok(Color::DarkRed));
+    }
+
+    #[test]
+    fn bad_colors_are_rejected() {
+        for value in ["#12345", "#1234567", "#12345g", "#", "#ééé", "256", "purplish"] {
+            assert!(parse_color(value).is_err(), "{value}");
+        }
+        assert!(Theme::from_toml("name = \"x\"\n[text]\nforeground = \"#12\"").is_err());
+        assert!(Theme::from_toml("name = \"x\"\n[texts]\nforeground = \"red\"").is_err());
+    }
+
+    #[test]
+    fn user_themes_replace_built_in_ones_of_the_same_name() {
+        let config_dir = std::env::temp_dir().join(format!("hecto-theme-test-{}", std::process::id()));
+        let themes_dir = config_dir.join(THEMES_DIR);
+        std::fs::create_dir_all(&themes_dir).unwrap();
+        std::fs::write(themes_dir.join("dark.toml"), "name = \"dark\"\n[text]\nforeground = \"#010203\"\n").unwrap();
+        std::fs::write(themes_dir.join("mine.toml"), "name = \"mine\"\n").unwrap();
+
+        let (mut themes, errors) = Themes::load(Some(&config_dir));
+        let _ = std::fs::remove_dir_all(&config_dir);
+
+        assert!(errors.is_empty());
+        assert_eq!(themes.themes.len(), BUILT_IN_THEMES.len().saturating_add(1));
+        assert!(themes.select("dark"));
+        assert_eq!(
+            themes.current().style(ThemeElement::Text).foreground,
+            Some(Color::Rgb { r: 1, g: 2, b: 3 })
+        );
+        assert!(themes.select("mine"));
+    }
+}
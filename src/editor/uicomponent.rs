@@ -1,6 +1,6 @@
 use std::io::Error;
 
-use super::Size;
+use super::{Screen, Size, Theme};
 
 pub trait UIComponent {
     fn set_needs_redraw(&mut self, value: bool);
@@ -10,9 +10,9 @@ pub trait UIComponent {
         self.set_needs_redraw(true);
     }
     fn set_size(&mut self, size: Size);
-    fn render(&mut self, origin_row: usize) {
+    fn render(&mut self, screen: &mut Screen, theme: &Theme, origin_row: usize) {
         if self.needs_redraw() {
-            if let Err(err) = self.draw(origin_row) {
+            if let Err(err) = self.draw(screen, theme, origin_row) {
                 #[cfg(debug_assertions)]
                 {
                     panic!("Could not render component: {err:?}");
@@ -26,5 +26,5 @@ pub trait UIComponent {
             }
         }
     }
-    fn draw(&mut self, origin_row: usize) -> Result<(), Error>;
+    fn draw(&mut self, screen: &mut Screen, theme: &Theme, origin_row: usize) -> Result<(), Error>;
 }
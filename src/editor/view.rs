@@ -1,16 +1,28 @@
-use std::{cmp::min, io::Error};
+use std::{
+    cmp::min,
+    io::{Error, Read, Write},
+    ops::{Range, RangeInclusive},
+};
+
+use regex::Regex;
 
 use super::{
-    command::{Edit, Move},
-    Col, Row, DocumentStatus, Line, Position, Size, Terminal, UIComponent, NAME, VERSION,
-    annotatedstring::{AnnotatedString},
+    command::{Edit, Lines, Move, SortKey, SortOptions},
+    config::{BufferSettings, Charset},
+    filetype::FileType,
+    vim::{InsertAt, Motion, Register},
+    Col, Row, DocumentStatus, Line, Position, Screen, Size, Theme, ThemeElement, UIComponent,
+    NAME, VERSION,
 };
 mod buffer;
 use buffer::Buffer;
 mod fileinfo;
 use fileinfo::FileInfo;
+mod history;
+use history::History;
 mod location;
 use location::Location;
+mod reflow;
 mod searchinfo;
 use searchinfo::SearchInfo;
 
@@ -20,8 +32,40 @@ pub struct View {
     needs_redraw: bool,
     size: Size,
     text_location: Location,
+    /// Further carets besides `text_location`, in document order. Edits and
+    /// moves apply at all of them.
+    carets: Vec<Location>,
     scroll_offset: Position,
     search_info: Option<SearchInfo>,
+    selection_anchor: Option<Location>,
+    /// Whether the selection is the block of columns between the anchor and
+    /// the caret, rather than the text between them.
+    block_selection: bool,
+    /// The brackets highlighted as the pair at the caret.
+    bracket_pair: Option<(Location, Location)>,
+}
+
+/// A range of text an operator acts on. Character ranges end before `end`,
+/// blocks before the column `end_col`.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum TextRange {
+    Chars { start: Location, end: Location },
+    Lines { first: usize, last: usize },
+    Block { first: usize, last: usize, start_col: usize, end_col: usize },
+}
+
+impl TextRange {
+    /// The lines the range touches. A character range ending at the start of
+    /// a line leaves that line out.
+    pub fn lines(self) -> RangeInclusive<usize> {
+        match self {
+            Self::Chars { start, end } if end.line_idx > start.line_idx && end.grapheme_idx == 0 => {
+                start.line_idx..=end.line_idx.saturating_sub(1)
+            }
+            Self::Chars { start, end } => start.line_idx..=end.line_idx,
+            Self::Lines { first, last } | Self::Block { first, last, .. } => first..=last,
+        }
+    }
 }
 
 #[derive(Default, Eq, PartialEq, Clone, Copy)]
@@ -38,18 +82,70 @@ impl View {
             current_line_idx: self.text_location.line_idx,
             file_name: format!("{}", self.buffer.file_info),
             is_modified: self.buffer.dirty,
+            mode: None,
         }
     }
     pub fn handle_edit_command(&mut self, command: Edit) {
+        if self.carets.is_empty() {
+            self.edit_at_caret(command);
+            if let Edit::Insert(character) = command
+                && !character.is_whitespace()
+                && self.buffer.settings().auto_wrap
+            {
+                self.auto_wrap();
+            }
+        } else {
+            self.edit_at_all_carets(command);
+        }
+        // The caret may have been scrolled out of view with the mouse wheel.
+        self.scroll_text_location_into_view();
+    }
+    /// Applies `command` at the caret, and returns the edited span as the
+    /// location text after it moved from and the one it moved to.
+    fn edit_at_caret(&mut self, command: Edit) -> (Location, Location) {
+        let before = self.text_location;
         match command {
+            Edit::Insert('\t') if self.buffer.settings().expand_tabs => self.insert_tab_as_spaces(),
+            Edit::Insert(character) if self.buffer.settings().auto_pairs => return self.insert_paired(character),
+            Edit::DeleteBackward if self.is_in_empty_pair() => {
+                let after = self.location_after_delete(before);
+                self.delete_backward();
+                self.delete();
+                return (after, self.text_location);
+            }
             Edit::Insert(character) => self.insert_char(character),
-            Edit::Delete => self.delete(),
+            Edit::Delete => {
+                let after = self.location_after_delete(before);
+                self.delete();
+                return (after, before);
+            }
             Edit::DeleteBackward => self.delete_backward(),
             Edit::InsertNewLine => self.insert_newline(),
         }
+        (before, self.text_location)
     }
     pub fn handle_move_command(&mut self, command: Move) {
-
+        if !self.carets.is_empty() {
+            let primary = self.text_location;
+            for idx in 0..self.carets.len() {
+                let Some(&caret) = self.carets.get(idx) else {
+                    break;
+                };
+                self.text_location = caret;
+                self.move_caret(command);
+                if let Some(caret) = self.carets.get_mut(idx) {
+                    *caret = self.text_location;
+                }
+            }
+            self.text_location = primary;
+            self.set_needs_redraw(true);
+        }
+        self.move_caret(command);
+        self.merge_carets();
+        self.scroll_text_location_into_view();
+    }
+    /// Moves only the caret at `text_location`.
+    fn move_caret(&mut self, command: Move) {
         let Size {height, ..} = self.size;
 
         match command {
@@ -61,24 +157,114 @@ impl View {
             Move::PageDown => self.move_down(height.saturating_sub(1)),
             Move::StartOfLine => self.move_to_start_of_line(),
             Move::EndOfLine => self.move_to_end_of_line(),
+            Move::MatchingBracket => self.move_to_matching_bracket(),
         }
-        self.scroll_text_location_into_view();
     }
     pub const fn is_file_loaded(&self) -> bool {
         self.buffer.is_file_loaded()
     }
+    pub fn charset(&self) -> Charset {
+        self.buffer.charset()
+    }
+    pub fn file_type(&self) -> &'static FileType {
+        self.buffer.file_type()
+    }
+    pub fn apply_settings(&mut self, settings: BufferSettings) {
+        self.buffer.apply_settings(settings);
+        self.scroll_text_location_into_view();
+        self.set_needs_redraw(true);
+    }
     // region: file i/o
-    pub fn load(&mut self, filename: &String) -> Result<(), Error> {
+    pub fn load(&mut self, filename: &str) -> Result<(), Error> {
         let buffer = Buffer::load(filename)?;
-        self.buffer = buffer;
-        self.set_needs_redraw(true);
+        self.replace_buffer(buffer);
+        Ok(())
+    }
+    pub fn load_from_reader(&mut self, reader: &mut impl Read) -> Result<(), Error> {
+        let buffer = Buffer::load_from_reader(reader)?;
+        self.replace_buffer(buffer);
         Ok(())
     }
+    /// Shows `buffer` from the top, dropping any search or selection.
+    fn replace_buffer(&mut self, buffer: Buffer) {
+        *self = Self {
+            buffer,
+            size: self.size,
+            ..Self::default()
+        };
+        self.set_needs_redraw(true);
+    }
+    pub fn file_name(&self) -> Option<String> {
+        self.buffer
+            .file_info
+            .get_path()
+            .map(|path| path.to_string_lossy().into_owned())
+    }
+    pub fn write_to(&self, writer: &mut impl Write) -> Result<(), Error> {
+        self.buffer.write_to(writer)
+    }
     pub fn save(&mut self) -> Result<(), Error> {
-        self.buffer.save()
+        let result = self.buffer.save();
+        self.snap_after_save();
+        result
+    }
+    pub fn check_changed_on_disk(&mut self) -> bool {
+        self.buffer.check_changed_on_disk()
     }
     pub fn save_as(&mut self, file_name: &str) -> Result<(), Error> {
-        self.buffer.save_as(file_name)
+        let result = self.buffer.save_as(file_name);
+        self.snap_after_save();
+        result
+    }
+    pub fn autosave(&mut self) -> Result<(), Error> {
+        self.buffer.autosave()
+    }
+    /// Keeps the carets within the text the save hooks may have trimmed.
+    fn snap_after_save(&mut self) {
+        self.snap_to_valid_line();
+        self.snap_to_valid_grapheme();
+        let buffer = &self.buffer;
+        self.carets.retain(|caret| caret.line_idx < buffer.height());
+        for caret in &mut self.carets {
+            let line_len = buffer.lines.get(caret.line_idx).map_or(0, Line::grapheme_count);
+            caret.grapheme_idx = caret.grapheme_idx.min(line_len);
+        }
+        self.merge_carets();
+        self.set_needs_redraw(true);
+    }
+    // endregion
+    // region: undo
+    pub fn begin_undo_step(&mut self, typing: bool) {
+        self.buffer.begin_undo_step(self.text_location, typing);
+    }
+    pub fn end_undo_step(&mut self) {
+        self.buffer.end_undo_step();
+    }
+    /// Undoes the last step and puts the caret back where it was before it.
+    /// Returns false if there is nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        let Some(caret) = self.buffer.undo(self.text_location) else {
+            return false;
+        };
+        self.move_caret_after_history_change(caret);
+        true
+    }
+    /// Redoes the last undone step. Returns false if there is nothing to
+    /// redo.
+    pub fn redo(&mut self) -> bool {
+        let Some(caret) = self.buffer.redo(self.text_location) else {
+            return false;
+        };
+        self.move_caret_after_history_change(caret);
+        true
+    }
+    fn move_caret_after_history_change(&mut self, caret: Location) {
+        self.carets.clear();
+        self.text_location = caret;
+        self.snap_to_valid_line();
+        self.snap_to_valid_grapheme();
+        self.scroll_text_location_into_view();
+        self.set_needs_redraw(true);
     }
     // endregion
     // region: search
@@ -152,10 +338,737 @@ impl View {
         self.search_in_direction(self.text_location, SearchDirection::Backward);
     }
     // endregion
+    // region: ex commands
+    pub fn current_line_idx(&self) -> usize {
+        self.text_location.line_idx
+    }
+    pub fn height(&self) -> usize {
+        self.buffer.height()
+    }
+    pub fn settings(&self) -> BufferSettings {
+        *self.buffer.settings()
+    }
+    /// Moves the caret to the start of the given line, or the last one.
+    pub fn goto_line(&mut self, line_idx: usize) {
+        self.text_location = Location {
+            line_idx: line_idx.min(self.buffer.height().saturating_sub(1)),
+            grapheme_idx: 0,
+        };
+        self.center_text_location();
+    }
+    pub fn substitute(
+        &mut self,
+        lines: RangeInclusive<usize>,
+        regex: &Regex,
+        replacement: &str,
+        global: bool,
+    ) -> usize {
+        let replacements = self.buffer.substitute(lines, regex, replacement, global);
+        if replacements > 0 {
+            self.snap_to_valid_line();
+            self.snap_to_valid_grapheme();
+            self.scroll_text_location_into_view();
+            self.set_needs_redraw(true);
+        }
+        replacements
+    }
+    // endregion
+    // region: vim
+    /// Where `motion` takes the caret. `count` defaults to 1, except for the
+    /// motions to the first and last line, where it names a line.
+    fn motion_target(&self, motion: Motion, count: Option<usize>) -> Location {
+        let times = count.unwrap_or(1).max(1);
+        let from = self.text_location;
+        let last_line_idx = self.buffer.height().saturating_sub(1);
+        let line_len = |line_idx: usize| self.buffer.lines.get(line_idx).map_or(0, Line::grapheme_count);
+        let on_line = |line_idx: usize| {
+            let line_idx = line_idx.min(last_line_idx);
+            Location {
+                line_idx,
+                grapheme_idx: from.grapheme_idx.min(line_len(line_idx)),
+            }
+        };
+        let repeat = |step: &dyn Fn(Location) -> Location| (0..times).fold(from, |at, _| step(at));
+
+        match motion {
+            Motion::Left => Location {
+                grapheme_idx: from.grapheme_idx.saturating_sub(times),
+                ..from
+            },
+            Motion::Right => Location {
+                grapheme_idx: from
+                    .grapheme_idx
+                    .saturating_add(times)
+                    .min(line_len(from.line_idx)),
+                ..from
+            },
+            Motion::Up => on_line(from.line_idx.saturating_sub(times)),
+            Motion::Down => on_line(from.line_idx.saturating_add(times)),
+            Motion::WordStart => repeat(&|at| self.buffer.next_word_start(at)),
+            Motion::WordEnd => repeat(&|at| self.buffer.word_end(at)),
+            Motion::WordBackward => repeat(&|at| self.buffer.prev_word_start(at)),
+            Motion::LineStart => Location {
+                grapheme_idx: 0,
+                ..from
+            },
+            Motion::LineEnd => {
+                let line_idx = from
+                    .line_idx
+                    .saturating_add(times.saturating_sub(1))
+                    .min(last_line_idx);
+                Location {
+                    line_idx,
+                    grapheme_idx: line_len(line_idx),
+                }
+            }
+            Motion::MatchingBracket => self.matching_bracket(from),
+            Motion::CurrentLine => on_line(from.line_idx.saturating_add(times.saturating_sub(1))),
+            Motion::FirstLine | Motion::LastLine => {
+                let default = if motion == Motion::FirstLine { 0 } else { last_line_idx };
+                let line_idx = count.map_or(default, |line| line.saturating_sub(1));
+                Location {
+                    line_idx: line_idx.min(last_line_idx),
+                    grapheme_idx: 0,
+                }
+            }
+        }
+    }
+    pub fn move_by(&mut self, motion: Motion, count: Option<usize>) {
+        self.text_location = self.motion_target(motion, count);
+        self.scroll_text_location_into_view();
+        if self.selection_anchor.is_some() {
+            self.set_needs_redraw(true);
+        }
+    }
+    /// The text `motion` moves over, as an operator sees it.
+    pub fn motion_range(&self, motion: Motion, count: Option<usize>) -> TextRange {
+        let from = self.text_location;
+        let mut target = self.motion_target(motion, count);
+        if motion.is_linewise() {
+            return TextRange::Lines {
+                first: from.line_idx.min(target.line_idx),
+                last: from.line_idx.max(target.line_idx),
+            };
+        }
+        if motion.is_inclusive() {
+            // Inclusive motions may go backwards, so extend whichever end
+            // comes last.
+            return TextRange::Chars {
+                start: from.min(target),
+                end: self.location_after(from.max(target)),
+            };
+        } else if target.line_idx > from.line_idx && target.grapheme_idx == 0 {
+            // An exclusive motion ending at the start of a line stops at the
+            // end of the line before, so that `dw` on the last word of a
+            // line keeps the line break.
+            let line_idx = target.line_idx.saturating_sub(1);
+            target = Location {
+                line_idx,
+                grapheme_idx: self.buffer.lines.get(line_idx).map_or(0, Line::grapheme_count),
+            };
+        }
+        TextRange::Chars {
+            start: from.min(target),
+            end: from.max(target),
+        }
+    }
+    /// The location after `at`, without leaving its line.
+    fn location_after(&self, at: Location) -> Location {
+        let line_len = self.buffer.lines.get(at.line_idx).map_or(0, Line::grapheme_count);
+        Location {
+            grapheme_idx: at.grapheme_idx.saturating_add(1).min(line_len),
+            ..at
+        }
+    }
+    /// Where the text after `at` starts, which is on the next line at the
+    /// end of a line.
+    fn location_after_delete(&self, at: Location) -> Location {
+        let line_len = self.buffer.lines.get(at.line_idx).map_or(0, Line::grapheme_count);
+        if at.grapheme_idx < line_len {
+            Location {
+                grapheme_idx: at.grapheme_idx.saturating_add(1),
+                ..at
+            }
+        } else if at.line_idx.saturating_add(1) < self.buffer.height() {
+            Location {
+                line_idx: at.line_idx.saturating_add(1),
+                grapheme_idx: 0,
+            }
+        } else {
+            at
+        }
+    }
+    pub fn is_blank_at_caret(&self) -> bool {
+        self.buffer.is_blank_at(self.text_location)
+    }
+    pub fn text_in_range(&self, range: TextRange) -> Register {
+        match range {
+            TextRange::Chars { start, end } => Register {
+                text: self.buffer.text_in(start, end),
+                linewise: false,
+                blockwise: false,
+            },
+            TextRange::Lines { first, last } => Register {
+                text: self.buffer.lines_text(first..=last),
+                linewise: true,
+                blockwise: false,
+            },
+            TextRange::Block { first, last, start_col, end_col } => Register {
+                text: self.buffer.block_text(first..=last, &(start_col..end_col)),
+                linewise: false,
+                blockwise: true,
+            },
+        }
+    }
+    pub fn delete_range(&mut self, range: TextRange) {
+        match range {
+            TextRange::Chars { start, end } => {
+                self.buffer.delete_range(start, end);
+                self.text_location = start;
+            }
+            TextRange::Lines { first, last } => {
+                self.buffer.delete_lines(first..=last);
+                self.text_location = Location {
+                    line_idx: first.min(self.buffer.height().saturating_sub(1)),
+                    grapheme_idx: 0,
+                };
+            }
+            TextRange::Block { first, last, start_col, end_col } => {
+                self.buffer.delete_block(first..=last, &(start_col..end_col));
+                self.text_location = self.location_at_col(first, start_col);
+            }
+        }
+        self.scroll_text_location_into_view();
+        self.set_needs_redraw(true);
+    }
+    /// Deletes the range for `c`. Whole lines are replaced by an empty one to
+    /// type into, and a block by a caret on each of its lines.
+    pub fn change_range(&mut self, range: TextRange) {
+        match range {
+            TextRange::Chars { .. } => self.delete_range(range),
+            TextRange::Lines { first, last } => {
+                self.buffer.delete_lines(first..=last);
+                self.buffer.insert_lines(first, "");
+                self.text_location = Location {
+                    line_idx: first,
+                    grapheme_idx: 0,
+                };
+                self.scroll_text_location_into_view();
+                self.set_needs_redraw(true);
+            }
+            TextRange::Block { first, last, start_col, .. } => {
+                self.delete_range(range);
+                self.add_carets_in_column(first..=last, start_col);
+            }
+        }
+    }
+    pub fn move_to_range_start(&mut self, range: TextRange) {
+        self.text_location = match range {
+            TextRange::Chars { start, .. } => start,
+            TextRange::Lines { first, .. } => Location {
+                line_idx: first,
+                grapheme_idx: 0,
+            },
+            TextRange::Block { first, start_col, .. } => self.location_at_col(first, start_col),
+        };
+        self.scroll_text_location_into_view();
+    }
+    /// The grapheme shown at `col` on the given line.
+    fn location_at_col(&self, line_idx: usize, col: usize) -> Location {
+        Location {
+            line_idx,
+            grapheme_idx: self.buffer.lines.get(line_idx).map_or(0, |line| line.grapheme_idx_at(col)),
+        }
+    }
+    /// Puts a caret at column `col` of each of the lines, the primary one on
+    /// the first. Lines too short for that are padded with spaces.
+    pub fn add_carets_in_column(&mut self, lines: RangeInclusive<usize>, col: usize) {
+        let first = *lines.start();
+        self.carets.clear();
+        for line_idx in lines {
+            self.buffer.pad_line(line_idx, col);
+            self.carets.push(self.location_at_col(line_idx, col));
+        }
+        self.text_location = self.location_at_col(first, col);
+        self.merge_carets();
+        self.scroll_text_location_into_view();
+        self.set_needs_redraw(true);
+    }
+    /// Puts the register after the caret, or after the current line if it
+    /// holds whole lines. The caret ends up on the last grapheme put, or on
+    /// the first line put.
+    pub fn put(&mut self, register: &Register, before: bool, count: usize) {
+        if register.text.is_empty() && !register.linewise {
+            return;
+        }
+        if register.blockwise {
+            self.put_block(&register.text, before, count);
+            return;
+        }
+        let text = vec![register.text.as_str(); count.max(1)].join(if register.linewise { "\n" } else { "" });
+        if register.linewise {
+            let line_idx = if before || self.buffer.is_empty() {
+                self.text_location.line_idx
+            } else {
+                self.text_location.line_idx.saturating_add(1)
+            };
+            self.buffer.insert_lines(line_idx, &text);
+            self.text_location = Location {
+                line_idx,
+                grapheme_idx: 0,
+            };
+        } else {
+            let at = if before || self.buffer.is_empty() {
+                self.text_location
+            } else {
+                self.location_after(self.text_location)
+            };
+            let end = self.buffer.insert_text(at, &text);
+            self.text_location = Location {
+                grapheme_idx: end.grapheme_idx.saturating_sub(1),
+                ..end
+            };
+        }
+        self.scroll_text_location_into_view();
+        self.set_needs_redraw(true);
+    }
+    /// Puts the rows of a block into the lines from the caret down, at the
+    /// caret's column or right after it, adding and padding lines as needed.
+    /// Rows followed by text are padded to the width of the block. The caret
+    /// ends up on the top left of what was put.
+    fn put_block(&mut self, text: &str, before: bool, count: usize) {
+        let at = self.text_location;
+        let col = self.buffer.lines.get(at.line_idx).map_or(0, |line| {
+            let col = line.width_until(at.grapheme_idx);
+            if before || line.grapheme_count() == 0 {
+                col
+            } else {
+                col.saturating_add(line.width_at(at.grapheme_idx))
+            }
+        });
+        let rows: Vec<String> = text.split('\n').map(|row| row.repeat(count.max(1))).collect();
+        let width = rows.iter().map(|row| Line::from(row).width()).max().unwrap_or(0);
+        for (line_idx, row) in (at.line_idx..).zip(&rows) {
+            self.buffer.pad_line(line_idx, col);
+            let location = self.location_at_col(line_idx, col);
+            let has_text_after = self
+                .buffer
+                .lines
+                .get(line_idx)
+                .is_some_and(|line| location.grapheme_idx < line.grapheme_count());
+            let padding = if has_text_after {
+                " ".repeat(width.saturating_sub(Line::from(row).width()))
+            } else {
+                String::new()
+            };
+            self.buffer.insert_text(location, &format!("{row}{padding}"));
+        }
+        self.text_location = self.location_at_col(at.line_idx, col);
+        self.scroll_text_location_into_view();
+        self.set_needs_redraw(true);
+    }
+    pub fn prepare_insert(&mut self, at: InsertAt) {
+        let line_idx = self.text_location.line_idx;
+        match at {
+            InsertAt::Caret => {}
+            InsertAt::AfterCaret => self.text_location = self.location_after(self.text_location),
+            InsertAt::BlockStart | InsertAt::BlockEnd => {
+                if let Some(TextRange::Block { first, last, start_col, end_col }) = self.selection_range() {
+                    self.clear_selection();
+                    let col = if at == InsertAt::BlockStart { start_col } else { end_col };
+                    self.add_carets_in_column(first..=last, col);
+                }
+            }
+            InsertAt::LineStart => self.move_to_start_of_line(),
+            InsertAt::LineEnd => self.move_to_end_of_line(),
+            InsertAt::LineBelow | InsertAt::LineAbove => {
+                let line_idx = if at == InsertAt::LineBelow && !self.buffer.is_empty() {
+                    line_idx.saturating_add(1)
+                } else {
+                    line_idx
+                };
+                self.buffer.insert_lines(line_idx, "");
+                self.text_location = Location {
+                    line_idx,
+                    grapheme_idx: 0,
+                };
+                self.set_needs_redraw(true);
+            }
+        }
+        self.scroll_text_location_into_view();
+    }
+    /// Keeps the caret on a grapheme, as it has no room past the end of a
+    /// line outside of insert mode.
+    pub fn clamp_caret_to_text(&mut self) {
+        let last_line_idx = self.buffer.height().saturating_sub(1);
+        self.text_location.line_idx = self.text_location.line_idx.min(last_line_idx);
+        let line_len = self
+            .buffer
+            .lines
+            .get(self.text_location.line_idx)
+            .map_or(0, Line::grapheme_count);
+        self.text_location.grapheme_idx = self
+            .text_location
+            .grapheme_idx
+            .min(line_len.saturating_sub(1));
+        self.scroll_text_location_into_view();
+    }
+    /// Steps left for leaving insert mode, without wrapping to the line above.
+    pub fn step_left_in_line(&mut self) {
+        self.move_by(Motion::Left, None);
+    }
+    // region: selection
+    pub fn start_selection(&mut self) {
+        self.selection_anchor = Some(self.text_location);
+        self.block_selection = false;
+        self.set_needs_redraw(true);
+    }
+    pub fn start_block_selection(&mut self) {
+        self.start_selection();
+        self.block_selection = true;
+    }
+    /// Turns the selection into a block or back, keeping its anchor.
+    pub fn set_block_selection(&mut self, block: bool) {
+        self.block_selection = block;
+        self.set_needs_redraw(true);
+    }
+    pub fn clear_selection(&mut self) {
+        self.block_selection = false;
+        if self.selection_anchor.take().is_some() {
+            self.set_needs_redraw(true);
+        }
+    }
+    /// The selected text, which includes the grapheme under the caret.
+    pub fn selection_range(&self) -> Option<TextRange> {
+        let anchor = self.selection_anchor?;
+        if self.block_selection {
+            return Some(self.block_between(anchor, self.text_location));
+        }
+        let start = anchor.min(self.text_location);
+        let end = self.location_after(anchor.max(self.text_location));
+        Some(TextRange::Chars { start, end })
+    }
+    /// The block with `from` and `to` in opposite corners. It is as wide as
+    /// the columns they are shown at, and includes the graphemes on them.
+    fn block_between(&self, from: Location, to: Location) -> TextRange {
+        let cols = |location: Location| {
+            self.buffer.lines.get(location.line_idx).map_or(0..1, |line| {
+                let col = line.width_until(location.grapheme_idx);
+                col..col.saturating_add(line.width_at(location.grapheme_idx))
+            })
+        };
+        let (from_cols, to_cols) = (cols(from), cols(to));
+        TextRange::Block {
+            first: from.line_idx.min(to.line_idx),
+            last: from.line_idx.max(to.line_idx),
+            start_col: from_cols.start.min(to_cols.start),
+            end_col: from_cols.end.max(to_cols.end),
+        }
+    }
+    /// The lines the selection touches.
+    pub fn selected_lines(&self) -> Option<RangeInclusive<usize>> {
+        self.selection_range().map(TextRange::lines)
+    }
+    fn selected_graphemes(&self, line_idx: usize) -> Option<Range<usize>> {
+        match self.selection_range()? {
+            TextRange::Chars { start, end } => {
+                if !(start.line_idx..=end.line_idx).contains(&line_idx) {
+                    return None;
+                }
+                let first = if line_idx == start.line_idx { start.grapheme_idx } else { 0 };
+                let last = if line_idx == end.line_idx { end.grapheme_idx } else { usize::MAX };
+                Some(first..last)
+            }
+            TextRange::Block { first, last, start_col, end_col } => {
+                if !(first..=last).contains(&line_idx) {
+                    return None;
+                }
+                let line = self.buffer.lines.get(line_idx)?;
+                Some(line.grapheme_range_in_cols(start_col..end_col))
+            }
+            TextRange::Lines { .. } => None,
+        }
+    }
+    /// Selects the word under the caret, see `Buffer::word_around`.
+    pub fn select_word(&mut self) {
+        let range = self.buffer.word_around(self.text_location);
+        self.select_in_line(range);
+    }
+    pub fn select_line(&mut self) {
+        let line_len = self
+            .buffer
+            .lines
+            .get(self.text_location.line_idx)
+            .map_or(0, Line::grapheme_count);
+        self.select_in_line(0..line_len);
+    }
+    /// Selects the graphemes in `range` of the current line. The selection
+    /// includes the grapheme under the caret, so the caret goes on the last
+    /// one.
+    fn select_in_line(&mut self, range: Range<usize>) {
+        self.selection_anchor = Some(Location {
+            grapheme_idx: range.start,
+            ..self.text_location
+        });
+        self.text_location.grapheme_idx = range.end.saturating_sub(1).max(range.start);
+        self.set_needs_redraw(true);
+    }
+    // end region
+    // region: mouse
+    /// Moves the caret to the text shown at `position`, relative to the top
+    /// left of the view. Below the last line, that is the end of the text;
+    /// right of the end of a line, the end of that line. Positions below the
+    /// view scroll it.
+    pub fn move_caret_to_position(&mut self, position: Position) {
+        let line_idx = position
+            .row
+            .saturating_add(self.scroll_offset.row)
+            .min(self.buffer.height().saturating_sub(1));
+        let col = position.col.saturating_add(self.scroll_offset.col);
+        self.text_location = Location {
+            line_idx,
+            grapheme_idx: self
+                .buffer
+                .lines
+                .get(line_idx)
+                .map_or(0, |line| line.grapheme_idx_at(col)),
+        };
+        self.scroll_text_location_into_view();
+        if self.selection_anchor.is_some() {
+            self.set_needs_redraw(true);
+        }
+    }
+    /// Scrolls down by `lines` lines, or up if negative, leaving the caret
+    /// where it is. The last line can go up to the top of the view.
+    pub fn scroll_by(&mut self, lines: isize) {
+        let last_line_idx = self.buffer.height().saturating_sub(1);
+        let row = self
+            .scroll_offset
+            .row
+            .saturating_add_signed(lines)
+            .min(last_line_idx);
+        if row != self.scroll_offset.row {
+            self.scroll_offset.row = row;
+            self.set_needs_redraw(true);
+        }
+    }
+    /// Whether the caret is within the part of the text on screen.
+    pub fn is_caret_visible(&self) -> bool {
+        let Position { row, col } = self.text_location_to_position();
+        (self.scroll_offset.row..self.scroll_offset.row.saturating_add(self.size.height)).contains(&row)
+            && (self.scroll_offset.col..self.scroll_offset.col.saturating_add(self.size.width)).contains(&col)
+    }
+    // end region
+    // endregion
+    // region: carets
+    /// How many carets there are, counting the primary one.
+    pub fn caret_count(&self) -> usize {
+        self.carets.len().saturating_add(1)
+    }
+    /// Drops every caret but the primary one. Returns whether there were any.
+    pub fn clear_carets(&mut self) -> bool {
+        if self.carets.is_empty() {
+            return false;
+        }
+        self.carets.clear();
+        self.set_needs_redraw(true);
+        true
+    }
+    /// Makes `location` the primary caret, keeping the previous one.
+    fn add_caret(&mut self, location: Location) {
+        self.carets.push(self.text_location);
+        self.text_location = location;
+        self.merge_carets();
+        self.scroll_text_location_into_view();
+        self.set_needs_redraw(true);
+    }
+    /// Keeps the carets sorted, and drops those that ended up in the same
+    /// place as another.
+    fn merge_carets(&mut self) {
+        let primary = self.text_location;
+        self.carets.retain(|caret| *caret != primary);
+        self.carets.sort_unstable();
+        self.carets.dedup();
+    }
+    /// Adds a caret in the same column on the line above the topmost caret,
+    /// or below the bottommost one. Returns false at the start or end of
+    /// the text.
+    pub fn add_caret_vertically(&mut self, below: bool) -> bool {
+        let all = self.carets.iter().chain([&self.text_location]);
+        let edge = if below { all.max() } else { all.min() };
+        let Some(line_idx) = edge.and_then(|edge| {
+            if below {
+                Some(edge.line_idx.saturating_add(1)).filter(|line_idx| *line_idx < self.buffer.height())
+            } else {
+                edge.line_idx.checked_sub(1)
+            }
+        }) else {
+            return false;
+        };
+        let col = self.text_location_to_position().col;
+        let grapheme_idx = self.buffer.lines.get(line_idx).map_or(0, |line| line.grapheme_idx_at(col));
+        self.add_caret(Location { line_idx, grapheme_idx });
+        true
+    }
+    /// Adds a caret at the position clicked, see `move_caret_to_position`.
+    pub fn add_caret_at_position(&mut self, position: Position) {
+        let primary = self.text_location;
+        self.move_caret_to_position(position);
+        let clicked = self.text_location;
+        self.text_location = primary;
+        self.add_caret(clicked);
+    }
+    /// The text carets are added at: the selection if it lies within a
+    /// line, or else the word at or right before the caret.
+    fn caret_match_query(&self) -> Option<String> {
+        if let Some(TextRange::Chars { start, end }) = self.selection_range()
+            && start.line_idx == end.line_idx
+        {
+            let line = self.buffer.lines.get(start.line_idx)?;
+            return Some(line.slice(start.grapheme_idx..end.grapheme_idx).to_string());
+        }
+        let line = self.buffer.lines.get(self.text_location.line_idx)?;
+        let at = self.text_location;
+        let before = Location {
+            grapheme_idx: at.grapheme_idx.saturating_sub(1),
+            ..at
+        };
+        [at, before]
+            .into_iter()
+            .map(|location| line.slice(self.buffer.word_around(location)))
+            .find(|word| !word.trim().is_empty())
+            .map(String::from)
+    }
+    /// Where the matches of `query` end, in document order.
+    fn match_ends(&self, query: &str) -> Vec<Location> {
+        self.buffer
+            .lines
+            .iter()
+            .enumerate()
+            .flat_map(|(line_idx, line)| {
+                line.match_ranges(query).into_iter().map(move |range| Location {
+                    line_idx,
+                    grapheme_idx: range.end,
+                })
+            })
+            .collect()
+    }
+    /// Moves the caret to the end of the selection, or of the match it is
+    /// in, which is where carets at the other matches go.
+    fn move_to_end_of_match(&mut self, query: &str) {
+        if let Some(TextRange::Chars { end, .. }) = self.selection_range() {
+            self.text_location = end;
+            self.clear_selection();
+            return;
+        }
+        let caret = self.text_location;
+        if let Some(end) = self
+            .match_ends(query)
+            .into_iter()
+            .find(|end| end.line_idx == caret.line_idx && end.grapheme_idx >= caret.grapheme_idx)
+        {
+            self.text_location = end;
+        }
+    }
+    /// Adds a caret at the end of the next match of the selection, or of the
+    /// word at the caret, after the primary caret and wrapping around. Returns
+    /// false if every match has a caret already.
+    pub fn add_caret_at_next_match(&mut self) -> bool {
+        let Some(query) = self.caret_match_query() else {
+            return false;
+        };
+        self.move_to_end_of_match(&query);
+        let ends = self.match_ends(&query);
+        let is_free = |end: &&Location| **end != self.text_location && !self.carets.contains(end);
+        let next = ends
+            .iter()
+            .filter(is_free)
+            .find(|end| **end > self.text_location)
+            .or_else(|| ends.iter().find(is_free))
+            .copied();
+        match next {
+            Some(location) => {
+                self.add_caret(location);
+                true
+            }
+            None => false,
+        }
+    }
+    /// Puts a caret at the end of every match of the selection, or of the
+    /// word at the caret. Returns false if there is nothing to match.
+    pub fn add_carets_at_all_matches(&mut self) -> bool {
+        let Some(query) = self.caret_match_query() else {
+            return false;
+        };
+        self.move_to_end_of_match(&query);
+        self.carets.extend(self.match_ends(&query));
+        self.merge_carets();
+        self.set_needs_redraw(true);
+        true
+    }
+    /// Applies `command` at every caret, front to back. Each edit moves the
+    /// carets behind it along with the text.
+    fn edit_at_all_carets(&mut self, command: Edit) {
+        let primary = self.text_location;
+        self.carets.push(primary);
+        self.carets.sort_unstable();
+        let primary_idx = self.carets.iter().position(|caret| *caret == primary).unwrap_or(0);
+        for idx in 0..self.carets.len() {
+            let Some(&caret) = self.carets.get(idx) else {
+                break;
+            };
+            self.text_location = caret;
+            self.snap_to_valid_line();
+            self.snap_to_valid_grapheme();
+            let (from, to) = self.edit_at_caret(command);
+            if let Some(caret) = self.carets.get_mut(idx) {
+                *caret = self.text_location;
+            }
+            for later in self.carets.iter_mut().skip(idx.saturating_add(1)) {
+                *later = shift_location(*later, from, to);
+            }
+        }
+        // Carets that ran into each other merge into one.
+        let primary = self.carets.get(primary_idx).copied().unwrap_or(primary);
+        self.text_location = primary;
+        self.merge_carets();
+        self.set_needs_redraw(true);
+    }
+    /// Looks up the bracket pair at the caret, redrawing if it changed.
+    pub fn update_bracket_pair(&mut self) {
+        let bracket_pair = self.buffer.bracket_pair_at(self.text_location);
+        if bracket_pair != self.bracket_pair {
+            self.bracket_pair = bracket_pair;
+            self.set_needs_redraw(true);
+        }
+    }
+    fn brackets_in_line(&self, line_idx: usize) -> Vec<usize> {
+        self.bracket_pair
+            .iter()
+            .flat_map(|(start, end)| [start, end])
+            .filter(|bracket| bracket.line_idx == line_idx)
+            .map(|bracket| bracket.grapheme_idx)
+            .collect()
+    }
+    /// The whitespace at the end of the line, unless the caret is right
+    /// after it, which is where typing leaves the space between words.
+    fn trailing_whitespace_in_line(&self, line_idx: usize, line: &Line) -> Option<Range<usize>> {
+        let trailing = line.trailing_whitespace();
+        let typing = self.text_location.line_idx == line_idx && self.text_location.grapheme_idx >= trailing.start;
+        (!trailing.is_empty() && !typing).then_some(trailing)
+    }
+    fn carets_in_line(&self, line_idx: usize) -> Vec<usize> {
+        self.carets
+            .iter()
+            .filter(|caret| caret.line_idx == line_idx)
+            .map(|caret| caret.grapheme_idx)
+            .collect()
+    }
+    // endregion
     // region: Editing
     fn delete_backward(&mut self) {
         if self.text_location.line_idx != 0 || self.text_location.grapheme_idx != 0 {
-            self.handle_move_command(Move::Left);
+            self.move_caret(Move::Left);
             self.delete();
         }
     }
@@ -165,8 +1078,18 @@ impl View {
     }
     fn insert_newline(&mut self) {
         self.buffer.insert_newline(self.text_location);
-        self.handle_move_command(Move::Right);
-        self.set_needs_redraw(true)
+        self.move_caret(Move::Right);
+        self.set_needs_redraw(true);
+    }
+    /// Inserts spaces up to the next tab stop.
+    fn insert_tab_as_spaces(&mut self) {
+        let tab_width = self.buffer.settings().tab_width.max(1);
+        let col = self.text_location_to_position().col;
+        #[allow(clippy::arithmetic_side_effects)]
+        let spaces = tab_width - col % tab_width;
+        for _ in 0..spaces {
+            self.insert_char(' ');
+        }
     }
     fn insert_char(&mut self, character: char) {
         let old_len = self
@@ -184,33 +1107,229 @@ impl View {
 
         let grapheme_delta = new_len.saturating_sub(old_len);
         if grapheme_delta > 0 {
-            self.handle_move_command(Move::Right);
+            self.move_caret(Move::Right);
         }
         self.set_needs_redraw(true);
     }
-    // region: Rendering
-    fn render_annotated_string(at: usize, annotated_string: AnnotatedString) -> Result<(), Error> {
-        Terminal::print_annotated_row(at, &annotated_string)
+    // region: line commands
+    /// Applies `command` to the lines the selection touches, or else to the
+    /// caret's line. The caret follows the text it was on, and the other
+    /// carets go, as the lines under them may move.
+    pub fn handle_lines_command(&mut self, command: Lines) -> Result<(), String> {
+        self.clear_carets();
+        let caret_line = self.text_location.line_idx;
+        let lines = self.selected_lines().unwrap_or(caret_line..=caret_line);
+        match command {
+            Lines::ToggleComment => self.toggle_comment(lines)?,
+            Lines::MoveUp | Lines::MoveDown => {
+                let down = command == Lines::MoveDown;
+                if self.buffer.move_lines(&lines, down) {
+                    self.shift_caret_lines(1, down);
+                }
+            }
+            Lines::Duplicate => {
+                self.buffer.duplicate_lines(&lines);
+                self.shift_caret_lines(lines.count(), true);
+            }
+            Lines::Join => {
+                if let Some(join) = self.buffer.join_lines(&lines) {
+                    self.text_location = join;
+                }
+            }
+            Lines::Delete => {
+                self.buffer.delete_lines(lines.clone());
+                self.text_location.line_idx = *lines.start();
+                self.snap_to_valid_line();
+            }
+            Lines::Sort(options) => {
+                let lines = self.selected_lines().ok_or_else(|| String::from("Nothing selected"))?;
+                self.buffer.sort_lines(&lines, options);
+            }
+            Lines::Reflow => {
+                let lines = self.selected_lines().unwrap_or_else(|| self.buffer.paragraph_around(caret_line));
+                self.reflow(lines);
+            }
+        }
+        self.snap_to_valid_grapheme();
+        self.scroll_text_location_into_view();
+        self.set_needs_redraw(true);
+        Ok(())
     }
-    fn render_line(at: usize, line_text: &str) -> Result<(), Error> {
-
-        Terminal::print_row(at, line_text)
-        
+    /// Rewraps `lines` to the text width, and puts the caret at the start of
+    /// the last line they turned into.
+    pub fn reflow(&mut self, lines: RangeInclusive<usize>) {
+        let text_width = self.buffer.settings().text_width;
+        if let Some(lines) = self.buffer.reflow(&lines, text_width) {
+            self.text_location = Location {
+                line_idx: *lines.end(),
+                grapheme_idx: 0,
+            };
+        }
+        self.scroll_text_location_into_view();
+        self.set_needs_redraw(true);
     }
-    fn build_welcome_message(width: usize) -> String {
-        if width == 0 {
-            return String::new();
+    /// With auto-wrap on, breaks the caret's line at the last whitespace
+    /// within the text width once typing goes past it. The new line starts
+    /// with the prefix of the old one, such as its indentation.
+    fn auto_wrap(&mut self) {
+        let at = self.text_location;
+        let Some((line_idx, break_start, break_end, prefix)) = self.buffer.wrap_point(at) else {
+            return;
+        };
+        self.buffer.delete_range(
+            Location {
+                line_idx,
+                grapheme_idx: break_start,
+            },
+            Location {
+                line_idx,
+                grapheme_idx: break_end,
+            },
+        );
+        self.buffer.insert_newline(Location {
+            line_idx,
+            grapheme_idx: break_start,
+        });
+        let line_start = Location {
+            line_idx: line_idx.saturating_add(1),
+            grapheme_idx: 0,
+        };
+        let after_prefix = self.buffer.insert_text(line_start, &prefix);
+        self.text_location = Location {
+            grapheme_idx: after_prefix
+                .grapheme_idx
+                .saturating_add(at.grapheme_idx.saturating_sub(break_end)),
+            ..after_prefix
+        };
+        self.scroll_text_location_into_view();
+    }
+    /// Sorts `lines`, for `:sort`.
+    pub fn sort_lines(&mut self, lines: &RangeInclusive<usize>, options: SortOptions) {
+        self.buffer.sort_lines(lines, options);
+        self.snap_to_valid_grapheme();
+        self.set_needs_redraw(true);
+    }
+    /// Moves the caret and the selection anchor `by` lines, along with the
+    /// lines they are on. Line commands leave no other carets to move.
+    fn shift_caret_lines(&mut self, by: usize, down: bool) {
+        let shift = |location: &mut Location| {
+            location.line_idx = if down {
+                location.line_idx.saturating_add(by)
+            } else {
+                location.line_idx.saturating_sub(by)
+            };
+        };
+        shift(&mut self.text_location);
+        if let Some(anchor) = &mut self.selection_anchor {
+            shift(anchor);
+        }
+    }
+    /// Toggles comments, keeping the caret on the same text in its line.
+    fn toggle_comment(&mut self, lines: RangeInclusive<usize>) -> Result<(), String> {
+        self.text_location = self.buffer.toggle_comment(lines, self.text_location)?;
+        Ok(())
+    }
+    // end region
+    // region: auto pairs
+    /// The closing partner typing `character` adds with auto-pairing on: that
+    /// of an opening bracket, or the same quote. Quotes are the ones of the
+    /// file type's strings, so that `'` stays single in Rust.
+    pub fn closing_partner(&self, character: char) -> Option<char> {
+        if !self.buffer.settings().auto_pairs {
+            return None;
+        }
+        let quotes = self.buffer.file_type().syntax.map_or(QUOTES.as_slice(), |syntax| syntax.quotes);
+        BRACKET_PAIRS
+            .iter()
+            .find(|(opening, _)| *opening == character)
+            .map(|(_, closing)| *closing)
+            .or_else(|| quotes.contains(&character).then_some(character))
+    }
+    fn grapheme_at(&self, at: Location) -> Option<&str> {
+        self.buffer.lines.get(at.line_idx)?.grapheme(at.grapheme_idx)
+    }
+    /// Types `character`, adding its closing partner after the caret, or
+    /// typing over the closer already there. Quotes only pair up outside of
+    /// words. Returns the edited span like `edit_at_caret`.
+    fn insert_paired(&mut self, character: char) -> (Location, Location) {
+        let before = self.text_location;
+        let next = self.grapheme_at(before).and_then(|grapheme| grapheme.chars().next());
+        let is_closer = BRACKET_PAIRS.iter().any(|(_, closing)| *closing == character)
+            || self.closing_partner(character) == Some(character);
+        if is_closer && next == Some(character) {
+            self.move_caret(Move::Right);
+            return (before, before);
         }
-        
+        let is_word = |ch: Option<char>| ch.is_some_and(|ch| ch.is_alphanumeric() || ch == '_');
+        let previous = before
+            .grapheme_idx
+            .checked_sub(1)
+            .and_then(|grapheme_idx| self.grapheme_at(Location { grapheme_idx, ..before }))
+            .and_then(|grapheme| grapheme.chars().next());
+        let closing = self
+            .closing_partner(character)
+            .filter(|closing| *closing != character || !is_word(previous))
+            .filter(|_| !is_word(next));
+        self.insert_char(character);
+        let Some(closing) = closing else {
+            return (before, self.text_location);
+        };
+        self.buffer.insert_char(closing, self.text_location);
+        let after = Location {
+            grapheme_idx: self.text_location.grapheme_idx.saturating_add(1),
+            ..self.text_location
+        };
+        (before, after)
+    }
+    /// Whether the caret sits between an auto-paired opener and its closer,
+    /// such as in `(|)`.
+    fn is_in_empty_pair(&self) -> bool {
+        let at = self.text_location;
+        let Some(grapheme_idx) = at.grapheme_idx.checked_sub(1) else {
+            return false;
+        };
+        let previous = self.grapheme_at(Location { grapheme_idx, ..at }).and_then(|grapheme| grapheme.chars().next());
+        let next = self.grapheme_at(at).and_then(|grapheme| grapheme.chars().next());
+        previous.is_some_and(|previous| self.closing_partner(previous) == next && next.is_some())
+    }
+    /// Puts `character` and its closing partner around the text in `range`,
+    /// which stays selected.
+    pub fn wrap_range(&mut self, range: TextRange, character: char) {
+        let (Some(closing), TextRange::Chars { start, end }) = (self.closing_partner(character), range) else {
+            return;
+        };
+        self.buffer.insert_char(closing, end);
+        self.buffer.insert_char(character, start);
+        let shift = |at: Location| Location {
+            grapheme_idx: if at.line_idx == start.line_idx {
+                at.grapheme_idx.saturating_add(1)
+            } else {
+                at.grapheme_idx
+            },
+            ..at
+        };
+        self.selection_anchor = Some(shift(start));
+        let end = shift(end);
+        self.text_location = Location {
+            grapheme_idx: end.grapheme_idx.saturating_sub(1),
+            ..end
+        };
+        self.scroll_text_location_into_view();
+        self.set_needs_redraw(true);
+    }
+    // end region
+    // region: Rendering
+    /// The welcome message centered in the space right of the `~` marker.
+    fn build_welcome_message(width: usize) -> String {
         let welcome_message = format!("{NAME} editor -- version {VERSION}");
 
         let len = welcome_message.len();
         let remaining_width = width.saturating_sub(1);
 
         if remaining_width < len {
-            "~" .to_string();
+            return String::new();
         }
-        format!("{:<1}{:^remaining_width$}", "~", welcome_message)
+        format!("{welcome_message:^remaining_width$}")
     }
     // end region
     // region: Scrolling
@@ -315,6 +1434,16 @@ impl View {
     pub fn move_to_start_of_line(&mut self) {
         self.text_location.grapheme_idx = 0;
     }
+    /// Jumps to the other bracket of the pair at the caret, or to the opening
+    /// bracket of the innermost pair around the caret.
+    fn move_to_matching_bracket(&mut self) {
+        self.text_location = self.matching_bracket(self.text_location);
+    }
+    fn matching_bracket(&self, at: Location) -> Location {
+        self.buffer
+            .bracket_pair_at(at)
+            .map_or(at, |(start, end)| if at == start { end } else { start })
+    }
     fn move_to_end_of_line(&mut self) {
         self.text_location.grapheme_idx = self
             .buffer
@@ -347,7 +1476,7 @@ impl UIComponent for View {
         self.size = size;
         self.scroll_text_location_into_view();
     }
-    fn draw(&mut self, origin_row: usize) -> Result<(), Error> {
+    fn draw(&mut self, screen: &mut Screen, theme: &Theme, origin_row: usize) -> Result<(), Error> {
         let Size { height, width } = self.size;
         let end_y = origin_row.saturating_add(height);
 
@@ -363,24 +1492,80 @@ impl UIComponent for View {
                 let left = self.scroll_offset.col;
                 let right = self.scroll_offset.col.saturating_add(width);
                
-                if let Some(search_info) = &self.search_info {
-                    if let Some(query) = &search_info.query {
-                        // let query = search_info.query.unwrap();
-                        let annotated_string = line.get_annotated_visible_substr(left..right, &query);
-                        
-                        Self::render_annotated_string(current_row, annotated_string)?;
-                        continue;
-                    } 
-                }
+                let query = self
+                    .search_info
+                    .as_ref()
+                    .and_then(|search_info| search_info.query.as_deref());
+                let selection = self.selected_graphemes(line_idx);
+                let carets = self.carets_in_line(line_idx);
+                let brackets = self.brackets_in_line(line_idx);
+                let trailing = self.trailing_whitespace_in_line(line_idx, line);
+                if query.is_some()
+                    || selection.is_some()
+                    || !carets.is_empty()
+                    || !brackets.is_empty()
+                    || trailing.is_some()
+                {
+                    let annotated_string = line.get_annotated_visible_substr(
+                        left..right,
+                        query,
+                        selection.as_ref(),
+                        &carets,
+                        &brackets,
+                        trailing.as_ref(),
+                    );
 
-                Self::render_line(current_row, &line.get_visible_graphemes(left..right))?;
+                    screen.print_annotated_row(current_row, &annotated_string, theme);
+                    continue;
+                }
 
-            } else if current_row == top_third && self.buffer.is_empty() {
-                Self::render_line(current_row, &Self::build_welcome_message(width))?;
+                screen.print_row(
+                    current_row,
+                    &line.get_visible_graphemes(left..right),
+                    theme.style(ThemeElement::Text),
+                );
             } else {
-                Self::render_line(current_row, "~")?;
+                screen.print_row(current_row, "~", theme.style(ThemeElement::Gutter));
+                if current_row == top_third && self.buffer.is_empty() {
+                    screen.print_at(
+                        current_row,
+                        1,
+                        &Self::build_welcome_message(width),
+                        theme.style(ThemeElement::Welcome),
+                    );
+                }
             }
         }
         Ok(())
     }
 }
+
+/// Where `location` ends up after the text from `from` is replaced by text up
+/// to `to`: inserting moves `from` forward to `to`, deleting moves it back.
+/// Locations inside deleted text move to where it was.
+fn shift_location(location: Location, from: Location, to: Location) -> Location {
+    if location < from {
+        location.min(to)
+    } else if location.line_idx == from.line_idx {
+        Location {
+            line_idx: to.line_idx,
+            grapheme_idx: location
+                .grapheme_idx
+                .saturating_sub(from.grapheme_idx)
+                .saturating_add(to.grapheme_idx),
+        }
+    } else {
+        Location {
+            line_idx: location
+                .line_idx
+                .saturating_sub(from.line_idx)
+                .saturating_add(to.line_idx),
+            ..location
+        }
+    }
+}
+
+/// The brackets auto-pairing closes.
+const BRACKET_PAIRS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+/// The quotes auto-pairing closes in files without a known syntax.
+const QUOTES: [char; 2] = ['"', '\''];
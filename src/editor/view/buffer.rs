@@ -1,29 +1,132 @@
-use std::fs::{File, read_to_string};
-use std::io::{Write, Error};
+use std::fs::{File, metadata, read};
+use std::io::{Read, Write, Error, ErrorKind};
+use std::ops::{Range, RangeInclusive};
+use std::time::SystemTime;
 
-use super::Line;
+use regex::Regex;
+
+use super::{BufferSettings, Charset, SortKey, SortOptions};
 use super::FileInfo;
+use super::FileType;
+use super::History;
+use super::Line;
 use super::Location;
+use super::reflow::{self, PROSE_MARKERS};
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum CharClass {
+    Blank,
+    Word,
+    Punctuation,
+}
+
+/// Where the bracket scanner is: in code, or inside a comment or a string.
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Scope {
+    Code,
+    BlockComment,
+    String(char),
+}
+
+const BRACKETS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
 
 #[derive(Default)]
 pub struct Buffer {
     pub lines: Vec<Line>,
     pub file_info: FileInfo,
     pub dirty: bool,
+    modified_on_disk: Option<SystemTime>,
+    settings: BufferSettings,
+    /// The file ended without a line break when it was loaded.
+    missing_final_newline: bool,
+    /// The charset the file was read in.
+    charset: Charset,
+    history: History,
 }
 
 impl Buffer {
     pub fn load(file_name: &str) -> Result<Self, Error> {
-        let contents = read_to_string(&file_name)?;
+        let (contents, charset) = decode(&read(file_name)?);
+        let file_info = FileInfo::from(file_name);
+        Ok(Self {
+            modified_on_disk: Self::read_modified_time(&file_info),
+            file_info,
+            charset,
+            ..Self::from_text(&contents)
+        })
+    }
+    pub fn load_from_reader(reader: &mut impl Read) -> Result<Self, Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let (contents, charset) = decode(&bytes);
+        Ok(Self {
+            charset,
+            ..Self::from_text(&contents)
+        })
+    }
+    fn from_text(contents: &str) -> Self {
         let mut lines = Vec::new();
         for value in contents.lines() {
             lines.push(Line::from(value));
         }
-        Ok(Self { 
-            file_info: FileInfo::from(file_name),
+        Self {
             lines,
-            dirty: false,
-        })
+            missing_final_newline: !contents.is_empty() && !contents.ends_with('\n'),
+            ..Self::default()
+        }
+    }
+    pub fn file_type(&self) -> &'static FileType {
+        FileType::detect(self.file_info.get_path())
+    }
+    /// The charset the text is saved in: the one it was read in, unless the
+    /// settings say otherwise.
+    pub fn charset(&self) -> Charset {
+        self.settings.charset.unwrap_or(self.charset)
+    }
+    pub const fn settings(&self) -> &BufferSettings {
+        &self.settings
+    }
+    pub fn apply_settings(&mut self, settings: BufferSettings) {
+        self.settings = settings;
+        for line in &mut self.lines {
+            line.set_tab_width(settings.tab_width);
+        }
+    }
+    /// Keeps the lines for undoing before the first change of an undo step.
+    fn before_change(&mut self) {
+        self.history.before_change(&self.lines);
+    }
+    /// Starts an undo step, with the caret where it is before the step.
+    /// Typing joins the step before if that was typing as well.
+    pub fn begin_undo_step(&mut self, caret: Location, typing: bool) {
+        self.history.begin(caret, typing);
+    }
+    pub fn end_undo_step(&mut self) {
+        self.history.end();
+    }
+    /// Undoes the last step. Returns where the caret was before it, or
+    /// `None` if there is nothing to undo.
+    pub fn undo(&mut self, caret: Location) -> Option<Location> {
+        let caret = self.history.undo(&mut self.lines, caret)?;
+        self.after_history_change();
+        Some(caret)
+    }
+    /// Redoes the last undone step. Returns where the caret was when it was
+    /// undone, or `None` if there is nothing to redo.
+    pub fn redo(&mut self, caret: Location) -> Option<Location> {
+        let caret = self.history.redo(&mut self.lines, caret)?;
+        self.after_history_change();
+        Some(caret)
+    }
+    /// The lines put back may be from before the tab width changed.
+    fn after_history_change(&mut self) {
+        self.apply_settings(self.settings);
+        self.dirty = true;
+    }
+    fn new_line(&self, text: &str) -> Line {
+        let mut line = Line::from(text);
+        line.set_tab_width(self.settings.tab_width);
+        line
     }
     pub fn search_forward(&self, query: &str, from: Location) -> Option<Location> {
         if query.is_empty() {
@@ -31,6 +134,7 @@ impl Buffer {
         }
 
         for line_idx in 0..self.lines.len() {
+            #[allow(clippy::arithmetic_side_effects)]
             let line_idx = (line_idx + from.line_idx) % self.lines.len();
 
             let from_grapheme_idx = if line_idx == from.line_idx {
@@ -41,8 +145,8 @@ impl Buffer {
 
             if let Some(grapheme_idx) = self.lines[line_idx].search_forward(query, from_grapheme_idx) {
                 return Some(Location{
-                    grapheme_idx,
                     line_idx,
+                    grapheme_idx,
                 });
             }
         }
@@ -56,10 +160,10 @@ impl Buffer {
             let mut line_idx = from.line_idx;
 
             if index > line_idx {
-                line_idx += self.lines.len();
+                line_idx = line_idx.saturating_add(self.lines.len());
             }
 
-            line_idx -= index;
+            line_idx = line_idx.saturating_sub(index);
 
             let line = &self.lines[line_idx];
 
@@ -71,8 +175,8 @@ impl Buffer {
 
             if let Some(grapheme_idx) = line.search_backward(query, from_grapheme_idx) {
                 return Some(Location {
-                    grapheme_idx,
                     line_idx,
+                    grapheme_idx,
                 })
             }
         }
@@ -80,12 +184,16 @@ impl Buffer {
         None
 
     }
-    pub fn save_to_file(&self, file_info: &FileInfo) -> Result<(), Error> {
+    /// Runs the save hooks of the buffer settings, then writes the text to
+    /// the file.
+    pub fn save_to_file(&mut self, file_info: &FileInfo) -> Result<(), Error> {
+        self.run_save_hooks();
+        self.write_to_file(file_info)
+    }
+    fn write_to_file(&self, file_info: &FileInfo) -> Result<(), Error> {
         if let Some(file_path) = &file_info.get_path() {
             let mut file = File::create(file_path)?;
-            for line in &self.lines {
-                writeln!(file, "{line}")?;
-            }
+            self.write_to(&mut file)?;
         } else {
             #[cfg(debug_assertions)]
             {
@@ -94,27 +202,93 @@ impl Buffer {
         }
         Ok(())
     }
+    fn run_save_hooks(&mut self) {
+        let hooks = self.settings.save_hooks;
+        if hooks.trim_trailing_whitespace {
+            for line_idx in 0..self.height() {
+                let Some(line) = self.lines.get(line_idx) else {
+                    continue;
+                };
+                if line.trim_end().len() < line.len() {
+                    let trimmed = line.trim_end().to_string();
+                    self.set_line(line_idx, &trimmed);
+                }
+            }
+        }
+        if hooks.trim_trailing_blank_lines {
+            while self.lines.last().is_some_and(|line| line.trim().is_empty()) {
+                self.before_change();
+                self.lines.pop();
+                self.dirty = true;
+            }
+        }
+        if hooks.insert_final_newline {
+            self.missing_final_newline = false;
+        }
+    }
+    /// Writes the text with the line endings and in the charset of the
+    /// buffer settings.
+    pub fn write_to(&self, writer: &mut impl Write) -> Result<(), Error> {
+        let line_ending = self.settings.end_of_line.as_str();
+        let mut text = String::new();
+        for (line_idx, line) in self.lines.iter().enumerate() {
+            text.push_str(line);
+            if !self.missing_final_newline || line_idx.saturating_add(1) < self.height() {
+                text.push_str(line_ending);
+            }
+        }
+        writer.write_all(&encode(&text, self.charset())?)?;
+        writer.flush()
+    }
     pub fn save_as(&mut self, file_name: &str) -> Result<(), Error> {
         let file_info = FileInfo::from(file_name);
         self.save_to_file(&file_info)?;
         self.file_info = file_info;
         self.dirty = false;
+        self.modified_on_disk = Self::read_modified_time(&self.file_info);
         Ok(())
     }
     pub fn save(&mut self) -> Result<(), Error> {
-        self.save_to_file(&self.file_info)?;
+        self.save_to_file(&self.file_info.clone())?;
+        self.dirty = false;
+        self.modified_on_disk = Self::read_modified_time(&self.file_info);
+        Ok(())
+    }
+    /// Saves without the save hooks, which would trim the text while it is
+    /// being typed.
+    pub fn autosave(&mut self) -> Result<(), Error> {
+        self.write_to_file(&self.file_info)?;
         self.dirty = false;
+        self.modified_on_disk = Self::read_modified_time(&self.file_info);
         Ok(())
     }
+    fn read_modified_time(file_info: &FileInfo) -> Option<SystemTime> {
+        file_info
+            .get_path()
+            .and_then(|path| metadata(path).ok())
+            .and_then(|metadata| metadata.modified().ok())
+    }
+    /// Returns true once for every modification made to the file by another
+    /// program since it was loaded or last saved.
+    pub fn check_changed_on_disk(&mut self) -> bool {
+        let modified = Self::read_modified_time(&self.file_info);
+        if modified == self.modified_on_disk {
+            return false;
+        }
+        self.modified_on_disk = modified;
+        true
+    }
     pub fn delete(&mut self, at: Location) {
         if let Some(line) = self.lines.get(at.line_idx) {
             if at.grapheme_idx >= line.grapheme_count() && self.height() > at.line_idx.saturating_add(1) {
+                self.before_change();
                 let next_line = self.lines.remove(at.line_idx.saturating_add(1));
 
                 #[allow(clippy::indexing_slicing)]
                 self.lines[at.line_idx].append(&next_line);
                 self.dirty = true;
             } else if at.grapheme_idx < line.grapheme_count() {
+                self.before_change();
                 #[allow(clippy::indexing_slicing)]
                 self.lines[at.line_idx].delete(at.grapheme_idx);
                 self.dirty = true;
@@ -128,8 +302,9 @@ impl Buffer {
             return;
         }
 
+        self.before_change();
         if at.line_idx == self.height() {
-            self.lines.push(Line::from(&character.to_string()));
+            self.lines.push(self.new_line(&character.to_string()));
             self.dirty = true;
         } else if let Some(line) = self.lines.get_mut(at.line_idx) {
             line.insert_char(character, at.grapheme_idx);
@@ -137,8 +312,9 @@ impl Buffer {
         }
     }
     pub fn insert_newline(&mut self, at: Location) {
+        self.before_change();
         if at.line_idx == self.height() {
-            self.lines.push(Line::default());
+            self.lines.push(self.new_line(""));
             self.dirty = true;
         } else if let Some(line) = self.lines.get_mut(at.line_idx) {
             let new = line.split(at.grapheme_idx);
@@ -147,6 +323,649 @@ impl Buffer {
             self.dirty = true;
         }
     }
+    /// The text between two locations, with line breaks as `\n`.
+    pub fn text_in(&self, from: Location, to: Location) -> String {
+        let mut text = String::new();
+        for line_idx in from.line_idx..=to.line_idx {
+            let Some(line) = self.lines.get(line_idx) else {
+                break;
+            };
+            let start = if line_idx == from.line_idx { from.grapheme_idx } else { 0 };
+            let end = if line_idx == to.line_idx { to.grapheme_idx } else { line.grapheme_count() };
+            text.push_str(line.slice(start..end));
+            if line_idx != to.line_idx {
+                text.push('\n');
+            }
+        }
+        text
+    }
+    /// Removes the text between two locations, joining the lines around it.
+    pub fn delete_range(&mut self, from: Location, to: Location) {
+        let Some(first) = self.lines.get(from.line_idx) else {
+            return;
+        };
+        if from >= to {
+            return;
+        }
+        let last_idx = to.line_idx.min(self.height().saturating_sub(1));
+        let tail = self
+            .lines
+            .get(to.line_idx)
+            .map_or("", |line| line.slice(to.grapheme_idx..line.grapheme_count()));
+        let joined = format!("{}{tail}", first.slice(0..from.grapheme_idx));
+        let joined = self.new_line(&joined);
+
+        self.before_change();
+        self.lines.splice(from.line_idx..=last_idx, [joined]);
+        self.dirty = true;
+    }
+    /// Inserts text that may span several lines, and returns the location
+    /// right after it.
+    pub fn insert_text(&mut self, at: Location, text: &str) -> Location {
+        self.before_change();
+        if at.line_idx >= self.height() {
+            self.lines.push(self.new_line(""));
+        }
+        let line_idx = at.line_idx.min(self.height().saturating_sub(1));
+        let Some(line) = self.lines.get(line_idx) else {
+            return at;
+        };
+        let head = line.slice(0..at.grapheme_idx).to_string();
+        let tail = line.slice(at.grapheme_idx..line.grapheme_count()).to_string();
+
+        let mut parts: Vec<&str> = text.split('\n').collect();
+        let last = parts.pop().unwrap_or_default();
+        let last = if parts.is_empty() { format!("{head}{last}") } else { last.to_string() };
+        let end = Location {
+            line_idx: line_idx.saturating_add(parts.len()),
+            grapheme_idx: self.new_line(&last).grapheme_count(),
+        };
+
+        let mut new_lines = Vec::with_capacity(parts.len().saturating_add(1));
+        for (idx, part) in parts.iter().enumerate() {
+            let text = if idx == 0 { format!("{head}{part}") } else { (*part).to_string() };
+            new_lines.push(self.new_line(&text));
+        }
+        new_lines.push(self.new_line(&format!("{last}{tail}")));
+
+        self.lines.splice(line_idx..=line_idx, new_lines);
+        self.dirty = true;
+        end
+    }
+    /// The text within the columns `cols` of each line, one row per line.
+    pub fn block_text(&self, lines: RangeInclusive<usize>, cols: &Range<usize>) -> String {
+        lines
+            .filter_map(|line_idx| self.lines.get(line_idx))
+            .map(|line| line.slice(line.grapheme_range_in_cols(cols.clone())))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+    pub fn delete_block(&mut self, lines: RangeInclusive<usize>, cols: &Range<usize>) {
+        self.before_change();
+        for line_idx in lines {
+            let Some(line) = self.lines.get_mut(line_idx) else {
+                break;
+            };
+            let range = line.grapheme_range_in_cols(cols.clone());
+            for _ in range.clone() {
+                line.delete(range.start);
+            }
+            self.dirty |= !range.is_empty();
+        }
+    }
+    /// Appends spaces to the line until it reaches column `col`, so that text
+    /// can go there. Adds the line if it is just past the end.
+    pub fn pad_line(&mut self, line_idx: usize, col: usize) {
+        if self.lines.get(line_idx).is_some_and(|line| line.width() >= col) {
+            return;
+        }
+        self.before_change();
+        if line_idx == self.height() {
+            self.lines.push(self.new_line(""));
+        }
+        let Some(line) = self.lines.get_mut(line_idx) else {
+            return;
+        };
+        while line.width() < col {
+            line.append_char(' ');
+            self.dirty = true;
+        }
+    }
+    /// The text of whole lines, joined by `\n`.
+    pub fn lines_text(&self, range: RangeInclusive<usize>) -> String {
+        self.lines
+            .get(range)
+            .unwrap_or_default()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+    pub fn delete_lines(&mut self, range: RangeInclusive<usize>) {
+        let last = (*range.end()).min(self.height().saturating_sub(1));
+        if *range.start() > last || self.is_empty() {
+            return;
+        }
+        self.before_change();
+        self.lines.drain(*range.start()..=last);
+        self.dirty = true;
+    }
+    /// Inserts every line of `text` before the line at `line_idx`.
+    pub fn insert_lines(&mut self, line_idx: usize, text: &str) {
+        let line_idx = line_idx.min(self.height());
+        let new_lines: Vec<Line> = text.split('\n').map(|line| self.new_line(line)).collect();
+        self.before_change();
+        self.lines.splice(line_idx..line_idx, new_lines);
+        self.dirty = true;
+    }
+    /// Replaces the first match of `regex` in each of the given lines, or
+    /// every match if `global`. Returns the number of replacements made.
+    pub fn substitute(
+        &mut self,
+        lines: RangeInclusive<usize>,
+        regex: &Regex,
+        replacement: &str,
+        global: bool,
+    ) -> usize {
+        let mut replacements: usize = 0;
+        // Going backwards keeps the indices valid when a replacement
+        // contains line breaks.
+        for line_idx in lines.rev() {
+            let Some(line) = self.lines.get(line_idx) else {
+                continue;
+            };
+            let matches = if global { regex.find_iter(line).count() } else { usize::from(regex.is_match(line)) };
+            if matches == 0 {
+                continue;
+            }
+            let replaced = if global {
+                regex.replace_all(line, replacement)
+            } else {
+                regex.replace(line, replacement)
+            };
+            let new_lines: Vec<Line> = replaced.split('\n').map(|text| self.new_line(text)).collect();
+            self.before_change();
+            self.lines.splice(line_idx..=line_idx, new_lines);
+            replacements = replacements.saturating_add(matches);
+        }
+        if replacements > 0 {
+            self.dirty = true;
+        }
+        replacements
+    }
+    // region: word motions
+    fn char_class(&self, at: Location) -> CharClass {
+        self.lines
+            .get(at.line_idx)
+            .and_then(|line| line.grapheme(at.grapheme_idx))
+            .map_or(CharClass::Blank, |grapheme| {
+                if grapheme.chars().all(char::is_whitespace) {
+                    CharClass::Blank
+                } else if grapheme.chars().any(|ch| ch.is_alphanumeric() || ch == '_') {
+                    CharClass::Word
+                } else {
+                    CharClass::Punctuation
+                }
+            })
+    }
+    pub fn is_blank_at(&self, at: Location) -> bool {
+        self.char_class(at) == CharClass::Blank
+    }
+    /// The next location, counting the end of each line as one.
+    fn next_location(&self, at: Location) -> Option<Location> {
+        let line = self.lines.get(at.line_idx)?;
+        if at.grapheme_idx < line.grapheme_count() {
+            Some(Location {
+                grapheme_idx: at.grapheme_idx.saturating_add(1),
+                ..at
+            })
+        } else if at.line_idx.saturating_add(1) < self.height() {
+            Some(Location {
+                line_idx: at.line_idx.saturating_add(1),
+                grapheme_idx: 0,
+            })
+        } else {
+            None
+        }
+    }
+    fn prev_location(&self, at: Location) -> Option<Location> {
+        if at.grapheme_idx > 0 {
+            Some(Location {
+                grapheme_idx: at.grapheme_idx.saturating_sub(1),
+                ..at
+            })
+        } else if at.line_idx > 0 {
+            let line_idx = at.line_idx.saturating_sub(1);
+            Some(Location {
+                line_idx,
+                grapheme_idx: self.lines.get(line_idx).map_or(0, Line::grapheme_count),
+            })
+        } else {
+            None
+        }
+    }
+    /// Empty lines count as words of their own.
+    fn is_empty_line_at(&self, at: Location) -> bool {
+        at.grapheme_idx == 0
+            && self
+                .lines
+                .get(at.line_idx)
+                .is_some_and(|line| line.grapheme_count() == 0)
+    }
+    pub fn next_word_start(&self, from: Location) -> Location {
+        let mut at = from;
+        let class = self.char_class(at);
+        while class != CharClass::Blank && self.char_class(at) == class {
+            let Some(next) = self.next_location(at) else {
+                return at;
+            };
+            at = next;
+        }
+        while self.char_class(at) == CharClass::Blank {
+            if at != from && self.is_empty_line_at(at) {
+                return at;
+            }
+            let Some(next) = self.next_location(at) else {
+                return at;
+            };
+            at = next;
+        }
+        at
+    }
+    pub fn word_end(&self, from: Location) -> Location {
+        let Some(mut at) = self.next_location(from) else {
+            return from;
+        };
+        while self.char_class(at) == CharClass::Blank {
+            let Some(next) = self.next_location(at) else {
+                return at;
+            };
+            at = next;
+        }
+        let class = self.char_class(at);
+        while let Some(next) = self.next_location(at)
+            && self.char_class(next) == class
+        {
+            at = next;
+        }
+        at
+    }
+    pub fn prev_word_start(&self, from: Location) -> Location {
+        let Some(mut at) = self.prev_location(from) else {
+            return from;
+        };
+        while self.char_class(at) == CharClass::Blank {
+            if self.is_empty_line_at(at) {
+                return at;
+            }
+            let Some(prev) = self.prev_location(at) else {
+                return at;
+            };
+            at = prev;
+        }
+        let class = self.char_class(at);
+        while let Some(prev) = self.prev_location(at)
+            && self.char_class(prev) == class
+        {
+            at = prev;
+        }
+        at
+    }
+    /// The graphemes around `at` in its line that belong to the same word,
+    /// run of punctuation or run of blanks.
+    pub fn word_around(&self, at: Location) -> Range<usize> {
+        let class = self.char_class(at);
+        let line_len = self.lines.get(at.line_idx).map_or(0, Line::grapheme_count);
+        let is_same = |grapheme_idx: &usize| self.char_class(Location { grapheme_idx: *grapheme_idx, ..at }) == class;
+        let start = (0..at.grapheme_idx)
+            .rev()
+            .take_while(is_same)
+            .last()
+            .unwrap_or(at.grapheme_idx);
+        let end = (at.grapheme_idx..line_len)
+            .take_while(is_same)
+            .last()
+            .map_or(at.grapheme_idx, |last| last.saturating_add(1));
+        start..end
+    }
+    // end region
+    // region: lines
+    fn set_line(&mut self, line_idx: usize, text: &str) {
+        let new_line = self.new_line(text);
+        self.before_change();
+        if let Some(line) = self.lines.get_mut(line_idx) {
+            *line = new_line;
+            self.dirty = true;
+        }
+    }
+    /// `lines` cut down to the lines there are, if any.
+    fn existing_lines(&self, lines: &RangeInclusive<usize>) -> Option<RangeInclusive<usize>> {
+        let last = (*lines.end()).min(self.height().checked_sub(1)?);
+        (*lines.start() <= last).then_some(*lines.start()..=last)
+    }
+    /// Swaps `lines` with the line above or below them. Returns false when
+    /// there is no such line.
+    pub fn move_lines(&mut self, lines: &RangeInclusive<usize>, down: bool) -> bool {
+        let Some(lines) = self.existing_lines(lines) else {
+            return false;
+        };
+        let (first, last) = (*lines.start(), *lines.end());
+        let swapped = if down {
+            first..=last.saturating_add(1)
+        } else {
+            let Some(above) = first.checked_sub(1) else {
+                return false;
+            };
+            above..=last
+        };
+        if *swapped.end() >= self.height() {
+            return false;
+        }
+        self.before_change();
+        if let Some(lines) = self.lines.get_mut(swapped) {
+            if down {
+                lines.rotate_right(1);
+            } else {
+                lines.rotate_left(1);
+            }
+        }
+        self.dirty = true;
+        true
+    }
+    /// Inserts a copy of `lines` after them.
+    pub fn duplicate_lines(&mut self, lines: &RangeInclusive<usize>) {
+        let Some(lines) = self.existing_lines(lines) else {
+            return;
+        };
+        let copies = self.lines.get(lines.clone()).unwrap_or_default().to_vec();
+        let after = lines.end().saturating_add(1);
+        self.before_change();
+        self.lines.splice(after..after, copies);
+        self.dirty = true;
+    }
+    /// Joins `lines`, or the first of them with the next if there is just
+    /// one, into a single line. The whitespace around each line break turns
+    /// into a single space, or none next to an empty line. Returns where the
+    /// last join happened.
+    pub fn join_lines(&mut self, lines: &RangeInclusive<usize>) -> Option<Location> {
+        let first = *lines.start();
+        let last = (*lines.end()).max(first.saturating_add(1));
+        let lines = self.existing_lines(&(first..=last)).filter(|lines| lines.start() < lines.end())?;
+        let mut text = self.lines.get(first).map(ToString::to_string).unwrap_or_default();
+        let mut join_col = 0;
+        for line in self.lines.get(first.saturating_add(1)..=*lines.end()).unwrap_or_default() {
+            let next = line.trim_start();
+            text.truncate(text.trim_end().len());
+            join_col = text.len();
+            if !text.is_empty() && !next.is_empty() {
+                text.push(' ');
+            }
+            text.push_str(next);
+        }
+        self.delete_lines(first.saturating_add(1)..=*lines.end());
+        self.set_line(first, &text);
+        let grapheme_idx = self.lines.get(first).map_or(0, |line| line.byte_idx_to_grapheme_idx(join_col));
+        Some(Location {
+            line_idx: first,
+            grapheme_idx,
+        })
+    }
+    /// Sorts `lines` as `options` say.
+    pub fn sort_lines(&mut self, lines: &RangeInclusive<usize>, options: SortOptions) {
+        let Some(lines) = self.existing_lines(lines) else {
+            return;
+        };
+        let mut texts: Vec<String> = self
+            .lines
+            .get(lines.clone())
+            .unwrap_or_default()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        let key = |text: &String| match options.key {
+            SortKey::Lexical => (None, text.clone()),
+            SortKey::IgnoreCase => (None, text.to_lowercase()),
+            SortKey::Numeric => (Some(first_number(text)), String::new()),
+        };
+        texts.sort_by_cached_key(key);
+        if options.unique {
+            texts.dedup_by(|text, previous| key(text) == key(previous));
+        }
+        if options.reverse {
+            texts.reverse();
+        }
+        self.delete_lines(lines.clone());
+        self.insert_lines(*lines.start(), &texts.join("\n"));
+    }
+    /// The markers that start comments, or quotes, in this file.
+    fn comment_markers(&self) -> Vec<&'static str> {
+        match self.file_type().syntax {
+            Some(syntax) => syntax.line_comment.into_iter().collect(),
+            None => PROSE_MARKERS.to_vec(),
+        }
+    }
+    /// The lines of the paragraph `line_idx` is in, up to the blank lines
+    /// around it.
+    pub fn paragraph_around(&self, line_idx: usize) -> RangeInclusive<usize> {
+        let is_blank = |line_idx: usize| self.lines.get(line_idx).is_none_or(|line| line.trim().is_empty());
+        if is_blank(line_idx) {
+            return line_idx..=line_idx;
+        }
+        let first = (0..line_idx)
+            .rev()
+            .find(|idx| is_blank(*idx))
+            .map_or(0, |idx| idx.saturating_add(1));
+        let last = (line_idx..self.height())
+            .find(|idx| is_blank(*idx))
+            .map_or(self.height(), |idx| idx)
+            .saturating_sub(1);
+        first..=last
+    }
+    /// Rewraps each paragraph in `lines` to `width` columns. A paragraph is a
+    /// run of lines with the same prefix of indentation and comment or quote
+    /// markers, which every line it turns into starts with. Returns the lines
+    /// the text takes up now.
+    pub fn reflow(&mut self, lines: &RangeInclusive<usize>, width: usize) -> Option<RangeInclusive<usize>> {
+        let lines = self.existing_lines(lines)?;
+        let markers = self.comment_markers();
+        let tab_width = self.settings.tab_width;
+        let texts: Vec<String> = self
+            .lines
+            .get(lines.clone())
+            .unwrap_or_default()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        let mut reflowed = Vec::new();
+        let mut prefix = "";
+        let mut words: Vec<&str> = Vec::new();
+        for text in &texts {
+            let (line_prefix, body) = text.split_at(reflow::prefix_len(text, &markers));
+            if body.is_empty() || line_prefix.trim_end() != prefix.trim_end() {
+                reflowed.extend(reflow::fill(&words, prefix, width, tab_width));
+                words.clear();
+                prefix = line_prefix;
+            }
+            if body.is_empty() {
+                reflowed.push(text.clone());
+            } else {
+                words.extend(body.split_whitespace());
+            }
+        }
+        reflowed.extend(reflow::fill(&words, prefix, width, tab_width));
+        let first = *lines.start();
+        self.delete_lines(lines);
+        self.insert_lines(first, &reflowed.join("\n"));
+        Some(first..=first.saturating_add(reflowed.len().saturating_sub(1)))
+    }
+    /// Where to break the line `at` is on, once typing at `at` went past the
+    /// text width: the whitespace before the last word that still starts
+    /// within it, as the line and the graphemes to replace with a line break.
+    /// Also returns the prefix for the new line.
+    pub fn wrap_point(&self, at: Location) -> Option<(usize, usize, usize, String)> {
+        let line = self.lines.get(at.line_idx)?;
+        let text_width = self.settings.text_width;
+        if line.width_until(at.grapheme_idx) <= text_width {
+            return None;
+        }
+        let text = line.to_string();
+        let prefix_len = reflow::prefix_len(&text, &self.comment_markers());
+        let prefix_end = line.byte_idx_to_grapheme_idx(prefix_len);
+        let is_blank = |idx: usize| line.grapheme(idx).is_some_and(|grapheme| grapheme.trim().is_empty());
+        let blank = (prefix_end..at.grapheme_idx)
+            .rev()
+            .find(|idx| is_blank(*idx) && line.width_until(*idx) <= text_width)?;
+        let break_start = (prefix_end..blank)
+            .rev()
+            .find(|idx| !is_blank(*idx))
+            .map(|idx| idx.saturating_add(1))?;
+        let break_end = (blank..at.grapheme_idx).find(|idx| !is_blank(*idx)).unwrap_or(at.grapheme_idx);
+        let prefix = text.get(..prefix_len).unwrap_or_default().to_string();
+        Some((at.line_idx, break_start, break_end, prefix))
+    }
+    /// Comments out the non-blank lines in `lines` with the file type's line
+    /// comment marker, lined up at their smallest indentation, or else wraps
+    /// them in one block comment. When they are comments already, uncomments
+    /// them instead. Returns where `caret` ends up.
+    pub fn toggle_comment(&mut self, lines: RangeInclusive<usize>, caret: Location) -> Result<Location, String> {
+        let file_type = self.file_type();
+        let syntax = file_type.syntax;
+        let (start, end) = match (
+            syntax.and_then(|syntax| syntax.line_comment),
+            syntax.and_then(|syntax| syntax.block_comment),
+        ) {
+            (Some(start), _) => (start, None),
+            (None, Some((start, end))) => (start, Some(end)),
+            (None, None) => return Err(format!("No comments in {} files", file_type.name)),
+        };
+        let first = *lines.start();
+        let last = (*lines.end()).min(self.height().saturating_sub(1));
+        let texts: Vec<(usize, String)> = self
+            .lines
+            .get(first..=last)
+            .unwrap_or_default()
+            .iter()
+            .zip(first..)
+            .map(|(line, line_idx)| (line_idx, line.to_string()))
+            .filter(|(_, text)| !text.trim().is_empty())
+            .collect();
+        let (Some((first, first_text)), Some((last, last_text))) = (texts.first(), texts.last()) else {
+            return Ok(caret);
+        };
+        let is_comment_start = |text: &str| text.trim_start().starts_with(start);
+        let uncomment = match end {
+            None => texts.iter().all(|(_, text)| is_comment_start(text)),
+            Some(end) => is_comment_start(first_text) && last_text.trim_end().ends_with(end),
+        };
+        let (first, last) = (*first, *last);
+        let indent = texts.iter().map(|(_, text)| indent_len(text)).min().unwrap_or(0);
+        let mut caret = caret;
+        for (line_idx, text) in texts {
+            // Only the opening marker moves the text after it.
+            let new_text = if end.is_some() && line_idx != first {
+                text.clone()
+            } else if uncomment {
+                uncomment_line(&text, start)
+            } else {
+                comment_line(&text, indent, start)
+            };
+            if line_idx == caret.line_idx {
+                caret.grapheme_idx = if new_text.len() >= text.len() {
+                    caret.grapheme_idx.saturating_add(new_text.len().saturating_sub(text.len()))
+                } else {
+                    caret.grapheme_idx.saturating_sub(text.len().saturating_sub(new_text.len()))
+                };
+            }
+            let new_text = match end {
+                Some(end) if line_idx == last && uncomment => uncomment_end(&new_text, end),
+                Some(end) if line_idx == last => format!("{new_text} {end}"),
+                _ => new_text,
+            };
+            self.set_line(line_idx, &new_text);
+        }
+        Ok(caret)
+    }
+    // end region
+    // region: brackets
+    /// Every pair of matching brackets, in the order they close. When the
+    /// file type has a syntax, brackets in comments and strings don't count.
+    /// A closing bracket that doesn't match the last open one is skipped.
+    pub fn bracket_pairs(&self) -> Vec<(Location, Location)> {
+        let syntax = self.file_type().syntax;
+        let mut pairs = Vec::new();
+        let mut open: Vec<(Location, char)> = Vec::new();
+        let mut scope = Scope::Code;
+        for (line_idx, line) in self.lines.iter().enumerate() {
+            let line_len = line.grapheme_count();
+            let mut grapheme_idx = 0;
+            while grapheme_idx < line_len {
+                let rest = line.slice(grapheme_idx..line_len);
+                let character = rest.chars().next().unwrap_or_default();
+                let mut step = 1;
+                match (scope, syntax) {
+                    (Scope::BlockComment, Some(syntax)) => {
+                        if let Some((_, end)) = syntax.block_comment
+                            && rest.starts_with(end)
+                        {
+                            scope = Scope::Code;
+                            step = end.len();
+                        }
+                    }
+                    (Scope::String(quote), _) => {
+                        if character == '\\' {
+                            step = 2;
+                        } else if character == quote {
+                            scope = Scope::Code;
+                        }
+                    }
+                    (_, Some(syntax)) if syntax.block_comment.is_some_and(|(start, _)| rest.starts_with(start)) => {
+                        scope = Scope::BlockComment;
+                        step = syntax.block_comment.map_or(1, |(start, _)| start.len());
+                    }
+                    (_, Some(syntax)) if syntax.line_comment.is_some_and(|start| rest.starts_with(start)) => break,
+                    (_, Some(syntax)) if syntax.char_literals && character == '\'' => {
+                        step = char_literal_len(rest);
+                    }
+                    (_, Some(syntax)) if syntax.quotes.contains(&character) => scope = Scope::String(character),
+                    _ => {
+                        let at = Location { line_idx, grapheme_idx };
+                        if BRACKETS.iter().any(|(opening, _)| *opening == character) {
+                            open.push((at, character));
+                        } else if let Some((opening, _)) = BRACKETS.iter().find(|(_, closing)| *closing == character)
+                            && let Some(&(start, last_open)) = open.last()
+                            && last_open == *opening
+                        {
+                            open.pop();
+                            pairs.push((start, at));
+                        }
+                    }
+                }
+                grapheme_idx = grapheme_idx.saturating_add(step);
+            }
+            if let Scope::String(_) = scope
+                && !syntax.is_some_and(|syntax| syntax.multiline_strings)
+            {
+                scope = Scope::Code;
+            }
+        }
+        pairs
+    }
+    /// The bracket pair at `at`: the one with a bracket there, or else the
+    /// innermost one around it.
+    pub fn bracket_pair_at(&self, at: Location) -> Option<(Location, Location)> {
+        let pairs = self.bracket_pairs();
+        pairs
+            .iter()
+            .find(|(start, end)| *start == at || *end == at)
+            .or_else(|| {
+                pairs
+                    .iter()
+                    .filter(|(start, end)| *start < at && at < *end)
+                    .max_by_key(|(start, _)| *start)
+            })
+            .copied()
+    }
+    // end region
     pub fn is_empty(&self) -> bool {
         self.lines.is_empty()
     }
@@ -157,3 +976,106 @@ impl Buffer {
         self.lines.len()
     }
 }
+
+/// How many graphemes to skip at the `'` that `text` starts with: the whole
+/// character literal, such as `'{'` or `'\''`, or just the `'` of a lifetime.
+fn char_literal_len(text: &str) -> usize {
+    let mut chars = text.chars().skip(1);
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('\\'), Some(_), Some('\'')) => 4,
+        (Some(_), Some('\''), _) => 3,
+        _ => 1,
+    }
+}
+
+/// The length in bytes of the whitespace `text` starts with.
+fn indent_len(text: &str) -> usize {
+    text.len().saturating_sub(text.trim_start().len())
+}
+
+/// Puts the comment marker `start` into `text`, `indent` bytes in.
+fn comment_line(text: &str, indent: usize, start: &str) -> String {
+    let (indent, body) = text.split_at_checked(indent).unwrap_or(("", text));
+    format!("{indent}{start} {body}")
+}
+
+/// Takes the comment marker `start` out of `text`, with the space after it.
+fn uncomment_line(text: &str, start: &str) -> String {
+    let (indent, body) = text.split_at(indent_len(text));
+    let body = body.strip_prefix(start).unwrap_or(body);
+    let body = body.strip_prefix(' ').unwrap_or(body);
+    format!("{indent}{body}")
+}
+
+/// Takes the closing marker `end` of a block comment off `text`, with the
+/// space before it.
+fn uncomment_end(text: &str, end: &str) -> String {
+    let body = text.trim_end();
+    let body = body.strip_suffix(end).unwrap_or(body);
+    body.strip_suffix(' ').unwrap_or(body).to_string()
+}
+
+/// The first number in `text`, which may be negative, for sorting lines by
+/// number. Lines without one sort before all others.
+fn first_number(text: &str) -> Option<i128> {
+    let start = text.find(|ch: char| ch.is_ascii_digit())?;
+    let digits_len = text
+        .get(start..)
+        .and_then(|rest| rest.find(|ch: char| !ch.is_ascii_digit()))
+        .unwrap_or(text.len().saturating_sub(start));
+    let number: i128 = text.get(start..start.saturating_add(digits_len))?.parse().ok()?;
+    let negative = start
+        .checked_sub(1)
+        .and_then(|minus| text.get(minus..start))
+        .is_some_and(|before| before == "-");
+    Some(if negative { number.saturating_neg() } else { number })
+}
+
+/// The text of a file and its charset: UTF-16 or UTF-8 going by its byte
+/// order mark, otherwise UTF-8, or Latin-1 when it isn't valid UTF-8.
+fn decode(bytes: &[u8]) -> (String, Charset) {
+    let utf16 = |bytes: &[u8], from_bytes: fn([u8; 2]) -> u16| {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .filter_map(|pair| pair.try_into().ok().map(from_bytes))
+            .collect();
+        String::from_utf16_lossy(&units)
+    };
+    if let Some(rest) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        (String::from_utf8_lossy(rest).into_owned(), Charset::Utf8Bom)
+    } else if let Some(rest) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        (utf16(rest, u16::from_be_bytes), Charset::Utf16Be)
+    } else if let Some(rest) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+        (utf16(rest, u16::from_le_bytes), Charset::Utf16Le)
+    } else {
+        match String::from_utf8(bytes.to_vec()) {
+            Ok(text) => (text, Charset::Utf8),
+            Err(_) => (bytes.iter().copied().map(char::from).collect(), Charset::Latin1),
+        }
+    }
+}
+
+/// `text` in `charset`, starting with a byte order mark for UTF-16 and
+/// `utf-8-bom`. Fails for characters Latin-1 has no byte for.
+fn encode(text: &str, charset: Charset) -> Result<Vec<u8>, Error> {
+    Ok(match charset {
+        Charset::Utf8 => text.as_bytes().to_vec(),
+        Charset::Utf8Bom => [0xEF, 0xBB, 0xBF].iter().chain(text.as_bytes()).copied().collect(),
+        Charset::Latin1 => text
+            .chars()
+            .map(|ch| {
+                u8::try_from(u32::from(ch)).map_err(|_| {
+                    Error::new(ErrorKind::InvalidData, format!("'{ch}' can't be written in latin1"))
+                })
+            })
+            .collect::<Result<_, _>>()?,
+        Charset::Utf16Be => [0xFE, 0xFF]
+            .into_iter()
+            .chain(text.encode_utf16().flat_map(u16::to_be_bytes))
+            .collect(),
+        Charset::Utf16Le => [0xFF, 0xFE]
+            .into_iter()
+            .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+            .collect(),
+    })
+}
@@ -3,7 +3,7 @@ use std::{
     path::{Path, PathBuf},
 };
 
-#[derive(Default, Debug)]
+#[derive(Default, Clone, Debug)]
 pub struct FileInfo {
     path: Option<PathBuf>,
 }
@@ -0,0 +1,101 @@
+use std::mem::replace;
+
+use super::Line;
+use super::Location;
+
+/// Undo steps kept at most, so that a long session doesn't hold on to every
+/// version of the text.
+const MAX_STEPS: usize = 100;
+
+/// The text as it was, and where the caret was then.
+struct Snapshot {
+    lines: Vec<Line>,
+    caret: Location,
+}
+
+/// The step being made, from when the editor starts handling an event until
+/// it is done with it.
+struct OpenStep {
+    caret: Location,
+    /// Whether the step joins the last one if that was typing as well.
+    typing: bool,
+    /// The lines before the first change in this step.
+    before: Option<Vec<Line>>,
+    changed: bool,
+}
+
+/// The undo and redo steps of a buffer. Everything one event changes, at
+/// every caret and on every line, is one step, and a run of typed
+/// characters is one step too.
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+    open: Option<OpenStep>,
+    /// The last step was typing, so more typing joins it.
+    typing: bool,
+}
+
+impl History {
+    pub fn begin(&mut self, caret: Location, typing: bool) {
+        self.open = Some(OpenStep {
+            caret,
+            typing,
+            before: None,
+            changed: false,
+        });
+    }
+    /// Keeps `lines` for undoing, if they are the first to change in the
+    /// open step. Changes outside of a step can't be undone.
+    pub fn before_change(&mut self, lines: &[Line]) {
+        let Some(open) = &mut self.open else {
+            return;
+        };
+        open.changed = true;
+        if open.before.is_none() && !(open.typing && self.typing) {
+            open.before = Some(lines.to_vec());
+        }
+    }
+    pub fn end(&mut self) {
+        let Some(open) = self.open.take() else {
+            return;
+        };
+        if !open.changed {
+            self.typing &= open.typing;
+            return;
+        }
+        if let Some(lines) = open.before {
+            if self.undo.len() >= MAX_STEPS {
+                self.undo.remove(0);
+            }
+            self.undo.push(Snapshot {
+                lines,
+                caret: open.caret,
+            });
+        }
+        self.redo.clear();
+        self.typing = open.typing;
+    }
+    /// Puts back the lines from before the last step, keeping `lines` for
+    /// redoing. Returns where the caret was before the step.
+    pub fn undo(&mut self, lines: &mut Vec<Line>, caret: Location) -> Option<Location> {
+        let snapshot = self.undo.pop()?;
+        self.typing = false;
+        self.redo.push(Snapshot {
+            lines: replace(lines, snapshot.lines),
+            caret,
+        });
+        Some(snapshot.caret)
+    }
+    /// Puts back the lines the last undo replaced. Returns where the caret
+    /// was when undoing.
+    pub fn redo(&mut self, lines: &mut Vec<Line>, caret: Location) -> Option<Location> {
+        let snapshot = self.redo.pop()?;
+        self.typing = false;
+        self.undo.push(Snapshot {
+            lines: replace(lines, snapshot.lines),
+            caret,
+        });
+        Some(snapshot.caret)
+    }
+}
@@ -1,5 +1,6 @@
-#[derive(Copy, Clone, Default)]
+/// A place in the buffer. Locations order by line first, then by grapheme.
+#[derive(Copy, Clone, Default, Eq, PartialEq, Ord, PartialOrd, Debug)]
 pub struct Location {
-    pub grapheme_idx: usize,
     pub line_idx: usize,
+    pub grapheme_idx: usize,
 }
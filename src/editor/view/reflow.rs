@@ -0,0 +1,60 @@
+use unicode_width::UnicodeWidthChar;
+
+/// The markers that start comments and quotes in files without a syntax,
+/// such as commit messages and Markdown.
+pub const PROSE_MARKERS: [&str; 3] = ["//", "#", ">"];
+
+/// The length in bytes of the prefix of `text` that every line of its
+/// paragraph repeats: the indentation, and any of `markers` with the
+/// whitespace after them. Doc comment markers such as `///` and `//!` count
+/// as a whole.
+pub fn prefix_len(text: &str, markers: &[&str]) -> usize {
+    let mut rest = text.trim_start();
+    while let Some(marker) = markers.iter().find(|marker| rest.starts_with(**marker)) {
+        rest = rest
+            .get(marker.len()..)
+            .unwrap_or_default()
+            .trim_start_matches(['/', '!'])
+            .trim_start();
+    }
+    text.len().saturating_sub(rest.len())
+}
+
+/// How many columns `text` takes up, with tabs going to the next tab stop.
+pub fn display_width(text: &str, tab_width: usize) -> usize {
+    text.chars().fold(0, |width, ch| {
+        if ch == '\t' {
+            width.saturating_add(1).next_multiple_of(tab_width.max(1))
+        } else {
+            width.saturating_add(ch.width().unwrap_or(0))
+        }
+    })
+}
+
+/// Fills `words` into lines no wider than `width`, each starting with
+/// `prefix`. A word too wide for any line gets one of its own.
+pub fn fill(words: &[&str], prefix: &str, width: usize, tab_width: usize) -> Vec<String> {
+    let prefix_width = display_width(prefix, tab_width);
+    let mut lines = Vec::new();
+    let mut line = String::from(prefix);
+    let mut line_width = prefix_width;
+    for word in words {
+        let word_width = display_width(word, tab_width);
+        if line_width > prefix_width {
+            if line_width.saturating_add(1).saturating_add(word_width) > width {
+                lines.push(line);
+                line = String::from(prefix);
+                line_width = prefix_width;
+            } else {
+                line.push(' ');
+                line_width = line_width.saturating_add(1);
+            }
+        }
+        line.push_str(word);
+        line_width = line_width.saturating_add(word_width);
+    }
+    if line_width > prefix_width {
+        lines.push(line);
+    }
+    lines
+}
@@ -0,0 +1,397 @@
+use super::{
+    command::{Command, Edit, History, Lines, System},
+    macros::Macros,
+};
+
+#[derive(Default, Clone, Copy, Eq, PartialEq)]
+pub enum Mode {
+    #[default]
+    Normal,
+    Insert,
+    Visual,
+    /// Selects a block of columns, entered with `Ctrl-V`.
+    VisualBlock,
+}
+
+impl Mode {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Normal => "NORMAL",
+            Self::Insert => "INSERT",
+            Self::Visual => "VISUAL",
+            Self::VisualBlock => "V-BLOCK",
+        }
+    }
+    pub const fn is_visual(self) -> bool {
+        matches!(self, Self::Visual | Self::VisualBlock)
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    /// `w`
+    WordStart,
+    /// `e`
+    WordEnd,
+    /// `b`
+    WordBackward,
+    /// `0`
+    LineStart,
+    /// `$`
+    LineEnd,
+    /// `gg`, or the line given by the count.
+    FirstLine,
+    /// `G`, or the line given by the count.
+    LastLine,
+    /// `%`
+    MatchingBracket,
+    /// The motion of doubled operators such as `dd`.
+    CurrentLine,
+}
+
+impl Motion {
+    /// Operators with these motions act on whole lines.
+    pub const fn is_linewise(self) -> bool {
+        matches!(
+            self,
+            Self::Up | Self::Down | Self::FirstLine | Self::LastLine | Self::CurrentLine
+        )
+    }
+    /// Operators with these motions include the grapheme the motion ends on.
+    pub const fn is_inclusive(self) -> bool {
+        matches!(self, Self::WordEnd | Self::MatchingBracket)
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+    /// `gq`, which rewraps the lines to the text width.
+    Format,
+}
+
+/// Where the caret goes when entering insert mode.
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum InsertAt {
+    /// `i`
+    Caret,
+    /// `a`
+    AfterCaret,
+    /// `I`
+    LineStart,
+    /// `A`
+    LineEnd,
+    /// `o`
+    LineBelow,
+    /// `O`
+    LineAbove,
+    /// `I` in visual block mode, on every line of the block.
+    BlockStart,
+    /// `A` in visual block mode, on every line of the block.
+    BlockEnd,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum Action {
+    Move(Motion),
+    Operate(Operator, Motion),
+    OperateSelection(Operator),
+    Insert(InsertAt),
+    Put { before: bool },
+    ToggleVisual,
+    ToggleVisualBlock,
+    Repeat,
+    /// `q` and a register.
+    RecordMacro(char),
+    /// `q` while recording.
+    StopRecording,
+    /// `@` and a register, or `@@` for the one played last.
+    PlayMacro(Option<char>),
+    /// A key that runs one of the regular editor commands.
+    Command(Command),
+}
+
+impl Action {
+    /// Actions that change the text, and can be repeated with `.`.
+    const fn is_change(self) -> bool {
+        matches!(
+            self,
+            Self::Operate(Operator::Delete | Operator::Change | Operator::Format, _)
+                | Self::OperateSelection(Operator::Format)
+                | Self::Insert(_)
+                | Self::Put { .. }
+        )
+    }
+}
+
+/// Text that was yanked or deleted, ready to be put back.
+#[derive(Default, Clone)]
+pub struct Register {
+    pub text: String,
+    pub linewise: bool,
+    /// The rows of a block selection, one per line of `text`.
+    pub blockwise: bool,
+}
+
+/// A change as it is replayed by `.`: the action, its count, and the text
+/// typed in insert mode afterwards, if the action entered it.
+#[derive(Clone)]
+pub struct Change {
+    pub action: Action,
+    pub count: Option<usize>,
+    pub inserted: Vec<Edit>,
+}
+
+/// The key state machine of the Vim mode. It turns the keys typed outside of
+/// insert mode into actions, and keeps what `.` needs to repeat the last one.
+#[derive(Default)]
+pub struct Vim {
+    mode: Mode,
+    count: Option<usize>,
+    operator: Option<(Operator, Option<usize>)>,
+    awaiting_g: bool,
+    /// `q` or `@`, which wait for the register to use.
+    awaiting_register: Option<char>,
+    recording: bool,
+    last_change: Option<Change>,
+    pending_change: Option<Change>,
+    pub register: Register,
+}
+
+impl Vim {
+    pub const fn mode(&self) -> Mode {
+        self.mode
+    }
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.reset_pending();
+    }
+    /// Drops a half typed count or operator.
+    pub fn reset_pending(&mut self) {
+        self.count = None;
+        self.operator = None;
+        self.awaiting_g = false;
+        self.awaiting_register = None;
+    }
+    /// Tells whether a macro is being recorded, which makes `q` stop it.
+    pub fn set_recording(&mut self, recording: bool) {
+        self.recording = recording;
+    }
+    pub fn last_change(&self) -> Option<&Change> {
+        self.last_change.as_ref()
+    }
+    /// Handles the character typed after `g`.
+    fn handle_g(&mut self, character: char) -> Option<(Action, Option<usize>)> {
+        match character {
+            'g' => Some(self.finish(Action::Move(Motion::FirstLine))),
+            'q' if self.mode.is_visual() => Some(self.finish(Action::OperateSelection(Operator::Format))),
+            'q' if self.operator.is_none() => {
+                self.operator = Some((Operator::Format, self.count.take()));
+                None
+            }
+            _ => {
+                self.reset_pending();
+                None
+            }
+        }
+    }
+    /// Feeds a typed character. Returns the action and its count once the
+    /// keys typed so far form a complete one.
+    pub fn handle_char(&mut self, character: char) -> Option<(Action, Option<usize>)> {
+        if let Some(key) = self.awaiting_register.take() {
+            return match (key, character) {
+                ('@', '@') => Some(self.finish(Action::PlayMacro(None))),
+                ('@', register) if Macros::is_register(register) => {
+                    Some(self.finish(Action::PlayMacro(Some(register))))
+                }
+                ('q', register) if Macros::is_register(register) => {
+                    Some(self.finish(Action::RecordMacro(register)))
+                }
+                _ => {
+                    self.reset_pending();
+                    None
+                }
+            };
+        }
+        if self.awaiting_g {
+            self.awaiting_g = false;
+            return self.handle_g(character);
+        }
+
+        if let Some(digit) = character.to_digit(10)
+            && (digit != 0 || self.count.is_some())
+        {
+            let digit = usize::try_from(digit).unwrap_or_default();
+            self.count = Some(
+                self.count
+                    .unwrap_or_default()
+                    .saturating_mul(10)
+                    .saturating_add(digit),
+            );
+            return None;
+        }
+
+        if let Some(motion) = Self::motion_for(character) {
+            return Some(self.finish(Action::Move(motion)));
+        }
+
+        let operator = match character {
+            'd' => Some(Operator::Delete),
+            'c' => Some(Operator::Change),
+            'y' => Some(Operator::Yank),
+            // The second `q` of `gqq`.
+            'q' if matches!(self.operator, Some((Operator::Format, _))) => Some(Operator::Format),
+            _ => None,
+        };
+        if let Some(operator) = operator {
+            if self.mode.is_visual() {
+                return Some(self.finish(Action::OperateSelection(operator)));
+            }
+            if let Some((pending, _)) = self.operator {
+                return if pending == operator {
+                    Some(self.finish(Action::Move(Motion::CurrentLine)))
+                } else {
+                    self.reset_pending();
+                    None
+                };
+            }
+            self.operator = Some((operator, self.count.take()));
+            return None;
+        }
+        // Starts `gg`, which can follow an operator as well.
+        if character == 'g' {
+            self.awaiting_g = true;
+            return None;
+        }
+        if self.operator.is_some() {
+            // Only motions can follow an operator.
+            self.reset_pending();
+            return None;
+        }
+
+        let action = match (character, self.mode) {
+            ('q', _) if self.recording => Action::StopRecording,
+            ('q' | '@', _) => {
+                self.awaiting_register = Some(character);
+                return None;
+            }
+            ('x', Mode::Visual | Mode::VisualBlock) => Action::OperateSelection(Operator::Delete),
+            ('I', Mode::VisualBlock) => Action::Insert(InsertAt::BlockStart),
+            ('A', Mode::VisualBlock) => Action::Insert(InsertAt::BlockEnd),
+            ('x', _) => Action::Operate(Operator::Delete, Motion::Right),
+            ('D', _) => Action::Operate(Operator::Delete, Motion::LineEnd),
+            ('C', _) => Action::Operate(Operator::Change, Motion::LineEnd),
+            ('i', Mode::Normal) => Action::Insert(InsertAt::Caret),
+            ('a', Mode::Normal) => Action::Insert(InsertAt::AfterCaret),
+            ('I', Mode::Normal) => Action::Insert(InsertAt::LineStart),
+            ('A', Mode::Normal) => Action::Insert(InsertAt::LineEnd),
+            ('o', Mode::Normal) => Action::Insert(InsertAt::LineBelow),
+            ('O', Mode::Normal) => Action::Insert(InsertAt::LineAbove),
+            ('p', Mode::Normal) => Action::Put { before: false },
+            ('P', Mode::Normal) => Action::Put { before: true },
+            ('.', Mode::Normal) => Action::Repeat,
+            ('u', Mode::Normal) => Action::Command(Command::History(History::Undo)),
+            ('v', _) => Action::ToggleVisual,
+            ('J', _) => Action::Command(Command::Lines(Lines::Join)),
+            ('/', _) => Action::Command(Command::System(System::Search)),
+            (':', _) => Action::Command(Command::System(System::CommandLine)),
+            _ => {
+                self.reset_pending();
+                return None;
+            }
+        };
+        Some(self.finish(action))
+    }
+    /// Completes the action, folding in a pending operator. The counts typed
+    /// before the operator and before the motion multiply, as in `2d3w`.
+    fn finish(&mut self, action: Action) -> (Action, Option<usize>) {
+        let count = self.count.take();
+        let (action, count) = match (self.operator.take(), action) {
+            (Some((operator, operator_count)), Action::Move(motion)) => {
+                let count = match (operator_count, count) {
+                    (Some(first), Some(second)) => Some(first.saturating_mul(second)),
+                    (first, second) => first.or(second),
+                };
+                (Action::Operate(operator, motion), count)
+            }
+            (_, action) => (action, count),
+        };
+        self.awaiting_g = false;
+        (action, count)
+    }
+    const fn motion_for(character: char) -> Option<Motion> {
+        let motion = match character {
+            'h' => Motion::Left,
+            'l' | ' ' => Motion::Right,
+            'j' => Motion::Down,
+            'k' => Motion::Up,
+            'w' => Motion::WordStart,
+            'e' => Motion::WordEnd,
+            'b' => Motion::WordBackward,
+            '0' => Motion::LineStart,
+            '$' => Motion::LineEnd,
+            'G' => Motion::LastLine,
+            '%' => Motion::MatchingBracket,
+            _ => return None,
+        };
+        Some(motion)
+    }
+    // region: dot repeat
+    /// Remembers an action that was just carried out, if it changed the text.
+    /// Actions that enter insert mode are completed by `finish_insert`.
+    pub fn record(&mut self, action: Action, count: Option<usize>) {
+        if !action.is_change() {
+            return;
+        }
+        let change = Change {
+            action,
+            count,
+            inserted: Vec::new(),
+        };
+        if self.mode == Mode::Insert {
+            self.pending_change = Some(change);
+        } else {
+            self.last_change = Some(change);
+        }
+    }
+    /// Records an edit typed in insert mode.
+    pub fn record_edit(&mut self, edit: Edit) {
+        if let Some(change) = &mut self.pending_change {
+            change.inserted.push(edit);
+        }
+    }
+    /// Moving the caret in insert mode starts over what gets repeated.
+    pub fn restart_insert(&mut self) {
+        if let Some(change) = &mut self.pending_change {
+            change.inserted.clear();
+        }
+    }
+    /// Leaves insert mode. Returns the edits to type again for a count, as in
+    /// `3ix<Esc>`, which inserts `xxx`.
+    pub fn finish_insert(&mut self) -> Vec<Edit> {
+        self.set_mode(Mode::Normal);
+        let Some(change) = self.pending_change.take() else {
+            return Vec::new();
+        };
+        let repeat = match change.action {
+            Action::Insert(_) => change.count.unwrap_or(1).saturating_sub(1),
+            _ => 0,
+        };
+        let edits = change
+            .inserted
+            .iter()
+            .copied()
+            .cycle()
+            .take(change.inserted.len().saturating_mul(repeat))
+            .collect();
+        self.last_change = Some(change);
+        edits
+    }
+    // end region
+}
@@ -0,0 +1,21 @@
+#![warn(
+    clippy::all,
+    clippy::pedantic,
+    clippy::print_stdout,
+    clippy::arithmetic_side_effects,
+    clippy::as_conversions,
+    clippy::integer_division
+)]
+// The library only exists so integration tests can reach the editor; these
+// lints are aimed at published APIs.
+#![allow(
+    clippy::missing_errors_doc,
+    clippy::must_use_candidate,
+    clippy::return_self_not_must_use
+)]
+mod editor;
+
+pub use editor::{
+    terminal::{Backend, HeadlessBackend},
+    Arguments, ColorDepth, Config, Editor, Position, Size, Style,
+};
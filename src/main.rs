@@ -6,22 +6,25 @@
     clippy::as_conversions,
     clippy::integer_division
 )]
-mod editor;
-
-use simplelog::*;
+use simplelog::{CombinedLogger, Config, LevelFilter, WriteLogger};
 use std::fs::File;
-use editor::Editor;
+use hecto::Editor;
 
 fn main() {
-
-    CombinedLogger::init(
-        vec![
-            WriteLogger::new(LevelFilter::Debug, Config::default(), File::create("debug.log").unwrap())
-        ]
-    ).unwrap();
+    let config_dir = hecto::Config::dir();
+    // An invalid config is reported by the editor itself once it is running.
+    let config = hecto::Config::load(config_dir.as_deref());
+    let log_file = config.as_ref().ok().and_then(|config| config.log_file.as_ref());
+    if let Some(log_file) = log_file.and_then(|path| File::create(path).ok()) {
+        CombinedLogger::init(
+            vec![
+                WriteLogger::new(LevelFilter::Debug, Config::default(), log_file)
+            ]
+        ).unwrap();
+    }
 
     log::debug!("this is a test entry");
-    Editor::new().unwrap().run();
+    Editor::new(config, config_dir).unwrap().run();
 }
 
 
@@ -0,0 +1,1053 @@
+use crossterm::event::{
+    Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use hecto::{Arguments, Config, Editor, HeadlessBackend, Position, Size};
+
+fn key(code: KeyCode) -> Event {
+    Event::Key(KeyEvent::new(code, KeyModifiers::NONE))
+}
+
+fn type_text(text: &str) -> Vec<Event> {
+    text.chars().map(|character| key(KeyCode::Char(character))).collect()
+}
+
+fn esc() -> Event {
+    key(KeyCode::Esc)
+}
+
+fn ctrl(character: char) -> Event {
+    Event::Key(KeyEvent::new(KeyCode::Char(character), KeyModifiers::CONTROL))
+}
+
+fn mouse(kind: MouseEventKind, col: u16, row: u16) -> Event {
+    Event::Mouse(MouseEvent {
+        kind,
+        column: col,
+        row,
+        modifiers: KeyModifiers::NONE,
+    })
+}
+
+fn click(col: u16, row: u16) -> Event {
+    mouse(MouseEventKind::Down(MouseButton::Left), col, row)
+}
+
+fn test_root() -> &'static std::path::Path {
+    static ROOT: std::sync::OnceLock<std::path::PathBuf> = std::sync::OnceLock::new();
+    ROOT.get_or_init(|| {
+        let root = std::env::temp_dir().join(format!("hecto-tests-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join(".editorconfig"), "root = true\n").unwrap();
+        root
+    })
+}
+
+/// An editor on `backend` whose config directory is empty, so the user's
+/// themes, macros and config don't get in.
+fn new_editor(backend: &HeadlessBackend, arguments: Arguments, config: Config) -> Editor {
+    Editor::with_backend(backend.clone(), arguments, config, Some(test_root().join("config"))).unwrap()
+}
+
+fn run_editor(backend: &HeadlessBackend) {
+    run_editor_with_config(backend, Config::default());
+}
+
+fn run_editor_with_config(backend: &HeadlessBackend, config: Config) {
+    let mut editor = new_editor(backend, Arguments::default(), config);
+    editor.run();
+}
+
+#[test]
+fn typed_text_is_shown_with_caret_after_it() {
+    let backend = HeadlessBackend::new(Size { width: 40, height: 6 });
+    backend.push_events(type_text("hello"));
+    backend.push_event(key(KeyCode::Enter));
+    backend.push_events(type_text("world"));
+
+    run_editor(&backend);
+
+    assert_eq!(backend.row_text(0), "hello");
+    assert_eq!(backend.row_text(1), "world");
+    assert_eq!(backend.row_text(2), "~");
+    assert_eq!(backend.caret_position(), Some(Position { col: 5, row: 1 }));
+    assert!(backend.row_text(4).starts_with("[No Name] - 2 lines (modified)"));
+}
+
+#[test]
+fn search_prompt_replaces_message_bar() {
+    let backend = HeadlessBackend::new(Size { width: 60, height: 6 });
+    backend.push_events(type_text("needle"));
+    backend.push_event(ctrl('f'));
+    backend.push_events(type_text("ee"));
+
+    run_editor(&backend);
+
+    assert_eq!(backend.row_text(5), "Search (Esc to cancel, Arrows to navigate): ee");
+    assert_ne!(
+        backend.style_at(Position { col: 1, row: 0 }),
+        backend.style_at(Position { col: 0, row: 0 })
+    );
+}
+
+#[test]
+fn key_sequences_from_config_are_bound() {
+    let config = Config::from_toml(
+        r#"
+        [keys.normal]
+        "ctrl-f" = "none"
+        "ctrl-k ctrl-f" = "search"
+        "#,
+    )
+    .unwrap();
+    let backend = HeadlessBackend::new(Size { width: 60, height: 6 });
+    backend.push_events([ctrl('f'), ctrl('k'), ctrl('f')]);
+    backend.push_events(type_text("x"));
+
+    run_editor_with_config(&backend, config);
+
+    assert_eq!(backend.row_text(0), "~");
+    assert_eq!(backend.row_text(5), "Search (Esc to cancel, Arrows to navigate): x");
+}
+
+fn vim_config() -> Config {
+    Config::from_toml("vim_mode = true").unwrap()
+}
+
+#[test]
+fn vim_operators_take_counts_and_repeat() {
+    let backend = HeadlessBackend::new(Size { width: 60, height: 6 });
+    backend.push_events(type_text("ione two three four five"));
+    backend.push_event(esc());
+    backend.push_events(type_text("0d2w."));
+
+    run_editor_with_config(&backend, vim_config());
+
+    assert_eq!(backend.row_text(0), "five");
+    assert!(backend.row_text(4).starts_with("[NORMAL] [No Name] - 1 lines"));
+}
+
+#[test]
+fn vim_operators_take_gg_as_a_motion() {
+    let backend = HeadlessBackend::new(Size { width: 60, height: 6 });
+    backend.push_events(type_text("ia"));
+    for line in ["b", "c"] {
+        backend.push_event(key(KeyCode::Enter));
+        backend.push_events(type_text(line));
+    }
+    backend.push_event(esc());
+    backend.push_events(type_text("yggGp"));
+
+    let mut editor = new_editor(&backend, Arguments::default(), vim_config());
+    editor.run();
+
+    let rows: Vec<String> = (0..6).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows[..3], ["a", "b", "c"]);
+    assert!(rows[4].starts_with("[NORMAL] [No Name] - 6 lines"));
+
+    backend.push_events(type_text("jdgg"));
+    editor.run();
+
+    let rows: Vec<String> = (0..2).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["c", "~"]);
+}
+
+#[test]
+fn vim_visual_yank_and_put() {
+    let backend = HeadlessBackend::new(Size { width: 60, height: 6 });
+    backend.push_events(type_text("ione two"));
+    backend.push_event(esc());
+    backend.push_events(type_text("0veyA "));
+    backend.push_event(esc());
+    backend.push_events(type_text("pv"));
+
+    run_editor_with_config(&backend, vim_config());
+
+    assert_eq!(backend.row_text(0), "one two one");
+    assert_eq!(backend.caret_position(), Some(Position { col: 10, row: 0 }));
+    assert_ne!(
+        backend.style_at(Position { col: 10, row: 0 }),
+        backend.style_at(Position { col: 9, row: 0 })
+    );
+    assert!(backend.row_text(4).starts_with("[VISUAL]"));
+}
+
+#[test]
+fn command_line_substitutes_over_a_range() {
+    let backend = HeadlessBackend::new(Size { width: 60, height: 6 });
+    for _ in 0..3 {
+        backend.push_events(type_text("a-a"));
+        backend.push_event(key(KeyCode::Enter));
+    }
+    backend.push_event(ctrl('e'));
+    backend.push_events(type_text("1,2s/a/(&)/g"));
+    backend.push_event(key(KeyCode::Enter));
+    backend.push_event(ctrl('e'));
+    backend.push_events(type_text("goto 1"));
+    backend.push_event(key(KeyCode::Enter));
+
+    run_editor(&backend);
+
+    assert_eq!(backend.row_text(0), "(a)-(a)");
+    assert_eq!(backend.row_text(1), "(a)-(a)");
+    assert_eq!(backend.row_text(2), "a-a");
+    assert_eq!(backend.row_text(5), "4 substitutions");
+    assert_eq!(backend.caret_position(), Some(Position { col: 0, row: 0 }));
+}
+
+#[test]
+fn command_line_reports_errors_in_message_bar() {
+    let backend = HeadlessBackend::new(Size { width: 60, height: 6 });
+    backend.push_events(type_text("text"));
+    backend.push_event(ctrl('e'));
+    backend.push_events(type_text("q"));
+    backend.push_event(key(KeyCode::Enter));
+
+    run_editor(&backend);
+
+    assert_eq!(
+        backend.row_text(5),
+        "ERR: No write since last change (add ! to override)"
+    );
+}
+
+#[test]
+fn command_palette_lists_matching_commands_with_keys() {
+    let backend = HeadlessBackend::new(Size { width: 60, height: 8 });
+    backend.push_event(ctrl('p'));
+    backend.push_events(type_text("cyth"));
+
+    run_editor(&backend);
+
+    assert_eq!(backend.row_text(5), " cycle theme                                         Ctrl-T");
+    assert_eq!(backend.row_text(7), "> cyth");
+}
+
+#[test]
+fn command_palette_runs_selected_command() {
+    let backend = HeadlessBackend::new(Size { width: 60, height: 8 });
+    backend.push_event(ctrl('p'));
+    backend.push_events(type_text("theme"));
+    backend.push_event(key(KeyCode::Enter));
+
+    run_editor(&backend);
+
+    assert_eq!(backend.row_text(7), "Theme: dark");
+    assert_eq!(backend.row_text(5), "~");
+}
+
+/// An empty directory for the test, under one for this test run whose root
+/// `.editorconfig` keeps those further up from applying.
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = test_root().join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A small project for the file finder and grep to search.
+fn project_dir(name: &str) -> std::path::PathBuf {
+    let dir = temp_dir(name);
+    std::fs::create_dir(dir.join("src")).unwrap();
+    std::fs::write(dir.join("README.md"), "# Hecto - Text Editor\n").unwrap();
+    std::fs::write(dir.join("Cargo.toml"), "[package]\nname = \"hecto\"\n").unwrap();
+    std::fs::write(dir.join("src/main.rs"), "fn main() {}\n").unwrap();
+    dir
+}
+
+fn new_editor_in(backend: &HeadlessBackend, dir: &std::path::Path) -> Editor {
+    let mut editor = new_editor(backend, Arguments::default(), Config::default());
+    editor.set_root_dir(dir.to_path_buf());
+    editor
+}
+
+#[test]
+fn file_finder_previews_selected_file() {
+    let dir = project_dir("finder-preview");
+    let backend = HeadlessBackend::new(Size { width: 60, height: 8 });
+    backend.push_event(ctrl('o'));
+    backend.push_events(type_text("readme"));
+
+    new_editor_in(&backend, &dir).run();
+
+    assert_eq!(backend.row_text(0), "# Hecto - Text Editor");
+    assert_eq!(backend.row_text(5), " README.md");
+    assert_eq!(backend.row_text(7), "Open: readme");
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn file_finder_opens_selected_file() {
+    let dir = project_dir("finder-open");
+    let backend = HeadlessBackend::new(Size { width: 60, height: 8 });
+    backend.push_event(ctrl('o'));
+    backend.push_events(type_text("readme"));
+    backend.push_event(key(KeyCode::Enter));
+
+    new_editor_in(&backend, &dir).run();
+
+    assert_eq!(backend.row_text(0), "# Hecto - Text Editor");
+    assert!(backend.row_text(6).starts_with("README.md - "));
+    assert!(backend.row_text(7).ends_with("README.md"));
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn file_finder_asks_before_discarding_changes() {
+    let dir = project_dir("finder-discard");
+    let backend = HeadlessBackend::new(Size { width: 60, height: 8 });
+    backend.push_events(type_text("x"));
+    backend.push_event(ctrl('o'));
+    backend.push_events(type_text("readme"));
+    backend.push_event(key(KeyCode::Enter));
+
+    new_editor_in(&backend, &dir).run();
+
+    assert_eq!(backend.row_text(7), "Discard unsaved changes? (y/n)");
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+fn file_arguments(file_name: &std::path::Path) -> Arguments {
+    Arguments {
+        file_name: Some(file_name.to_string_lossy().into_owned()),
+        ..Arguments::default()
+    }
+}
+
+fn run_editor_on(backend: &HeadlessBackend, file_name: &std::path::Path) {
+    let mut editor = new_editor(backend, file_arguments(file_name), Config::default());
+    editor.run();
+}
+
+#[test]
+fn directory_browser_lists_and_opens_entries() {
+    let dir = temp_dir("browse");
+    std::fs::create_dir(dir.join("sub")).unwrap();
+    std::fs::write(dir.join("b.txt"), "bee").unwrap();
+    std::fs::write(dir.join("A.txt"), "ay").unwrap();
+    let backend = HeadlessBackend::new(Size { width: 60, height: 8 });
+    backend.push_events(type_text("jjj"));
+    backend.push_event(key(KeyCode::Enter));
+
+    run_editor_on(&backend, &dir);
+
+    assert_eq!(backend.row_text(0), "bee");
+    assert!(backend.row_text(6).starts_with("b.txt - 1 lines"));
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn directory_browser_creates_renames_and_deletes_files() {
+    let dir = temp_dir("manage");
+    let backend = HeadlessBackend::new(Size { width: 60, height: 8 });
+    backend.push_events(type_text("cnew"));
+    backend.push_event(key(KeyCode::Enter));
+    backend.push_events(type_text("r"));
+    backend.push_events(std::iter::repeat_n(key(KeyCode::Backspace), 3));
+    backend.push_events(type_text("old"));
+    backend.push_event(key(KeyCode::Enter));
+    backend.push_events(type_text("csub/"));
+    backend.push_event(key(KeyCode::Enter));
+    backend.push_events(type_text("dy"));
+
+    run_editor_on(&backend, &dir);
+
+    assert!(dir.join("old").is_file());
+    assert!(!dir.join("new").exists());
+    assert!(!dir.join("sub").exists());
+    assert_eq!(backend.row_text(0), "../");
+    assert!(backend.row_text(1).starts_with("old "));
+    assert_eq!(backend.row_text(7), "Deleted sub");
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn grep_lists_matches_and_jumps_to_them() {
+    let dir = project_dir("grep");
+    let backend = HeadlessBackend::new(Size { width: 60, height: 8 });
+    let mut editor = new_editor_in(&backend, &dir);
+    backend.push_event(ctrl('g'));
+    backend.push_events(type_text(r#"^name = "hecto""#));
+    backend.push_event(key(KeyCode::Enter));
+    editor.run();
+    // The search runs on its own thread; every event gives the editor a
+    // chance to collect what was found.
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    while editor.is_searching() {
+        assert!(std::time::Instant::now() < deadline, "grep did not finish within 10 seconds");
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        backend.push_event(Event::Resize(60, 8));
+        editor.run();
+    }
+
+    assert_eq!(backend.row_text(0), r#"Cargo.toml:2: name = "hecto""#);
+    assert_eq!(backend.row_text(1), "");
+    assert_ne!(
+        backend.style_at(Position { col: 14, row: 0 }),
+        backend.style_at(Position { col: 13, row: 0 })
+    );
+
+    backend.push_event(key(KeyCode::Enter));
+    editor.run();
+
+    assert!(backend.row_text(6).starts_with("Cargo.toml - "));
+    assert_eq!(backend.caret_position(), Some(Position { col: 0, row: 1 }));
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn clicks_place_the_caret_and_select_words() {
+    let backend = HeadlessBackend::new(Size { width: 40, height: 6 });
+    backend.push_events(type_text("hello wide world"));
+    backend.push_event(key(KeyCode::Enter));
+    backend.push_events(type_text("\tx"));
+    backend.push_event(click(4, 1));
+    backend.push_events(type_text("y"));
+    backend.push_events([click(7, 0), click(7, 0)]);
+    backend.push_events(type_text("big"));
+
+    run_editor(&backend);
+
+    assert_eq!(backend.row_text(0), "hello big world");
+    assert_eq!(backend.row_text(1), "    yx");
+    assert_eq!(backend.caret_position(), Some(Position { col: 9, row: 0 }));
+}
+
+#[test]
+fn vim_drag_selects_and_wheel_scrolls() {
+    let backend = HeadlessBackend::new(Size { width: 60, height: 6 });
+    backend.push_events(type_text("ione two three"));
+    backend.push_event(esc());
+    backend.push_events([
+        click(4, 0),
+        mouse(MouseEventKind::Drag(MouseButton::Left), 6, 0),
+        mouse(MouseEventKind::Up(MouseButton::Left), 6, 0),
+    ]);
+    backend.push_events(type_text("d"));
+    for _ in 0..5 {
+        backend.push_events(type_text("o"));
+        backend.push_event(esc());
+    }
+    backend.push_event(mouse(MouseEventKind::ScrollUp, 0, 0));
+
+    run_editor_with_config(&backend, vim_config());
+
+    assert_eq!(backend.row_text(0), "one  three");
+    assert!(backend.row_text(4).starts_with("[NORMAL] [No Name] - 6 lines"));
+    // The caret stays on the last line, which is now out of view.
+    assert_eq!(backend.caret_position(), None);
+}
+
+fn ctrl_alt(code: KeyCode) -> Event {
+    Event::Key(KeyEvent::new(code, KeyModifiers::CONTROL | KeyModifiers::ALT))
+}
+
+#[test]
+fn carets_below_edit_every_line() {
+    let backend = HeadlessBackend::new(Size { width: 40, height: 8 });
+    for line in ["ab", "cd"] {
+        backend.push_events(type_text(line));
+        backend.push_event(key(KeyCode::Enter));
+    }
+    backend.push_events(type_text("ef"));
+    backend.push_events([key(KeyCode::Up), key(KeyCode::Up)]);
+    backend.push_events([ctrl_alt(KeyCode::Down), ctrl_alt(KeyCode::Down)]);
+    backend.push_events(type_text("X"));
+    backend.push_event(key(KeyCode::Enter));
+    backend.push_events(type_text("y"));
+
+    run_editor(&backend);
+
+    let rows: Vec<String> = (0..6).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["abX", "y", "cdX", "y", "efX", "y"]);
+    assert_eq!(backend.caret_position(), Some(Position { col: 1, row: 5 }));
+}
+
+#[test]
+fn undo_takes_back_an_edit_at_every_caret_at_once() {
+    let backend = HeadlessBackend::new(Size { width: 40, height: 8 });
+    for line in ["ab", "cd"] {
+        backend.push_events(type_text(line));
+        backend.push_event(key(KeyCode::Enter));
+    }
+    backend.push_events(type_text("ef"));
+    backend.push_events([key(KeyCode::Up), key(KeyCode::Up)]);
+    backend.push_events([ctrl_alt(KeyCode::Down), ctrl_alt(KeyCode::Down)]);
+    backend.push_events(type_text("XY"));
+    backend.push_event(ctrl('z'));
+
+    let mut editor = new_editor(&backend, Arguments::default(), Config::default());
+    editor.run();
+
+    let rows: Vec<String> = (0..3).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["ab", "cd", "ef"]);
+    assert_eq!(backend.caret_position(), Some(Position { col: 2, row: 2 }));
+
+    backend.push_event(ctrl('y'));
+    editor.run();
+
+    let rows: Vec<String> = (0..3).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["abXY", "cdXY", "efXY"]);
+
+    backend.push_events([ctrl('z'), ctrl('z')]);
+    editor.run();
+
+    let rows: Vec<String> = (0..3).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["ab", "cd", ""]);
+}
+
+#[test]
+fn carets_at_matches_rename_a_word() {
+    let backend = HeadlessBackend::new(Size { width: 40, height: 6 });
+    backend.push_events(type_text("let foo = foo + food;"));
+    backend.push_event(key(KeyCode::Home));
+    backend.push_events((0..4).map(|_| key(KeyCode::Right)));
+    backend.push_event(ctrl('d'));
+    backend.push_events((0..3).map(|_| key(KeyCode::Backspace)));
+    backend.push_events(type_text("bar"));
+    backend.push_event(esc());
+    backend.push_events(type_text("!"));
+
+    run_editor(&backend);
+
+    assert_eq!(backend.row_text(0), "let bar = bar! + food;");
+
+    let backend = HeadlessBackend::new(Size { width: 40, height: 6 });
+    backend.push_events(type_text("a.a.a"));
+    backend.push_event(key(KeyCode::Home));
+    backend.push_event(ctrl_alt(KeyCode::Char('d')));
+    backend.push_events(type_text("b"));
+
+    run_editor(&backend);
+
+    assert_eq!(backend.row_text(0), "ab.ab.ab");
+}
+
+#[test]
+fn block_selection_copies_cuts_and_pastes_rectangles() {
+    let backend = HeadlessBackend::new(Size { width: 40, height: 8 });
+    let alt = |character| Event::Key(KeyEvent::new(KeyCode::Char(character), KeyModifiers::ALT));
+    backend.push_events(type_text("abcd"));
+    for line in ["efgh", "ijkl"] {
+        backend.push_event(key(KeyCode::Enter));
+        backend.push_events(type_text(line));
+    }
+    backend.push_events([key(KeyCode::Up), key(KeyCode::Up), key(KeyCode::Home), key(KeyCode::Right)]);
+    backend.push_event(alt('b'));
+    backend.push_events([key(KeyCode::Down), key(KeyCode::Right)]);
+    backend.push_event(alt('x'));
+    backend.push_events([key(KeyCode::Down), key(KeyCode::Down), key(KeyCode::Home)]);
+    backend.push_event(alt('v'));
+
+    let mut editor = new_editor(&backend, Arguments::default(), Config::default());
+    editor.run();
+
+    let rows: Vec<String> = (0..4).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["ad", "eh", "bcijkl", "fg"]);
+    assert_eq!(backend.caret_position(), Some(Position { col: 0, row: 2 }));
+
+    backend.push_event(alt('b'));
+    backend.push_event(key(KeyCode::Right));
+    backend.push_event(alt('c'));
+    backend.push_event(key(KeyCode::End));
+    backend.push_event(alt('v'));
+    editor.run();
+
+    assert_eq!(backend.row_text(2), "bcijklbc");
+    assert_eq!(backend.row_text(3), "fg");
+}
+
+#[test]
+fn vim_block_selection_deletes_puts_and_inserts() {
+    let backend = HeadlessBackend::new(Size { width: 60, height: 8 });
+    backend.push_events(type_text("iabcd"));
+    backend.push_event(key(KeyCode::Enter));
+    backend.push_events(type_text("efgh"));
+    backend.push_event(key(KeyCode::Enter));
+    backend.push_events(type_text("ij"));
+    backend.push_event(esc());
+    backend.push_events(type_text("gg0l"));
+    backend.push_event(ctrl('v'));
+    backend.push_events(type_text("ljdjj0P"));
+
+    let mut editor = new_editor(&backend, Arguments::default(), vim_config());
+    editor.run();
+
+    let rows: Vec<String> = (0..4).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["ad", "eh", "bcij", "fg"]);
+
+    backend.push_events(type_text("gg0l"));
+    backend.push_event(ctrl('v'));
+    backend.push_events(type_text("jjIX"));
+    backend.push_event(esc());
+    backend.push_event(ctrl('v'));
+    backend.push_events(type_text("jjlllA|"));
+    backend.push_event(esc());
+
+    editor.run();
+
+    let rows: Vec<String> = (0..4).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["aXd  |", "eXh  |", "bXcij|", "fg"]);
+}
+
+#[test]
+fn alt_drag_selects_a_block_to_type_over() {
+    let backend = HeadlessBackend::new(Size { width: 40, height: 6 });
+    for line in ["one", "two"] {
+        backend.push_events(type_text(line));
+        backend.push_event(key(KeyCode::Enter));
+    }
+    backend.push_events(type_text("six"));
+    let alt_mouse = |kind, col, row| {
+        Event::Mouse(MouseEvent {
+            kind,
+            column: col,
+            row,
+            modifiers: KeyModifiers::ALT,
+        })
+    };
+    backend.push_event(alt_mouse(MouseEventKind::Down(MouseButton::Left), 1, 0));
+    backend.push_event(alt_mouse(MouseEventKind::Drag(MouseButton::Left), 1, 2));
+    backend.push_events(type_text("__"));
+
+    run_editor(&backend);
+
+    let rows: Vec<String> = (0..3).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["o__e", "t__o", "s__x"]);
+}
+
+#[test]
+fn macros_record_and_play_with_counts() {
+    let backend = HeadlessBackend::new(Size { width: 40, height: 8 });
+    for line in ["one", "two", "three"] {
+        backend.push_events(type_text(line));
+        backend.push_event(key(KeyCode::Enter));
+    }
+    backend.push_events(type_text("four"));
+    backend.push_events((0..3).map(|_| key(KeyCode::Up)));
+    backend.push_event(key(KeyCode::F(3)));
+    backend.push_events(type_text("a"));
+    backend.push_event(key(KeyCode::Enter));
+    backend.push_event(key(KeyCode::Home));
+    backend.push_events(type_text("- "));
+    backend.push_event(key(KeyCode::Down));
+    backend.push_event(key(KeyCode::F(3)));
+    backend.push_event(key(KeyCode::F(4)));
+    backend.push_events(type_text("2a"));
+    backend.push_event(key(KeyCode::Enter));
+
+    run_editor(&backend);
+
+    let rows: Vec<String> = (0..4).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["- one", "- two", "- three", "four"]);
+}
+
+#[test]
+fn vim_macros_repeat_and_apply_to_selected_lines() {
+    let backend = HeadlessBackend::new(Size { width: 40, height: 8 });
+    backend.push_events(type_text("ione"));
+    for line in ["two", "three", "four"] {
+        backend.push_event(key(KeyCode::Enter));
+        backend.push_events(type_text(line));
+    }
+    backend.push_event(esc());
+    backend.push_events(type_text("ggqaI* "));
+    backend.push_event(esc());
+    backend.push_events(type_text("jq2@a"));
+
+    let mut editor = new_editor(&backend, Arguments::default(), vim_config());
+    editor.run();
+
+    let rows: Vec<String> = (0..4).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["* one", "* two", "* three", "four"]);
+
+    backend.push_events(type_text("ggvjj@a"));
+
+    editor.run();
+
+    let rows: Vec<String> = (0..4).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["* * one", "* * two", "* * three", "four"]);
+}
+
+#[test]
+fn vim_macros_record_visual_block_selections() {
+    let backend = HeadlessBackend::new(Size { width: 40, height: 8 });
+    backend.push_events(type_text("iabc"));
+    for line in ["def", "ghi"] {
+        backend.push_event(key(KeyCode::Enter));
+        backend.push_events(type_text(line));
+    }
+    backend.push_event(esc());
+    backend.push_events(type_text("ggqa"));
+    backend.push_event(ctrl('v'));
+    backend.push_events(type_text("jxql@a"));
+
+    run_editor_with_config(&backend, vim_config());
+
+    let rows: Vec<String> = (0..3).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["b", "e", "ghi"]);
+}
+
+#[test]
+fn brackets_match_across_lines_outside_strings_and_comments() {
+    let dir = temp_dir("brackets");
+    let file_name = dir.join("lib.rs");
+    std::fs::write(&file_name, "fn f() {\n    let s = \"}\"; // )\n    /* { */ g('}');\n}\n").unwrap();
+    let backend = HeadlessBackend::new(Size { width: 40, height: 8 });
+    backend.push_event(key(KeyCode::End));
+    let alt_m = || Event::Key(KeyEvent::new(KeyCode::Char('m'), KeyModifiers::ALT));
+    backend.push_events([alt_m(), alt_m()]);
+
+    run_editor_on(&backend, &file_name);
+
+    assert_eq!(backend.caret_position(), Some(Position { col: 0, row: 3 }));
+    let bracket_style = backend.style_at(Position { col: 7, row: 0 });
+    assert_ne!(bracket_style, backend.style_at(Position { col: 6, row: 0 }));
+    assert_eq!(bracket_style, backend.style_at(Position { col: 0, row: 3 }));
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn vim_percent_jumps_and_operates_to_the_matching_bracket() {
+    let backend = HeadlessBackend::new(Size { width: 40, height: 6 });
+    backend.push_events(type_text("ia(b[c]d)e"));
+    backend.push_event(esc());
+    backend.push_events(type_text("0l%x"));
+
+    run_editor_with_config(&backend, vim_config());
+
+    assert_eq!(backend.row_text(0), "a(b[c]de");
+    let backend = HeadlessBackend::new(Size { width: 40, height: 6 });
+    backend.push_events(type_text("ia(b[c]d)e"));
+    backend.push_event(esc());
+    backend.push_events(type_text("$h%d%"));
+
+    run_editor_with_config(&backend, vim_config());
+
+    assert_eq!(backend.row_text(0), "ae");
+}
+
+#[test]
+fn auto_pairs_close_type_over_and_wrap() {
+    let config = || Config::from_toml("auto_pairs = true").unwrap();
+    let backend = HeadlessBackend::new(Size { width: 40, height: 6 });
+    backend.push_events(type_text("f(x) {"));
+    backend.push_event(key(KeyCode::Backspace));
+    backend.push_events(type_text("don't \"a"));
+
+    run_editor_with_config(&backend, config());
+
+    assert_eq!(backend.row_text(0), "f(x) don't \"a\"");
+    assert_eq!(backend.caret_position(), Some(Position { col: 13, row: 0 }));
+
+    let backend = HeadlessBackend::new(Size { width: 40, height: 6 });
+    backend.push_events(type_text("a word"));
+    backend.push_events([click(3, 0), click(3, 0)]);
+    backend.push_events(type_text("(["));
+
+    run_editor_with_config(&backend, config());
+
+    assert_eq!(backend.row_text(0), "a ([word])");
+}
+
+#[test]
+fn toggle_comment_lines_up_markers_and_uncomments() {
+    let dir = temp_dir("comment");
+    let file_name = dir.join("main.py");
+    std::fs::write(&file_name, "def f():\n    x = 1\n\n    if x:\n        y = 2\n").unwrap();
+    let backend = HeadlessBackend::new(Size { width: 40, height: 8 });
+    let mut editor = new_editor(&backend, file_arguments(&file_name), Config::default());
+    backend.push_event(click(4, 1));
+    backend.push_event(mouse(MouseEventKind::Drag(MouseButton::Left), 4, 4));
+    backend.push_event(ctrl('/'));
+
+    editor.run();
+
+    let rows: Vec<String> = (0..5).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["def f():", "    # x = 1", "", "    # if x:", "    #     y = 2"]);
+
+    // Terminals report Ctrl-/ as Ctrl-7.
+    backend.push_event(ctrl('7'));
+
+    editor.run();
+
+    let rows: Vec<String> = (0..5).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["def f():", "    x = 1", "", "    if x:", "        y = 2"]);
+
+    // Without line comments, the lines go into one block comment.
+    let file_name = dir.join("style.css");
+    std::fs::write(&file_name, "a {\n  color: red;\n  margin: 0;\n}\n").unwrap();
+    let backend = HeadlessBackend::new(Size { width: 40, height: 8 });
+    let mut editor = new_editor(&backend, file_arguments(&file_name), Config::default());
+    backend.push_event(click(2, 1));
+    backend.push_event(mouse(MouseEventKind::Drag(MouseButton::Left), 4, 2));
+    backend.push_event(ctrl('/'));
+
+    editor.run();
+
+    let rows: Vec<String> = (0..4).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["a {", "  /* color: red;", "  margin: 0; */", "}"]);
+    assert_eq!(backend.caret_position(), Some(Position { col: 4, row: 2 }));
+
+    backend.push_event(ctrl('/'));
+
+    editor.run();
+
+    let rows: Vec<String> = (0..4).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["a {", "  color: red;", "  margin: 0;", "}"]);
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn line_operations_move_duplicate_delete_sort_and_join() {
+    let backend = HeadlessBackend::new(Size { width: 40, height: 8 });
+    let alt = |code| Event::Key(KeyEvent::new(code, KeyModifiers::ALT));
+    backend.push_events(type_text("b 10"));
+    for line in ["a 2", "c 1", "a 2"] {
+        backend.push_event(key(KeyCode::Enter));
+        backend.push_events(type_text(line));
+    }
+    backend.push_events((0..3).map(|_| key(KeyCode::Up)));
+    backend.push_event(alt(KeyCode::Down));
+    backend.push_event(Event::Key(KeyEvent::new(KeyCode::Down, KeyModifiers::ALT | KeyModifiers::SHIFT)));
+    backend.push_event(ctrl('k'));
+    backend.push_event(alt(KeyCode::Up));
+
+    let mut editor = new_editor(&backend, Arguments::default(), Config::default());
+    editor.run();
+
+    let rows: Vec<String> = (0..4).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["a 2", "c 1", "b 10", "a 2"]);
+    assert_eq!(backend.caret_position().map(|position| position.row), Some(1));
+
+    backend.push_event(ctrl('e'));
+    backend.push_events(type_text("sort nu"));
+    backend.push_event(key(KeyCode::Enter));
+    backend.push_event(key(KeyCode::Up));
+    backend.push_event(alt(KeyCode::Char('j')));
+
+    editor.run();
+
+    let rows: Vec<String> = (0..3).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["c 1 a 2", "b 10", "~"]);
+    assert_eq!(backend.caret_position(), Some(Position { col: 3, row: 0 }));
+
+    // Without a selection there is nothing for `sort_lines` to sort.
+    backend.push_event(ctrl('p'));
+    backend.push_events(type_text("sort lines"));
+    backend.push_event(key(KeyCode::Enter));
+
+    editor.run();
+
+    assert_eq!(backend.row_text(0), "c 1 a 2");
+    assert_eq!(backend.row_text(7), "ERR: Nothing selected");
+
+    // The palette sorts the selection by the other keys as well.
+    let backend = HeadlessBackend::new(Size { width: 40, height: 8 });
+    backend.push_events(type_text("10"));
+    for line in ["9", "9"] {
+        backend.push_event(key(KeyCode::Enter));
+        backend.push_events(type_text(line));
+    }
+    backend.push_event(click(0, 0));
+    backend.push_event(mouse(MouseEventKind::Drag(MouseButton::Left), 0, 2));
+    backend.push_event(ctrl('p'));
+    backend.push_events(type_text("sort lines numeric"));
+    backend.push_event(key(KeyCode::Enter));
+
+    run_editor(&backend);
+
+    let rows: Vec<String> = (0..3).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["9", "9", "10"]);
+
+    // Moving lines leaves a single caret, rather than one on the wrong line.
+    let backend = HeadlessBackend::new(Size { width: 40, height: 6 });
+    backend.push_events(type_text("a"));
+    for line in ["b", "c"] {
+        backend.push_event(key(KeyCode::Enter));
+        backend.push_events(type_text(line));
+    }
+    let add_caret_above = Event::Key(KeyEvent::new(KeyCode::Up, KeyModifiers::CONTROL | KeyModifiers::ALT));
+    backend.push_events([add_caret_above.clone(), add_caret_above]);
+    backend.push_event(alt(KeyCode::Down));
+    backend.push_events(type_text("x"));
+
+    run_editor(&backend);
+
+    let rows: Vec<String> = (0..3).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["b", "ax", "c"]);
+
+    let backend = HeadlessBackend::new(Size { width: 40, height: 6 });
+    backend.push_events(type_text("ifoo  "));
+    backend.push_event(key(KeyCode::Enter));
+    backend.push_events(type_text("   bar"));
+    backend.push_event(esc());
+    backend.push_events(type_text("ggJ"));
+
+    run_editor_with_config(&backend, vim_config());
+
+    assert_eq!(backend.row_text(0), "foo bar");
+}
+
+
+#[test]
+fn line_operations_are_undone_as_one_step() {
+    let backend = HeadlessBackend::new(Size { width: 40, height: 8 });
+    let alt = |code| Event::Key(KeyEvent::new(code, KeyModifiers::ALT));
+    backend.push_events(type_text("10"));
+    for line in ["9", "9"] {
+        backend.push_event(key(KeyCode::Enter));
+        backend.push_events(type_text(line));
+    }
+    backend.push_event(click(0, 0));
+    backend.push_event(mouse(MouseEventKind::Drag(MouseButton::Left), 0, 2));
+    backend.push_event(ctrl('p'));
+    backend.push_events(type_text("sort lines numeric"));
+    backend.push_event(key(KeyCode::Enter));
+
+    let mut editor = new_editor(&backend, Arguments::default(), Config::default());
+    editor.run();
+
+    let rows: Vec<String> = (0..3).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["9", "9", "10"]);
+
+    backend.push_event(ctrl('z'));
+    editor.run();
+
+    let rows: Vec<String> = (0..3).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["10", "9", "9"]);
+
+    backend.push_event(click(0, 0));
+    backend.push_event(alt(KeyCode::Char('j')));
+    backend.push_event(alt(KeyCode::Char('j')));
+    backend.push_event(ctrl('z'));
+    editor.run();
+
+    let rows: Vec<String> = (0..3).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["10 9", "9", "~"]);
+
+    let backend = HeadlessBackend::new(Size { width: 40, height: 8 });
+    backend.push_events(type_text("ione"));
+    backend.push_event(key(KeyCode::Enter));
+    backend.push_events(type_text("two"));
+    backend.push_event(esc());
+    backend.push_events(type_text("gg2ddu"));
+
+    run_editor_with_config(&backend, vim_config());
+
+    let rows: Vec<String> = (0..2).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["one", "two"]);
+}
+#[test]
+fn reflow_keeps_prefixes_and_auto_wrap_breaks_lines() {
+    let backend = HeadlessBackend::new(Size { width: 40, height: 6 });
+    backend.push_events(type_text("# one two three"));
+    backend.push_event(key(KeyCode::Enter));
+    backend.push_events(type_text("# four five six"));
+    backend.push_event(Event::Key(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::ALT)));
+
+    run_editor_with_config(&backend, Config::from_toml("text_width = 12").unwrap());
+
+    let rows: Vec<String> = (0..4).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["# one two", "# three four", "# five six", "~"]);
+
+    let backend = HeadlessBackend::new(Size { width: 40, height: 6 });
+    backend.push_events(type_text("  hello world again"));
+
+    run_editor_with_config(&backend, Config::from_toml("text_width = 10\nauto_wrap = true").unwrap());
+
+    let rows: Vec<String> = (0..3).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["  hello", "  world", "  again"]);
+    assert_eq!(backend.caret_position(), Some(Position { col: 7, row: 2 }));
+
+    let backend = HeadlessBackend::new(Size { width: 40, height: 6 });
+    backend.push_events(type_text("ione two three"));
+    backend.push_event(esc());
+    backend.push_events(type_text("gqq"));
+
+    run_editor_with_config(&backend, Config::from_toml("vim_mode = true\ntext_width = 5").unwrap());
+
+    let rows: Vec<String> = (0..3).map(|row| backend.row_text(row)).collect();
+    assert_eq!(rows, ["one", "two", "three"]);
+}
+
+#[test]
+fn saving_trims_trailing_whitespace_and_blank_lines() {
+    let dir = temp_dir("save-hooks");
+    let file_name = dir.join("main.rs");
+    std::fs::write(&file_name, "fn f() {  \n}\t\n\n  \n").unwrap();
+    let backend = HeadlessBackend::new(Size { width: 40, height: 8 });
+
+    let config = Config::from_toml("trim_trailing_whitespace = true\ntrim_trailing_blank_lines = true").unwrap();
+    let mut editor = new_editor(&backend, file_arguments(&file_name), config);
+    editor.run();
+
+    assert_ne!(
+        backend.style_at(Position { col: 8, row: 0 }),
+        backend.style_at(Position { col: 7, row: 0 })
+    );
+
+    backend.push_event(ctrl('s'));
+    editor.run();
+
+    assert_eq!(std::fs::read_to_string(&file_name).unwrap(), "fn f() {\n}\n");
+    assert_eq!(backend.style_at(Position { col: 8, row: 0 }), backend.style_at(Position { col: 7, row: 0 }));
+
+    let file_name = dir.join("notes.md");
+    std::fs::write(&file_name, "hard  \nbreak").unwrap();
+    let backend = HeadlessBackend::new(Size { width: 40, height: 8 });
+    backend.push_event(ctrl('s'));
+
+    let config = Config::from_toml("insert_final_newline = false").unwrap();
+    new_editor(&backend, file_arguments(&file_name), config).run();
+
+    assert_eq!(std::fs::read_to_string(&file_name).unwrap(), "hard  \nbreak");
+}
+
+#[test]
+fn editorconfig_sections_apply_to_matching_files() {
+    let dir = temp_dir("editorconfig");
+    std::fs::write(
+        dir.join(".editorconfig"),
+        "root = true\n\n[*]\nend_of_line = crlf\n\n[*.{rs,py}]\nindent_style = space\nindent_size = 2\n",
+    )
+    .unwrap();
+    std::fs::create_dir(dir.join("sub")).unwrap();
+    std::fs::write(dir.join("sub/.editorconfig"), "[lib.rs]\nindent_style = tab\ncharset = utf-8-bom\n").unwrap();
+    let main = dir.join("main.rs");
+    let lib = dir.join("sub/lib.rs");
+    std::fs::write(&main, "fn f() {}\n").unwrap();
+    std::fs::write(&lib, "fn g() {}\n").unwrap();
+
+    for file_name in [&main, &lib] {
+        let backend = HeadlessBackend::new(Size { width: 40, height: 6 });
+        backend.push_event(key(KeyCode::Tab));
+        backend.push_event(ctrl('s'));
+        run_editor_on(&backend, file_name);
+    }
+
+    assert_eq!(std::fs::read_to_string(&main).unwrap(), "  fn f() {}\r\n");
+    assert_eq!(std::fs::read_to_string(&lib).unwrap(), "\u{feff}\tfn g() {}\r\n");
+}
+
+#[test]
+fn saving_keeps_the_charset_a_file_was_read_in() {
+    let dir = temp_dir("charsets");
+    let utf16le: Vec<u8> = [0xFF, 0xFE]
+        .into_iter()
+        .chain("h\u{e9}llo \u{1f600}\n".encode_utf16().flat_map(u16::to_le_bytes))
+        .collect();
+    let utf8_bom = b"\xEF\xBB\xBFh\xC3\xA9llo\n".to_vec();
+    let latin1 = b"h\xE9llo\n".to_vec();
+    for (name, bytes) in [("utf16le.txt", utf16le), ("bom.txt", utf8_bom), ("latin1.txt", latin1)] {
+        let file_name = dir.join(name);
+        std::fs::write(&file_name, &bytes).unwrap();
+        let backend = HeadlessBackend::new(Size { width: 40, height: 6 });
+        backend.push_event(ctrl('s'));
+
+        run_editor_on(&backend, &file_name);
+
+        assert!(backend.row_text(0).starts_with("h\u{e9}llo"), "{name}");
+        assert_eq!(std::fs::read(&file_name).unwrap(), bytes, "{name}");
+    }
+}